@@ -0,0 +1,535 @@
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator, Space};
+use anchor_spl::token::TokenAccount;
+use liquidation_engine::{
+    InsuranceFund, LegacyPosition, LiquidatorStats, Market, MarketInsuranceFund, Position,
+    PriceFeed,
+};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::errors::LiquidationError;
+use crate::models::market_index_for_symbol;
+
+// Offset of `Position.closed` in the full account data, for memcmp filters.
+pub const POSITION_CLOSED_OFFSET: usize = 8 + std::mem::offset_of!(Position, closed);
+
+// Matches `declare_id!` in programs/liquidation_engine.
+pub const PROGRAM_ID: &str = "EHGrMFLNaYrKDt6cp5b3iABFwUsS5mCNa1EfwNARWm5n";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionAccount {
+    pub owner: Pubkey,
+    pub market_index: u16,
+    pub size: u64,
+    pub entry_price: u64,
+    pub collateral: u64,
+    pub leverage: u16,
+    pub is_long: bool,
+    pub closed: bool,
+    pub version: u8,
+    // Unix time `flag_at_risk` was called; 0 if not flagged. Carved from the
+    // v1 layout's reserve, so always 0 for legacy (v0) positions.
+    pub at_risk_since: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsuranceFundAccount {
+    pub authority: Pubkey,
+    pub balance: u64,
+    pub total_contributions: u64,
+    pub total_bad_debt_covered: u64,
+    pub utilization_ratio: u64,
+    pub withdrawal_timelock_secs: i64,
+    pub withdrawal_epoch_secs: i64,
+    pub max_withdrawal_bps_per_epoch: u64,
+    pub min_reserve: u64,
+    pub epoch_start: i64,
+    pub epoch_start_balance: u64,
+    pub withdrawn_this_epoch: u64,
+    pub pending_withdrawal_amount: u64,
+    pub pending_withdrawal_requested_at: i64,
+    pub version: u8,
+    pub pending_withdrawal_recipient: Pubkey, // token account the pending request pays
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceFeedAccount {
+    pub price: u64,
+    pub last_updated: i64,
+    pub version: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketAccount {
+    pub market_index: u16,
+    pub symbol: String,
+    pub insurance_fund: Pubkey,
+    pub price_decimals: u8,
+    pub base_decimals: u8,
+    pub min_position_notional: u64,
+    // How long `flag_at_risk` must have been set before a non-critical
+    // position can be liquidated; 0 = no grace period.
+    pub at_risk_grace_secs: i64,
+    // Bound by `initialize_market`; instructions reject any other feed.
+    pub price_feed: Pubkey,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketInsuranceFundAccount {
+    pub insurance_fund: Pubkey, // global fund the liquidation instructions must be passed
+    pub market_index: u16,
+    pub balance: u64,
+    pub total_contributions: u64,
+    pub total_bad_debt_covered: u64,
+    pub utilization_ratio: u64,
+    pub global_backstop_cap: u64,
+    pub global_backstop_drawn: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidatorStatsAccount {
+    pub liquidator: Pubkey,
+    pub liquidation_count: u64,
+    pub total_reward: u64,
+    pub last_liquidation_slot: u64,
+}
+
+pub fn decode_position(data: &[u8]) -> Result<PositionAccount, LiquidationError> {
+    if data.len() < 8 || data[..8] != Position::DISCRIMINATOR {
+        return Err(LiquidationError::Decode("not a Position account".to_string()));
+    }
+    let body = &data[8..];
+
+    // Told apart by the version byte: a legacy body is 64 + symbol length bytes,
+    // which can coincide with the zero-copy size. Read unaligned, as RPC buffers
+    // make no alignment promise.
+    if body.len() == Position::LEN && body[Position::VERSION_OFFSET] == Position::VERSION {
+        let position: Position = bytemuck::pod_read_unaligned(body);
+        return Ok(PositionAccount {
+            owner: position.owner,
+            market_index: position.market_index,
+            size: position.size,
+            entry_price: position.entry_price,
+            collateral: position.collateral,
+            leverage: position.leverage,
+            is_long: position.is_long(),
+            closed: position.is_closed(),
+            version: position.version,
+            at_risk_since: position.at_risk_since,
+        });
+    }
+
+    // Version 0: borsh with a heap symbol.
+    let legacy = LegacyPosition::deserialize(&mut &body[..])
+        .map_err(|e| LiquidationError::Decode(format!("legacy Position: {e}")))?;
+    let market_index = market_index_for_symbol(&legacy.symbol)
+        .ok_or_else(|| LiquidationError::Decode(format!("unknown market {}", legacy.symbol)))?;
+
+    Ok(PositionAccount {
+        owner: legacy.owner,
+        market_index,
+        size: legacy.size,
+        entry_price: legacy.entry_price,
+        collateral: legacy.collateral,
+        leverage: legacy.leverage,
+        is_long: legacy.is_long,
+        closed: legacy.closed,
+        version: 0,
+        at_risk_since: 0,
+    })
+}
+
+pub fn decode_insurance_fund(data: &[u8]) -> Result<InsuranceFundAccount, LiquidationError> {
+    // Version 0 predates the withdrawal safeguards; they read as zero until migrated.
+    let fund: InsuranceFund =
+        deserialize(data, "InsuranceFund", Some(InsuranceFund::V0_LEN))?;
+
+    Ok(InsuranceFundAccount {
+        authority: fund.authority,
+        balance: fund.balance,
+        total_contributions: fund.total_contributions,
+        total_bad_debt_covered: fund.total_bad_debt_covered,
+        utilization_ratio: fund.utilization_ratio,
+        withdrawal_timelock_secs: fund.withdrawal_timelock_secs,
+        withdrawal_epoch_secs: fund.withdrawal_epoch_secs,
+        max_withdrawal_bps_per_epoch: fund.max_withdrawal_bps_per_epoch,
+        min_reserve: fund.min_reserve,
+        epoch_start: fund.epoch_start,
+        epoch_start_balance: fund.epoch_start_balance,
+        withdrawn_this_epoch: fund.withdrawn_this_epoch,
+        pending_withdrawal_amount: fund.pending_withdrawal_amount,
+        pending_withdrawal_requested_at: fund.pending_withdrawal_requested_at,
+        version: fund.version,
+        pending_withdrawal_recipient: fund.pending_withdrawal_recipient,
+    })
+}
+
+pub fn decode_price_feed(data: &[u8]) -> Result<PriceFeedAccount, LiquidationError> {
+    let feed: PriceFeed = deserialize(data, "PriceFeed", Some(PriceFeed::V0_LEN))?;
+    Ok(PriceFeedAccount {
+        price: feed.price,
+        last_updated: feed.last_updated,
+        version: feed.version,
+    })
+}
+
+pub fn decode_liquidator_stats(data: &[u8]) -> Result<LiquidatorStatsAccount, LiquidationError> {
+    let stats: LiquidatorStats = deserialize(data, "LiquidatorStats", None)?;
+    Ok(LiquidatorStatsAccount {
+        liquidator: stats.liquidator,
+        liquidation_count: stats.liquidation_count,
+        total_reward: stats.total_reward,
+        last_liquidation_slot: stats.last_liquidation_slot,
+    })
+}
+
+pub fn decode_market(data: &[u8]) -> Result<MarketAccount, LiquidationError> {
+    let market: Market = deserialize(data, "Market", None)?;
+    let symbol = String::from_utf8_lossy(&market.symbol).trim_end_matches('\0').to_string();
+
+    Ok(MarketAccount {
+        market_index: market.market_index,
+        symbol,
+        insurance_fund: market.insurance_fund,
+        price_decimals: market.price_decimals,
+        base_decimals: market.base_decimals,
+        min_position_notional: market.min_position_notional,
+        at_risk_grace_secs: market.at_risk_grace_secs,
+        price_feed: market.price_feed,
+    })
+}
+
+pub fn decode_market_insurance_fund(
+    data: &[u8],
+) -> Result<MarketInsuranceFundAccount, LiquidationError> {
+    let fund: MarketInsuranceFund = deserialize(data, "MarketInsuranceFund", None)?;
+    Ok(MarketInsuranceFundAccount {
+        insurance_fund: fund.insurance_fund,
+        market_index: fund.market_index,
+        balance: fund.balance,
+        total_contributions: fund.total_contributions,
+        total_bad_debt_covered: fund.total_bad_debt_covered,
+        utilization_ratio: fund.utilization_ratio,
+        global_backstop_cap: fund.global_backstop_cap,
+        global_backstop_drawn: fund.global_backstop_drawn,
+    })
+}
+
+// An SPL token account, e.g. a vault read for its mint.
+pub fn decode_token_account(data: &[u8]) -> Result<TokenAccount, LiquidationError> {
+    TokenAccount::try_deserialize(&mut &data[..])
+        .map_err(|e| LiquidationError::Decode(format!("not a token account: {e}")))
+}
+
+// PDA: [MARKET_SEED, market_index.to_le_bytes()]
+pub fn market_address(program_id: &Pubkey, market_index: u16) -> Pubkey {
+    let seeds: &[&[u8]] = &[liquidation_engine::MARKET_SEED, &market_index.to_le_bytes()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// PDA: [MARKET_INSURANCE_SEED, market_index.to_le_bytes()]
+pub fn market_insurance_fund_address(program_id: &Pubkey, market_index: u16) -> Pubkey {
+    let seeds: &[&[u8]] =
+        &[liquidation_engine::MARKET_INSURANCE_SEED, &market_index.to_le_bytes()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// PDA: [COLLATERAL_VAULT_SEED, market_index.to_le_bytes()]
+pub fn collateral_vault_address(program_id: &Pubkey, market_index: u16) -> Pubkey {
+    let seeds: &[&[u8]] =
+        &[liquidation_engine::COLLATERAL_VAULT_SEED, &market_index.to_le_bytes()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// PDA: [MARKET_INSURANCE_VAULT_SEED, market_index.to_le_bytes()]
+pub fn market_insurance_vault_address(program_id: &Pubkey, market_index: u16) -> Pubkey {
+    let seeds: &[&[u8]] =
+        &[liquidation_engine::MARKET_INSURANCE_VAULT_SEED, &market_index.to_le_bytes()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// PDA: [INSURANCE_VAULT_SEED, insurance_fund]
+pub fn insurance_vault_address(program_id: &Pubkey, insurance_fund: &Pubkey) -> Pubkey {
+    let seeds: &[&[u8]] = &[liquidation_engine::INSURANCE_VAULT_SEED, insurance_fund.as_ref()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// PDA: [INSURANCE_VAULT_AUTHORITY_SEED, insurance_fund]
+pub fn insurance_vault_authority_address(program_id: &Pubkey, insurance_fund: &Pubkey) -> Pubkey {
+    let seeds: &[&[u8]] =
+        &[liquidation_engine::INSURANCE_VAULT_AUTHORITY_SEED, insurance_fund.as_ref()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// PDA: [LIQUIDATOR_STATS_SEED, liquidator]
+pub fn liquidator_stats_address(program_id: &Pubkey, liquidator: &Pubkey) -> Pubkey {
+    let seeds: &[&[u8]] = &[liquidation_engine::LIQUIDATOR_STATS_SEED, liquidator.as_ref()];
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+// `try_deserialize` checks the discriminator. A body of `legacy_len` bytes
+// predates the fields carved from the reserve and is zero-extended first, the
+// way `migrate_account` does, so those fields and `version` read as 0.
+fn deserialize<T: AccountDeserialize + Space>(
+    data: &[u8],
+    name: &str,
+    legacy_len: Option<usize>,
+) -> Result<T, LiquidationError> {
+    let body_len = data.len().saturating_sub(8);
+    let padded;
+    let data = if body_len == T::INIT_SPACE {
+        data
+    } else if legacy_len == Some(body_len) {
+        padded = [data, &vec![0u8; T::INIT_SPACE - body_len]].concat();
+        &padded[..]
+    } else {
+        return Err(LiquidationError::Decode(format!("unexpected {name} length {body_len}")));
+    };
+
+    T::try_deserialize(&mut &data[..])
+        .map_err(|e| LiquidationError::Decode(format!("not a {name} account: {e}")))
+}
+
+// Serializes an `#[account]` the way the program stores it.
+#[cfg(test)]
+pub(crate) fn account_data<T: anchor_lang::AccountSerialize>(account: &T) -> Vec<u8> {
+    let mut data = Vec::new();
+    account.try_serialize(&mut data).unwrap();
+    data
+}
+
+#[cfg(test)]
+pub(crate) fn market_insurance_fund_data(market_index: u16, insurance_fund: Pubkey) -> Vec<u8> {
+    account_data(&MarketInsuranceFund {
+        insurance_fund,
+        market_index,
+        balance: 0,
+        total_contributions: 0,
+        total_bad_debt_covered: 0,
+        utilization_ratio: 0,
+        global_backstop_cap: 0,
+        global_backstop_drawn: 0,
+        bump: 254,
+    })
+}
+
+// A current-layout Market account, as `initialize_market` leaves it.
+#[cfg(test)]
+pub(crate) fn market_data(market_index: u16, symbol: &str, price_feed: Pubkey) -> Vec<u8> {
+    let mut symbol_bytes = [0u8; liquidation_engine::MAX_SYMBOL_LEN];
+    symbol_bytes[..symbol.len()].copy_from_slice(symbol.as_bytes());
+    account_data(&Market {
+        market_index,
+        symbol: symbol_bytes,
+        insurance_fund: Pubkey::new_unique(),
+        bump: 255,
+        price_decimals: 6,
+        base_decimals: if symbol == "BTC-PERP" { 8 } else { 6 }, // BTC sizes in sats
+        min_position_notional: 10_000_000,
+        warning_buffer_bps: 0,
+        at_risk_grace_secs: 60,
+        max_open_interest: 0,
+        max_notional_per_position: 0,
+        open_interest_long: 0,
+        open_interest_short: 0,
+        price_feed,
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn token_account_data(mint: Pubkey, owner: Pubkey) -> Vec<u8> {
+    use anchor_spl::token::spl_token::state::{Account, AccountState};
+    use solana_sdk::program_pack::Pack;
+
+    let mut data = vec![0u8; Account::LEN];
+    let account = Account {
+        mint,
+        owner,
+        state: AccountState::Initialized,
+        ..Account::default()
+    };
+    account.pack_into_slice(&mut data);
+    data
+}
+
+// A current-layout (zero-copy) Position account.
+#[cfg(test)]
+pub(crate) fn zero_copy_position_data(position: &Position) -> Vec<u8> {
+    [&Position::DISCRIMINATOR[..], bytemuck::bytes_of(position)].concat()
+}
+
+#[cfg(test)]
+pub(crate) fn position_data(market_index: u16, at_risk_since: i64) -> Vec<u8> {
+    zero_copy_position_data(&Position {
+        owner: Pubkey::new_unique(),
+        size: 1_000_000,
+        entry_price: 3_000_000_000,
+        collateral: 300_000_000,
+        market_index,
+        leverage: 10,
+        is_long: 1,
+        closed: 0,
+        version: Position::VERSION,
+        _padding: [0],
+        at_risk_since,
+        _reserved: [0; 56],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::AnchorSerialize;
+
+    fn insurance_fund(authority: Pubkey) -> InsuranceFund {
+        InsuranceFund {
+            authority,
+            balance: 500_000_000,
+            total_contributions: 600_000_000,
+            total_bad_debt_covered: 100_000_000,
+            utilization_ratio: 1_666,
+            withdrawal_timelock_secs: 3_600,
+            withdrawal_epoch_secs: 86_400,
+            max_withdrawal_bps_per_epoch: 1_000,
+            min_reserve: 0,
+            epoch_start: 0,
+            epoch_start_balance: 0,
+            withdrawn_this_epoch: 0,
+            pending_withdrawal_amount: 0,
+            pending_withdrawal_requested_at: 0,
+            version: InsuranceFund::VERSION,
+            pending_withdrawal_recipient: Pubkey::default(),
+            _reserved: [0; 32],
+        }
+    }
+
+    #[test]
+    fn decodes_every_position_version() {
+        let data = position_data(1, 1_700_000_000);
+        let v1 = decode_position(&data).unwrap();
+        assert_eq!(v1.version, 1);
+        assert_eq!(v1.market_index, 1);
+        assert_eq!(v1.size, 1_000_000);
+        assert_eq!(v1.leverage, 10);
+        assert!(v1.is_long);
+        assert!(!v1.closed);
+        assert_eq!(v1.at_risk_since, 1_700_000_000);
+
+        // Unaligned buffers decode too.
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(&data);
+        assert_eq!(decode_position(&shifted[1..]).unwrap(), v1);
+
+        let owner = Pubkey::new_unique();
+        let legacy = LegacyPosition {
+            owner,
+            symbol: "BTC-PERP".to_string(),
+            size: 1_000_000,
+            is_long: false,
+            entry_price: 3_000_000_000,
+            collateral: 100_000_000,
+            leverage: 10,
+            closed: false,
+        };
+        let mut v0 = Position::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut v0).unwrap();
+
+        let v0 = decode_position(&v0).unwrap();
+        assert_eq!(v0.version, 0);
+        assert_eq!(v0.owner, owner);
+        assert_eq!(v0.market_index, 0);
+        assert!(!v0.is_long);
+    }
+
+    #[test]
+    fn decodes_price_feed_versions_and_rejects_wrong_discriminator() {
+        let v1 = account_data(&PriceFeed {
+            price: 60_000_000_000,
+            last_updated: 1_700_000_000,
+            version: PriceFeed::VERSION,
+            _reserved: [0; 32],
+        });
+        let v0 = v1[..8 + PriceFeed::V0_LEN].to_vec();
+
+        assert_eq!(decode_price_feed(&v0).unwrap().version, 0);
+        let feed = decode_price_feed(&v1).unwrap();
+        assert_eq!(feed.version, 1);
+        assert_eq!(feed.last_updated, 1_700_000_000);
+
+        assert!(decode_position(&v1).is_err());
+        assert!(decode_price_feed(&v1[..v1.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn decodes_insurance_fund_versions() {
+        let authority = Pubkey::new_unique();
+        let v1 = account_data(&insurance_fund(authority));
+
+        let fund = decode_insurance_fund(&v1).unwrap();
+        assert_eq!(fund.version, 1);
+        assert_eq!(fund.withdrawal_timelock_secs, 3_600);
+        assert_eq!(fund.balance, 500_000_000);
+
+        let v0 = &v1[..8 + InsuranceFund::V0_LEN];
+        let fund = decode_insurance_fund(v0).unwrap();
+        assert_eq!(fund.version, 0);
+        assert_eq!(fund.authority, authority);
+        assert_eq!(fund.balance, 500_000_000);
+        assert_eq!(fund.utilization_ratio, 1_666);
+        assert_eq!(fund.withdrawal_timelock_secs, 0);
+        assert_eq!(fund.max_withdrawal_bps_per_epoch, 0);
+    }
+
+    #[test]
+    fn decodes_liquidator_stats() {
+        let liquidator = Pubkey::new_unique();
+        let data = account_data(&LiquidatorStats {
+            liquidator,
+            liquidation_count: 3,
+            total_reward: 4_500_000,
+            last_liquidation_slot: 1_234,
+            bump: 254,
+        });
+
+        let stats = decode_liquidator_stats(&data).unwrap();
+        assert_eq!(stats.liquidator, liquidator);
+        assert_eq!(stats.liquidation_count, 3);
+        assert_eq!(stats.total_reward, 4_500_000);
+        assert_eq!(stats.last_liquidation_slot, 1_234);
+
+        assert!(decode_liquidator_stats(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn decodes_market_and_derives_its_address() {
+        let feed = Pubkey::new_unique();
+        let data = market_data(1, "ETH-PERP", feed);
+
+        let market = decode_market(&data).unwrap();
+        assert_eq!(market.market_index, 1);
+        assert_eq!(market.symbol, "ETH-PERP");
+        assert_eq!(market.price_decimals, 6);
+        assert_eq!(market.min_position_notional, 10_000_000);
+        assert_eq!(market.at_risk_grace_secs, 60);
+        assert_eq!(market.price_feed, feed);
+
+        assert!(decode_market(&data[..data.len() - 32]).is_err());
+
+        let program_id = Pubkey::new_unique();
+        assert_eq!(market_address(&program_id, 1), market_address(&program_id, 1));
+        assert_ne!(market_address(&program_id, 1), market_address(&program_id, 2));
+        assert_ne!(market_address(&program_id, 1), market_insurance_fund_address(&program_id, 1));
+    }
+
+    #[test]
+    fn decodes_market_insurance_fund() {
+        let insurance_fund = Pubkey::new_unique();
+        let data = market_insurance_fund_data(2, insurance_fund);
+
+        let fund = decode_market_insurance_fund(&data).unwrap();
+        assert_eq!(fund.insurance_fund, insurance_fund);
+        assert_eq!(fund.market_index, 2);
+
+        assert!(decode_market_insurance_fund(&data[..data.len() - 1]).is_err());
+        assert!(decode_market(&data).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use deadpool_postgres::{Config, Pool, Runtime};
+use tokio_postgres::NoTls;
+
+use crate::errors::LiquidationError;
+
+// Pool for DATABASE_URL, or `None` when no database is configured.
+pub fn pool_from_env() -> Result<Option<Pool>, LiquidationError> {
+    match std::env::var("DATABASE_URL") {
+        Ok(database_url) => create_pool(&database_url).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+pub fn create_pool(database_url: &str) -> Result<Pool, LiquidationError> {
+    let mut config = Config::new();
+    config.url = Some(database_url.to_string());
+    config
+        .create_pool(Some(Runtime::Tokio1), NoTls)
+        .map_err(|e| LiquidationError::Db(e.to_string()))
+}
+
+pub(crate) fn db_err(err: impl std::fmt::Display) -> LiquidationError {
+    LiquidationError::Db(err.to_string())
+}
@@ -1,33 +1,86 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use futures_util::StreamExt;
 use tokio::time::{interval, Duration};
 
+use liquidation_engine::MAX_ORACLE_STALENESS_SECS;
+use liquidation_math::MAINTENANCE_TIERS;
+
 use crate::errors::LiquidationError;
-use crate::models::Position;
-use crate::oracle::PriceOracle;
+use crate::models::{Position, PositionChange};
+use crate::oracle::{unix_now, OraclePrice, PriceOracle, StaticPriceOracle};
 use crate::position_manager::PositionManager;
 use crate::queue::LiquidationQueue;
 use crate::executor::LiquidationExecutor;
 
 pub struct LiquidationEngine {
     pub check_interval_ms: u64,
-    pub oracle: Arc<PriceOracle>,
+    pub oracle: Arc<dyn PriceOracle>,
     pub position_manager: Arc<PositionManager>,
     pub queue: Arc<LiquidationQueue>,
     pub executor: Arc<LiquidationExecutor>,
+    book: Arc<PositionBook>,
+}
+
+// Open positions checked each tick. Kept current from the source's change
+// feed when it has one; reloaded in full every tick otherwise.
+#[derive(Default)]
+struct PositionBook {
+    positions: RwLock<HashMap<String, Position>>,
+    live: AtomicBool,
+}
+
+impl PositionBook {
+    fn replace(&self, positions: Vec<Position>) {
+        *self.positions.write().unwrap() =
+            positions.into_iter().map(|p| (p.id.clone(), p)).collect();
+    }
+
+    fn apply(&self, change: PositionChange) {
+        let mut positions = self.positions.write().unwrap();
+        match change {
+            PositionChange::Upserted { position } => {
+                positions.insert(position.id.clone(), position);
+            }
+            PositionChange::Closed { id } => {
+                positions.remove(&id);
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Vec<Position> {
+        self.positions.read().unwrap().values().cloned().collect()
+    }
+}
+
+impl Default for LiquidationEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LiquidationEngine {
     pub fn new() -> Self {
+        Self::with_position_manager(Arc::new(PositionManager::new()))
+    }
+
+    pub fn with_position_manager(position_manager: Arc<PositionManager>) -> Self {
         Self {
             check_interval_ms: 1000,
-            oracle: Arc::new(PriceOracle::new()),
-            position_manager: Arc::new(PositionManager::new()),
+            oracle: Arc::new(StaticPriceOracle::default()),
+            position_manager,
             queue: Arc::new(LiquidationQueue::new()),
-            executor: Arc::new(LiquidationExecutor::new()),
+            executor: Arc::new(LiquidationExecutor::from_env()),
+            book: Arc::new(PositionBook::default()),
         }
     }
 
+    pub fn with_oracle(mut self, oracle: Arc<dyn PriceOracle>) -> Self {
+        self.oracle = oracle;
+        self
+    }
+
     pub async fn start(&self) {
         let mut timer = interval(Duration::from_millis(self.check_interval_ms));
 
@@ -43,54 +96,230 @@ impl LiquidationEngine {
         }
     }
 
+    async fn open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
+        if !self.book.live.load(Ordering::Acquire) {
+            self.resync_book().await?;
+        }
+        Ok(self.book.snapshot())
+    }
+
+    // Subscribes before loading, so a change landing in between is replayed
+    // on top of the snapshot rather than lost.
+    async fn resync_book(&self) -> Result<(), LiquidationError> {
+        let changes = match self.position_manager.subscribe_changes().await {
+            Ok(changes) => changes,
+            Err(e) => {
+                eprintln!("[ENGINE] Position change feed unavailable: {e:?}");
+                None
+            }
+        };
+        self.book.replace(self.position_manager.get_open_positions().await?);
+
+        if let Some(mut changes) = changes {
+            self.book.live.store(true, Ordering::Release);
+            let book = self.book.clone();
+            tokio::spawn(async move {
+                while let Some(change) = changes.next().await {
+                    book.apply(change);
+                }
+                eprintln!("[ENGINE] Position change feed closed; reloading every tick");
+                book.live.store(false, Ordering::Release);
+            });
+        }
+        Ok(())
+    }
+
+    // The mark `liquidate_*` would use, in the market's price decimals. A
+    // price older than the program's staleness limit is an error: every
+    // liquidation at it would fail with `StaleOraclePrice`.
+    async fn fresh_mark_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError> {
+        let price = self.oracle.get_price(symbol).await?;
+        let age = unix_now() - price.publish_time;
+        if age > MAX_ORACLE_STALENESS_SECS {
+            return Err(LiquidationError::Oracle(format!("{symbol} price is {age}s old")));
+        }
+        Ok(price)
+    }
+
     async fn check_all_positions(&self) -> Result<(), LiquidationError> {
-        let open_positions: Vec<Position> =
-            self.position_manager.get_open_positions().await?;
+        let open_positions = self.open_positions().await?;
 
-        let mut price_cache: HashMap<String, f64> = HashMap::new();
+        // None: no usable price this tick, so the market's positions are skipped.
+        let mut price_cache: HashMap<u16, Option<OraclePrice>> = HashMap::new();
 
         for position in open_positions {
-            let mark_price = if let Some(&cached) = price_cache.get(&position.symbol) {
-                cached
-            } else {
-                let price = self.oracle.get_mark_price(&position.symbol).await?;
-                price_cache.insert(position.symbol.clone(), price);
-                price
+            let market = position.market()?;
+            let mark_price = match price_cache.get(&position.market_index) {
+                Some(&cached) => cached,
+                None => {
+                    let price = match self.fresh_mark_price(market.symbol).await {
+                        Ok(price) => Some(price),
+                        Err(e) => {
+                            eprintln!("[ENGINE] Skipping {} this tick: {e}", market.symbol);
+                            None
+                        }
+                    };
+                    price_cache.insert(position.market_index, price);
+                    price
+                }
             };
-
-            let unrealized_pnl = if position.is_long {
-                position.size * (mark_price - position.entry_price)
-            } else {
-                position.size * (position.entry_price - mark_price)
+            let Some(OraclePrice { price: mark_price, publish_time }) = mark_price else {
+                continue;
             };
 
-            let position_value = position.size * mark_price;
-            if position_value <= 0.0 {
+            // Same fixed-point math and tiers as the program, so a queued
+            // position is one `liquidate_*` will not reject as healthy.
+            let (margin_ratio_bps, position_value) = position.margin_ratio(mark_price)?;
+            if position_value == 0 {
                 continue;
             }
 
-            let equity = position.collateral + unrealized_pnl;
-            let margin_ratio = equity / position_value;
+            // Sub-dust positions cost more in fees than the reward pays out.
+            if position_value < market.min_position_notional as u128 {
+                continue;
+            }
 
-            let maintenance_margin_ratio =
-                Self::get_maintenance_margin_ratio(position.leverage);
+            let maintenance_bps =
+                MAINTENANCE_TIERS.maintenance_bps(position.leverage, position_value);
 
-            if margin_ratio < maintenance_margin_ratio {
-                self.queue.enqueue(position, mark_price, margin_ratio)?;
+            if margin_ratio_bps < maintenance_bps {
+                self.queue.enqueue(
+                    position,
+                    mark_price,
+                    publish_time,
+                    margin_ratio_bps,
+                    position_value,
+                )?;
+            } else {
+                // Recovered before the executor got to it.
+                self.queue.remove(&position.id);
             }
         }
 
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    use async_trait::async_trait;
+    use futures_util::stream::{self, BoxStream};
+    use solana_sdk::pubkey::Pubkey;
+    use tokio::sync::mpsc;
+
+    use crate::models::load_test_markets;
+    use crate::position_manager::PositionSource;
+
+    struct AgedOracle(i64);
+
+    #[async_trait]
+    impl PriceOracle for AgedOracle {
+        fn name(&self) -> &str {
+            "aged"
+        }
+
+        async fn get_price(&self, _symbol: &str) -> Result<OraclePrice, LiquidationError> {
+            Ok(OraclePrice {
+                price: 60_000_000_000,
+                publish_time: unix_now() - self.0,
+            })
+        }
+    }
 
-    fn get_maintenance_margin_ratio(leverage: u16) -> f64 {
-        match leverage {
-            1..=20 => 0.025,
-            21..=50 => 0.01,
-            51..=100 => 0.005,
-            101..=500 => 0.0025,
-            501..=1000 => 0.001,
-            _ => 0.025,
+    struct FeedSource {
+        loads: AtomicUsize,
+        feed: std::sync::Mutex<Option<mpsc::UnboundedReceiver<PositionChange>>>,
+    }
+
+    #[async_trait]
+    impl PositionSource for FeedSource {
+        async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![position("pos-1")])
+        }
+
+        async fn get_position(&self, _id: &str) -> Result<Option<Position>, LiquidationError> {
+            Ok(None)
         }
+
+        async fn upsert_position(&self, _position: &Position) -> Result<bool, LiquidationError> {
+            Ok(true)
+        }
+
+        async fn close_position(&self, _id: &str) -> Result<bool, LiquidationError> {
+            Ok(true)
+        }
+
+        async fn subscribe_changes(
+            &self,
+        ) -> Result<Option<BoxStream<'static, PositionChange>>, LiquidationError> {
+            let rx = self.feed.lock().unwrap().take();
+            Ok(rx.map(|rx| {
+                stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|c| (c, rx)) })
+                    .boxed()
+            }))
+        }
+    }
+
+    fn position(id: &str) -> Position {
+        Position {
+            id: id.to_string(),
+            owner: Pubkey::new_unique(),
+            market_index: 1,
+            size: 1_000_000,
+            is_long: true,
+            entry_price: 3_000_000_000,
+            collateral: 300_000_000,
+            leverage: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn book_follows_change_feed_without_reloading() {
+        load_test_markets();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let source = Arc::new(FeedSource {
+            loads: AtomicUsize::new(0),
+            feed: std::sync::Mutex::new(Some(rx)),
+        });
+        let engine =
+            LiquidationEngine::with_position_manager(Arc::new(PositionManager::with_source(
+                source.clone(),
+            )));
+
+        assert_eq!(engine.open_positions().await.unwrap().len(), 1);
+
+        tx.send(PositionChange::Upserted { position: position("pos-2") }).unwrap();
+        tx.send(PositionChange::Closed { id: "pos-1".to_string() }).unwrap();
+        tokio::task::yield_now().await;
+
+        let ids: Vec<String> =
+            engine.open_positions().await.unwrap().into_iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec!["pos-2".to_string()]);
+        assert_eq!(source.loads.load(Ordering::SeqCst), 1);
+
+        // Feed gone: back to a full reload per tick.
+        drop(tx);
+        tokio::task::yield_now().await;
+        engine.open_positions().await.unwrap();
+        assert_eq!(source.loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stale_prices_queue_nothing() {
+        load_test_markets();
+        // The demo position is liquidatable at 60,000.
+        let stale = LiquidationEngine::new()
+            .with_oracle(Arc::new(AgedOracle(MAX_ORACLE_STALENESS_SECS + 1)));
+        stale.check_all_positions().await.unwrap();
+        assert!(stale.queue.snapshot_ids().is_empty());
+
+        let fresh = LiquidationEngine::new()
+            .with_oracle(Arc::new(AgedOracle(MAX_ORACLE_STALENESS_SECS - 5)));
+        fresh.check_all_positions().await.unwrap();
+        assert_eq!(fresh.queue.snapshot_ids(), vec!["pos-1".to_string()]);
     }
 }
@@ -1,9 +1,17 @@
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::transaction::TransactionError;
 use thiserror::Error;
 
+use liquidation_engine::ErrorCode;
+
 #[derive(Debug, Error)]
 pub enum LiquidationError {
     #[error("RPC error: {0}")]
-    Rpc(#[from] solana_client::client_error::ClientError),
+    Rpc(Box<solana_client::client_error::ClientError>),
+
+    // A transaction the program rejected with a custom error code.
+    #[error("Program error: {0}")]
+    Program(ProgramErrorCode),
 
     #[error("Database error: {0}")]
     Db(String),
@@ -14,6 +22,155 @@ pub enum LiquidationError {
     #[error("Oracle error: {0}")]
     Oracle(String),
 
+    #[error("Account decode error: {0}")]
+    Decode(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
     #[error("Other: {0}")]
     Other(String),
 }
+
+impl LiquidationError {
+    // Short, filterable kind: the program error's name when there is one.
+    pub fn code(&self) -> String {
+        match self {
+            Self::Program(code) => format!("{code:?}"),
+            Self::Rpc(_) => "Rpc".to_string(),
+            Self::Db(_) => "Db".to_string(),
+            Self::Redis(_) => "Redis".to_string(),
+            Self::Oracle(_) => "Oracle".to_string(),
+            Self::Decode(_) => "Decode".to_string(),
+            Self::Timeout(_) => "Timeout".to_string(),
+            Self::Other(_) => "Other".to_string(),
+        }
+    }
+}
+
+// The program errors the executor acts on; any other custom code is kept raw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ProgramErrorCode {
+    #[error("PositionHealthy")]
+    PositionHealthy,
+    #[error("StaleOraclePrice")]
+    StaleOraclePrice,
+    #[error("PositionClosed")]
+    PositionClosed,
+    #[error("InvalidLiquidationSize")]
+    InvalidLiquidationSize,
+    #[error("MathOverflow")]
+    MathOverflow,
+    #[error("AtRiskGracePeriodActive")]
+    AtRiskGracePeriodActive,
+    #[error("PriceFeedMismatch")]
+    PriceFeedMismatch,
+    #[error("custom program error {0}")]
+    Other(u32),
+}
+
+impl ProgramErrorCode {
+    const KNOWN: [(ErrorCode, ProgramErrorCode); 7] = [
+        (ErrorCode::PositionHealthy, Self::PositionHealthy),
+        (ErrorCode::StaleOraclePrice, Self::StaleOraclePrice),
+        (ErrorCode::PositionClosed, Self::PositionClosed),
+        (ErrorCode::InvalidLiquidationSize, Self::InvalidLiquidationSize),
+        (ErrorCode::MathOverflow, Self::MathOverflow),
+        (ErrorCode::AtRiskGracePeriodActive, Self::AtRiskGracePeriodActive),
+        (ErrorCode::PriceFeedMismatch, Self::PriceFeedMismatch),
+    ];
+
+    // `code` as reported in `InstructionError::Custom`, i.e. with Anchor's 6000 offset.
+    pub fn from_code(code: u32) -> Self {
+        Self::KNOWN
+            .into_iter()
+            .find(|(known, _)| u32::from(*known) == code)
+            .map_or(Self::Other(code), |(_, typed)| typed)
+    }
+
+    // Finds the custom error in a failed send, whether preflight simulation
+    // or the confirmed transaction reported it.
+    pub fn from_client_error(err: &solana_client::client_error::ClientError) -> Option<Self> {
+        match err.get_transaction_error()? {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                Some(Self::from_code(code))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<solana_client::client_error::ClientError> for LiquidationError {
+    fn from(err: solana_client::client_error::ClientError) -> Self {
+        match ProgramErrorCode::from_client_error(&err) {
+            Some(code) => Self::Program(code),
+            None => Self::Rpc(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::client_error::{ClientError, ClientErrorKind};
+    use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+
+    fn preflight_failure(err: TransactionError) -> ClientError {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(
+                RpcSimulateTransactionResult {
+                    err: Some(err),
+                    logs: None,
+                    accounts: None,
+                    units_consumed: None,
+                    return_data: None,
+                    inner_instructions: None,
+                },
+            ),
+        })
+        .into()
+    }
+
+    #[test]
+    fn decodes_anchor_error_codes() {
+        assert_eq!(ProgramErrorCode::from_code(6001), ProgramErrorCode::PositionHealthy);
+        assert_eq!(ProgramErrorCode::from_code(6000), ProgramErrorCode::MathOverflow);
+        assert_eq!(
+            ProgramErrorCode::from_code(u32::from(ErrorCode::PriceFeedMismatch)),
+            ProgramErrorCode::PriceFeedMismatch
+        );
+        assert_eq!(
+            ProgramErrorCode::from_code(u32::from(ErrorCode::MarketMismatch)),
+            ProgramErrorCode::Other(u32::from(ErrorCode::MarketMismatch))
+        );
+    }
+
+    #[test]
+    fn client_errors_with_custom_codes_become_program_errors() {
+        let stale = u32::from(ErrorCode::StaleOraclePrice);
+        let err = preflight_failure(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(stale),
+        ));
+        assert!(matches!(
+            LiquidationError::from(err),
+            LiquidationError::Program(ProgramErrorCode::StaleOraclePrice)
+        ));
+
+        let confirmed: ClientError = TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(u32::from(ErrorCode::PositionClosed)),
+        )
+        .into();
+        assert!(matches!(
+            LiquidationError::from(confirmed),
+            LiquidationError::Program(ProgramErrorCode::PositionClosed)
+        ));
+
+        let other = preflight_failure(TransactionError::BlockhashNotFound);
+        assert!(matches!(LiquidationError::from(other), LiquidationError::Rpc(_)));
+    }
+}
@@ -1,32 +1,977 @@
+use std::str::FromStr;
 use std::sync::Arc;
 
-use crate::errors::LiquidationError;
+use anchor_spl::associated_token::get_associated_token_address;
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use tokio::time::{timeout, Duration, Instant};
+
+use liquidation_engine::liquidation_grace_elapsed;
+use liquidation_math::MAINTENANCE_TIERS;
+
+use crate::accounts::{
+    collateral_vault_address, decode_market, decode_market_insurance_fund, decode_position,
+    decode_token_account, insurance_vault_address, insurance_vault_authority_address,
+    liquidator_stats_address, market_address, market_insurance_fund_address,
+    market_insurance_vault_address, PROGRAM_ID,
+};
+use crate::errors::{LiquidationError, ProgramErrorCode};
+use crate::failed_liquidations::{
+    log_from_env, FailedAttempt, FailedLiquidationLog, MemoryFailedLiquidationLog,
+};
+use crate::instructions::{self, liquidate_full, liquidate_partial, LiquidationAccounts};
+use crate::models::{scale_up, LiquidationCandidate, LiquidationMode, QUOTE_DECIMALS};
+use crate::oracle::unix_now;
 use crate::queue::LiquidationQueue;
 
-pub struct LiquidationExecutor {}
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+// Sent but unconfirmed after this: recorded as a timeout and retried.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Positions at or above this notional (quote * 1e6) are routed to a backstop LP,
+// since closing them at mark is unrealistic.
+const DEFAULT_BACKSTOP_NOTIONAL_THRESHOLD: u128 = 1_000_000 * liquidation_math::SCALE;
+// A backstop candidate the LP has not taken over after this is closed with
+// `liquidate_full` instead.
+const DEFAULT_BACKSTOP_WAIT: Duration = Duration::from_secs(60);
+
+// A failed send, with the transaction's signature once one was signed.
+#[derive(Debug)]
+pub struct SendFailure {
+    pub error: LiquidationError,
+    pub signature: Option<Signature>,
+}
+
+impl From<LiquidationError> for SendFailure {
+    fn from(error: LiquidationError) -> Self {
+        Self {
+            error,
+            signature: None,
+        }
+    }
+}
+
+// A position's at-risk flag and its market's grace window, as read on chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraceWindow {
+    pub at_risk_since: i64, // 0: not flagged
+    pub grace_secs: i64,    // 0: no grace period
+}
+
+// Lands a `liquidate_partial` / `liquidate_full` for a candidate, and the
+// `flag_at_risk` that has to precede it on markets with a grace period.
+#[async_trait]
+pub trait LiquidationSender: Send + Sync {
+    fn liquidator(&self) -> Pubkey;
+
+    async fn grace_window(
+        &self,
+        candidate: &LiquidationCandidate,
+    ) -> Result<GraceWindow, LiquidationError>;
+
+    async fn flag_at_risk(&self, candidate: &LiquidationCandidate)
+        -> Result<Signature, SendFailure>;
+
+    async fn liquidate(
+        &self,
+        candidate: &LiquidationCandidate,
+        mode: LiquidationMode,
+    ) -> Result<Signature, SendFailure>;
+}
+
+// Signs with the liquidator key and waits for confirmation. The market's
+// feed and insurance fund are read fresh for every send, so a rebound
+// `Market.price_feed` only costs a `PriceFeedMismatch` if it races the send.
+pub struct RpcLiquidationSender {
+    rpc: RpcClient,
+    liquidator: Keypair,
+    program_id: Pubkey,
+}
+
+impl RpcLiquidationSender {
+    pub fn new(rpc: RpcClient, liquidator: Keypair, program_id: Pubkey) -> Self {
+        Self {
+            rpc,
+            liquidator,
+            program_id,
+        }
+    }
+
+    pub async fn resolve_accounts(
+        &self,
+        candidate: &LiquidationCandidate,
+    ) -> Result<LiquidationAccounts, LiquidationError> {
+        let position = position_address(candidate)?;
+        let market_index = candidate.position.market_index;
+        let liquidator = self.liquidator.pubkey();
+
+        let market = market_address(&self.program_id, market_index);
+        let market_insurance_fund = market_insurance_fund_address(&self.program_id, market_index);
+        let liquidator_stats = liquidator_stats_address(&self.program_id, &liquidator);
+        let collateral_vault = collateral_vault_address(&self.program_id, market_index);
+        let accounts = self
+            .rpc
+            .get_multiple_accounts(&[
+                market,
+                market_insurance_fund,
+                liquidator_stats,
+                collateral_vault,
+            ])
+            .await?;
+
+        let missing =
+            |what: &str| LiquidationError::Other(format!("no {what} for market {market_index}"));
+        let market_account = accounts[0].as_ref().ok_or_else(|| missing("Market"))?;
+        let price_feed = decode_market(&market_account.data)?.price_feed;
+        if price_feed == Pubkey::default() {
+            return Err(missing("price feed"));
+        }
+        let insurance_fund = decode_market_insurance_fund(
+            &accounts[1].as_ref().ok_or_else(|| missing("MarketInsuranceFund"))?.data,
+        )?
+        .insurance_fund;
+        // Payouts go to the liquidator's and the owner's associated token
+        // accounts for the vault's mint.
+        let mint = decode_token_account(
+            &accounts[3].as_ref().ok_or_else(|| missing("collateral vault"))?.data,
+        )?
+        .mint;
+
+        Ok(LiquidationAccounts {
+            position,
+            liquidator,
+            price_feed,
+            insurance_fund,
+            market_insurance_fund,
+            market,
+            liquidator_stats: accounts[2].as_ref().map(|_| liquidator_stats),
+            collateral_vault,
+            market_insurance_vault: market_insurance_vault_address(&self.program_id, market_index),
+            insurance_vault: insurance_vault_address(&self.program_id, &insurance_fund),
+            insurance_vault_authority: insurance_vault_authority_address(
+                &self.program_id,
+                &insurance_fund,
+            ),
+            liquidator_token_account: get_associated_token_address(&liquidator, &mint),
+            owner_token_account: get_associated_token_address(&candidate.position.owner, &mint),
+        })
+    }
+
+    async fn send(&self, ix: Instruction) -> Result<Signature, SendFailure> {
+        let blockhash = self.rpc.get_latest_blockhash().await.map_err(LiquidationError::from)?;
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.liquidator.pubkey()),
+            &[&self.liquidator],
+            blockhash,
+        );
+        let signature = tx.signatures[0];
+        let error = match timeout(CONFIRM_TIMEOUT, self.rpc.send_and_confirm_transaction(&tx)).await
+        {
+            Ok(Ok(confirmed)) => return Ok(confirmed),
+            Ok(Err(e)) => e.into(),
+            Err(_) => LiquidationError::Timeout(format!(
+                "{signature} not confirmed within {}s",
+                CONFIRM_TIMEOUT.as_secs()
+            )),
+        };
+        Err(SendFailure {
+            error,
+            signature: Some(signature),
+        })
+    }
+}
+
+// Position ids from the on-chain loader are the position account address.
+fn position_address(candidate: &LiquidationCandidate) -> Result<Pubkey, LiquidationError> {
+    let id = &candidate.position.id;
+    Pubkey::from_str(id)
+        .map_err(|_| LiquidationError::Other(format!("position {id} is not an account address")))
+}
+
+#[async_trait]
+impl LiquidationSender for RpcLiquidationSender {
+    fn liquidator(&self) -> Pubkey {
+        self.liquidator.pubkey()
+    }
+
+    async fn grace_window(
+        &self,
+        candidate: &LiquidationCandidate,
+    ) -> Result<GraceWindow, LiquidationError> {
+        let position = position_address(candidate)?;
+        let market = market_address(&self.program_id, candidate.position.market_index);
+        let accounts = self.rpc.get_multiple_accounts(&[position, market]).await?;
+
+        let missing = |what: &str| {
+            LiquidationError::Other(format!("no {what} for position {}", candidate.position.id))
+        };
+        let position = accounts[0].as_ref().ok_or_else(|| missing("Position"))?;
+        let market = accounts[1].as_ref().ok_or_else(|| missing("Market"))?;
+        Ok(GraceWindow {
+            at_risk_since: decode_position(&position.data)?.at_risk_since,
+            grace_secs: decode_market(&market.data)?.at_risk_grace_secs,
+        })
+    }
+
+    async fn flag_at_risk(
+        &self,
+        candidate: &LiquidationCandidate,
+    ) -> Result<Signature, SendFailure> {
+        let accounts = self.resolve_accounts(candidate).await?;
+        self.send(instructions::flag_at_risk(
+            &self.program_id,
+            &accounts.position,
+            &accounts.price_feed,
+            &accounts.market,
+        ))
+        .await
+    }
+
+    async fn liquidate(
+        &self,
+        candidate: &LiquidationCandidate,
+        mode: LiquidationMode,
+    ) -> Result<Signature, SendFailure> {
+        let accounts = self.resolve_accounts(candidate).await?;
+        let ix = match mode {
+            // Asks for everything; the program takes at most half.
+            LiquidationMode::Partial => {
+                liquidate_partial(&self.program_id, &accounts, candidate.position.size)
+            }
+            LiquidationMode::Full => liquidate_full(&self.program_id, &accounts),
+            LiquidationMode::BackstopTakeover => {
+                return Err(LiquidationError::Other(
+                    "backstop takeovers are signed by the LP".to_string(),
+                )
+                .into())
+            }
+        };
+        self.send(ix).await
+    }
+}
+
+// What a failed attempt means for the candidate, from the program's error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    Drop,              // healthy or closed on chain: nothing to liquidate
+    AwaitPriceRefresh, // feed stale on chain: retry once a newer price is seen
+    CloseFully,        // too small to split: retry right away with `liquidate_full`
+    AwaitGrace,        // grace window still running on chain: hold until it ends
+    ResolveFeed,       // market rebound its feed: re-resolve it and retry right away
+    Alert,             // the program's math overflowed: needs a human
+    Retry,             // anything else: retry after the cooldown
+}
+
+impl FailureAction {
+    pub fn for_error(err: &LiquidationError, mode: LiquidationMode) -> Self {
+        let LiquidationError::Program(code) = err else {
+            return Self::Retry;
+        };
+        match code {
+            ProgramErrorCode::PositionHealthy | ProgramErrorCode::PositionClosed => Self::Drop,
+            ProgramErrorCode::StaleOraclePrice => Self::AwaitPriceRefresh,
+            ProgramErrorCode::InvalidLiquidationSize if mode == LiquidationMode::Partial => {
+                Self::CloseFully
+            }
+            // Only an empty position has no size to close.
+            ProgramErrorCode::InvalidLiquidationSize => Self::Drop,
+            ProgramErrorCode::AtRiskGracePeriodActive => Self::AwaitGrace,
+            ProgramErrorCode::PriceFeedMismatch => Self::ResolveFeed,
+            ProgramErrorCode::MathOverflow => Self::Alert,
+            ProgramErrorCode::Other(_) => Self::Retry,
+        }
+    }
+}
+
+pub struct LiquidationExecutor {
+    pub backstop_lp: Option<Pubkey>,
+    pub backstop_notional_threshold: u128,
+    pub backstop_wait: Duration,
+    // None: dry run, candidates are only logged.
+    sender: Option<Arc<dyn LiquidationSender>>,
+    failures: Arc<dyn FailedLiquidationLog>,
+}
+
+impl Default for LiquidationExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl LiquidationExecutor {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            backstop_lp: None,
+            backstop_notional_threshold: DEFAULT_BACKSTOP_NOTIONAL_THRESHOLD,
+            backstop_wait: DEFAULT_BACKSTOP_WAIT,
+            sender: None,
+            failures: Arc::new(MemoryFailedLiquidationLog::new()),
+        }
     }
 
+    pub fn with_sender(mut self, sender: Arc<dyn LiquidationSender>) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn with_failure_log(mut self, failures: Arc<dyn FailedLiquidationLog>) -> Self {
+        self.failures = failures;
+        self
+    }
+
+    // Failed attempts, served by `GET /liquidations/failed`.
+    pub fn failure_log(&self) -> Arc<dyn FailedLiquidationLog> {
+        self.failures.clone()
+    }
+
+    // BACKSTOP_LP: registered LP pubkey; BACKSTOP_NOTIONAL_THRESHOLD: routing
+    // cutoff in whole quote units; BACKSTOP_WAIT_SECS: how long the LP gets
+    // before the executor closes the position itself. LIQUIDATOR_KEYPAIR turns on sending, with
+    // RPC_URL and LIQUIDATION_PROGRAM_ID; without it the executor only logs.
+    // Failed attempts go to Postgres when DATABASE_URL is set.
+    pub fn from_env() -> Self {
+        let mut executor = Self::new();
+        match log_from_env() {
+            Ok(failures) => executor.failures = failures,
+            Err(e) => eprintln!("[EXECUTOR] Keeping failed liquidations in memory: {e}"),
+        }
+        executor.backstop_lp = std::env::var("BACKSTOP_LP")
+            .ok()
+            .and_then(|v| v.parse::<Pubkey>().ok());
+        if let Some(threshold) = std::env::var("BACKSTOP_NOTIONAL_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+        {
+            executor.backstop_notional_threshold = scale_up(threshold, QUOTE_DECIMALS) as u128;
+        }
+        if let Some(secs) = std::env::var("BACKSTOP_WAIT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            executor.backstop_wait = Duration::from_secs(secs);
+        }
+
+        match sender_from_env() {
+            Ok(Some(sender)) => executor.with_sender(Arc::new(sender)),
+            Ok(None) => executor,
+            Err(e) => {
+                eprintln!("[EXECUTOR] Liquidator not configured, dry run only: {e}");
+                executor
+            }
+        }
+    }
+
+    pub fn select_mode(&self, candidate: &LiquidationCandidate) -> LiquidationMode {
+        if self.backstop_lp.is_some()
+            && candidate.position_value >= self.backstop_notional_threshold
+        {
+            LiquidationMode::BackstopTakeover
+        } else if candidate.margin_ratio_bps == 0 {
+            // no equity left -> close out and let the insurance funds absorb bad debt
+            LiquidationMode::Full
+        } else {
+            LiquidationMode::Partial
+        }
+    }
+
+    // A candidate leaves the queue once its liquidation confirms or the
+    // program says there is nothing to liquidate; other failures stay queued
+    // (see `FailureAction`).
     pub async fn drain_queue(
         &self,
         queue: Arc<LiquidationQueue>,
     ) -> Result<(), LiquidationError> {
-        // NOTE: we use snapshot() so we don't consume the queue.
-        let candidates = queue.snapshot();
-
-        for candidate in candidates {
-            println!(
-                "[EXECUTOR] Would liquidate position {} on {} at price {} (margin_ratio={})",
-                candidate.position.id,
-                candidate.position.symbol,
-                candidate.mark_price,
-                candidate.margin_ratio
-            );
+        for candidate in queue.due() {
+            let id = &candidate.position.id;
+            let symbol = candidate.position.symbol().unwrap_or("?");
+            let mut mode = self.select_mode(&candidate);
+
+            if mode == LiquidationMode::BackstopTakeover {
+                // `liquidate_backstop` needs the LP's signature, not ours; if
+                // the LP leaves it for `backstop_wait`, close it ourselves.
+                if queue.backstop_wait(id) < self.backstop_wait {
+                    println!(
+                        "[EXECUTOR] Position {id} on {symbol} is for backstop LP {} at price {} \
+                         (margin_ratio_bps={})",
+                        self.backstop_lp.unwrap_or_default(),
+                        candidate.mark_price,
+                        candidate.margin_ratio_bps
+                    );
+                    queue.mark_attempted(id);
+                    continue;
+                }
+                println!(
+                    "[EXECUTOR] Backstop LP did not take over position {id} on {symbol}; \
+                     closing it fully"
+                );
+                mode = LiquidationMode::Full;
+            }
+
+            let Some(sender) = &self.sender else {
+                println!(
+                    "[EXECUTOR] Would liquidate ({mode:?}) position {id} on {symbol} at price {} \
+                     (margin_ratio_bps={})",
+                    candidate.mark_price, candidate.margin_ratio_bps
+                );
+                continue;
+            };
+
+            if self.grace_elapsed(sender.as_ref(), &queue, &candidate).await {
+                self.execute(sender.as_ref(), &queue, &candidate, mode).await;
+            }
         }
 
         Ok(())
     }
+
+    // Mirrors the program's gate: a non-critical candidate can only be
+    // liquidated once its at-risk flag is `grace_secs` old. An unflagged one
+    // is flagged here; either way it is held until the window ends.
+    async fn grace_elapsed(
+        &self,
+        sender: &dyn LiquidationSender,
+        queue: &LiquidationQueue,
+        candidate: &LiquidationCandidate,
+    ) -> bool {
+        let id = &candidate.position.id;
+        let symbol = candidate.position.symbol().unwrap_or("?");
+        let window = match sender.grace_window(candidate).await {
+            Ok(window) => window,
+            Err(error) => {
+                queue.mark_attempted(id);
+                eprintln!("[EXECUTOR] Could not read the grace window of {id}: {error}");
+                self.record_failure(sender, candidate, &error.into()).await;
+                return false;
+            }
+        };
+
+        let now = unix_now();
+        let leverage = candidate.position.leverage;
+        let maintenance_bps = MAINTENANCE_TIERS.maintenance_bps(leverage, candidate.position_value);
+        if liquidation_grace_elapsed(
+            window.at_risk_since,
+            window.grace_secs,
+            candidate.margin_ratio_bps,
+            maintenance_bps,
+            now,
+        ) {
+            return true;
+        }
+        if window.at_risk_since != 0 {
+            queue.hold_until(id, window.at_risk_since.saturating_add(window.grace_secs));
+            return false;
+        }
+
+        queue.mark_attempted(id);
+        match sender.flag_at_risk(candidate).await {
+            Ok(signature) => {
+                queue.hold_until(id, now.saturating_add(window.grace_secs));
+                println!(
+                    "[EXECUTOR] Flagged position {id} on {symbol} at risk ({signature}); \
+                     liquidating after {}s",
+                    window.grace_secs
+                );
+            }
+            Err(failure) => {
+                eprintln!("[EXECUTOR] flag_at_risk of {id} failed: {}", failure.error);
+                self.record_failure(sender, candidate, &failure).await;
+            }
+        }
+        false
+    }
+
+    async fn execute(
+        &self,
+        sender: &dyn LiquidationSender,
+        queue: &LiquidationQueue,
+        candidate: &LiquidationCandidate,
+        mut mode: LiquidationMode,
+    ) {
+        let id = &candidate.position.id;
+        let symbol = candidate.position.symbol().unwrap_or("?");
+        let mut feed_resolved = false;
+
+        loop {
+            queue.mark_attempted(id);
+            let sent_at = Instant::now();
+            let failure = match sender.liquidate(candidate, mode).await {
+                Ok(signature) => {
+                    queue.remove(id);
+                    println!(
+                        "[EXECUTOR] Liquidated ({mode:?}) position {id} on {symbol} at price {}: \
+                         {signature} confirmed in {}ms",
+                        candidate.mark_price,
+                        sent_at.elapsed().as_millis()
+                    );
+                    return;
+                }
+                Err(failure) => failure,
+            };
+            let err = &failure.error;
+            self.record_failure(sender, candidate, &failure).await;
+
+            match FailureAction::for_error(err, mode) {
+                FailureAction::Drop => {
+                    queue.remove(id);
+                    println!("[EXECUTOR] Dropping position {id} on {symbol}: {err}");
+                }
+                FailureAction::AwaitPriceRefresh => {
+                    queue.await_price_refresh(id);
+                    eprintln!("[EXECUTOR] {symbol} feed is stale on chain; holding {id}: {err}");
+                }
+                FailureAction::CloseFully => {
+                    println!("[EXECUTOR] Position {id} is too small to split; closing it fully");
+                    mode = LiquidationMode::Full;
+                    continue;
+                }
+                FailureAction::AwaitGrace => match sender.grace_window(candidate).await {
+                    Ok(window) if window.at_risk_since != 0 => {
+                        let until = window.at_risk_since.saturating_add(window.grace_secs);
+                        queue.hold_until(id, until);
+                        println!("[EXECUTOR] Holding {id} on {symbol} until its grace window ends");
+                    }
+                    // Unflagged: the next attempt flags it (see `grace_elapsed`).
+                    Ok(_) => println!("[EXECUTOR] Position {id} on {symbol} is not flagged: {err}"),
+                    Err(e) => eprintln!("[EXECUTOR] Could not read the grace window of {id}: {e}"),
+                },
+                // The sender resolves the market's feed on every send; one
+                // retry picks up the rebound feed.
+                FailureAction::ResolveFeed if !feed_resolved => {
+                    println!("[EXECUTOR] {symbol} rebound its price feed; retrying {id}");
+                    feed_resolved = true;
+                    continue;
+                }
+                FailureAction::ResolveFeed => {
+                    eprintln!("[EXECUTOR] {symbol} feed still mismatched for {id}: {err}")
+                }
+                FailureAction::Alert => eprintln!(
+                    "[ALERT] Liquidation ({mode:?}) of {id} on {symbol} overflowed on chain \
+                     (mark_price={}, margin_ratio_bps={}): {err}",
+                    candidate.mark_price, candidate.margin_ratio_bps
+                ),
+                FailureAction::Retry => {
+                    eprintln!("[EXECUTOR] Liquidation ({mode:?}) of {id} failed: {err}")
+                }
+            }
+            return;
+        }
+    }
+
+    async fn record_failure(
+        &self,
+        sender: &dyn LiquidationSender,
+        candidate: &LiquidationCandidate,
+        failure: &SendFailure,
+    ) {
+        let attempt = FailedAttempt {
+            position_id: candidate.position.id.clone(),
+            liquidator: sender.liquidator().to_string(),
+            symbol: candidate.position.symbol().unwrap_or("?").to_string(),
+            margin_ratio_bps: Some(candidate.margin_ratio_bps),
+            error_code: failure.error.code(),
+            error_message: failure.error.to_string(),
+            attempt_tx: failure.signature.map(|s| s.to_string()),
+        };
+        if let Err(e) = self.failures.record(&attempt).await {
+            eprintln!(
+                "[EXECUTOR] Could not record failed liquidation of {}: {e}",
+                candidate.position.id
+            );
+        }
+    }
+}
+
+fn sender_from_env() -> Result<Option<RpcLiquidationSender>, LiquidationError> {
+    let Ok(keypair_path) = std::env::var("LIQUIDATOR_KEYPAIR") else {
+        return Ok(None);
+    };
+    let liquidator = read_keypair_file(&keypair_path)
+        .map_err(|e| LiquidationError::Other(format!("read {keypair_path}: {e}")))?;
+
+    let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+    let program_id = std::env::var("LIQUIDATION_PROGRAM_ID")
+        .unwrap_or_else(|_| PROGRAM_ID.to_string());
+    let program_id = Pubkey::from_str(&program_id)
+        .map_err(|e| LiquidationError::Other(format!("invalid program id: {e}")))?;
+
+    Ok(Some(RpcLiquidationSender::new(RpcClient::new(rpc_url), liquidator, program_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use solana_account_decoder::{UiAccount, UiAccountEncoding};
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::account::Account;
+
+    use crate::accounts::{
+        market_data, market_insurance_fund_data, position_data, token_account_data,
+    };
+    use crate::models::Position;
+
+    // Fails the (id, mode) pairs in `failures` with the given program error
+    // (None: a plain RPC failure); records everything it is asked to send.
+    // Grace windows default to none; `flag_at_risk` starts one like the program,
+    // and so does a failure with `AtRiskGracePeriodActive` (a flag set on chain
+    // after the window was read).
+    struct MockSender {
+        failures: Vec<(&'static str, LiquidationMode, Option<ProgramErrorCode>)>,
+        sent: Mutex<Vec<(String, LiquidationMode)>>,
+        windows: Mutex<HashMap<String, GraceWindow>>,
+        flagged: Mutex<Vec<String>>,
+    }
+
+    impl MockSender {
+        fn new(failures: Vec<(&'static str, LiquidationMode, Option<ProgramErrorCode>)>) -> Self {
+            Self {
+                failures,
+                sent: Mutex::new(Vec::new()),
+                windows: Mutex::new(HashMap::new()),
+                flagged: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn with_window(self, id: &str, at_risk_since: i64, grace_secs: i64) -> Self {
+            let window = GraceWindow {
+                at_risk_since,
+                grace_secs,
+            };
+            self.windows.lock().unwrap().insert(id.to_string(), window);
+            self
+        }
+    }
+
+    #[async_trait]
+    impl LiquidationSender for MockSender {
+        fn liquidator(&self) -> Pubkey {
+            Pubkey::default()
+        }
+
+        async fn grace_window(
+            &self,
+            candidate: &LiquidationCandidate,
+        ) -> Result<GraceWindow, LiquidationError> {
+            let windows = self.windows.lock().unwrap();
+            Ok(windows.get(&candidate.position.id).copied().unwrap_or(GraceWindow {
+                at_risk_since: 0,
+                grace_secs: 0,
+            }))
+        }
+
+        async fn flag_at_risk(
+            &self,
+            candidate: &LiquidationCandidate,
+        ) -> Result<Signature, SendFailure> {
+            let id = candidate.position.id.clone();
+            if let Some(window) = self.windows.lock().unwrap().get_mut(&id) {
+                window.at_risk_since = unix_now();
+            }
+            self.flagged.lock().unwrap().push(id);
+            Ok(Signature::default())
+        }
+
+        async fn liquidate(
+            &self,
+            candidate: &LiquidationCandidate,
+            mode: LiquidationMode,
+        ) -> Result<Signature, SendFailure> {
+            let id = candidate.position.id.clone();
+            self.sent.lock().unwrap().push((id.clone(), mode));
+            let failure = self.failures.iter().find(|(f, m, _)| *f == id && *m == mode);
+            if let Some((_, _, Some(ProgramErrorCode::AtRiskGracePeriodActive))) = failure {
+                let window = GraceWindow {
+                    at_risk_since: unix_now(),
+                    grace_secs: 60,
+                };
+                self.windows.lock().unwrap().insert(id.clone(), window);
+            }
+            let error = match failure {
+                Some((_, _, Some(code))) => LiquidationError::Program(*code),
+                Some((_, _, None)) => LiquidationError::Timeout("not confirmed".to_string()),
+                None => return Ok(Signature::default()),
+            };
+            Err(SendFailure {
+                error,
+                signature: Some(Signature::default()),
+            })
+        }
+    }
+
+    fn position(id: &str) -> Position {
+        Position {
+            id: id.to_string(),
+            owner: Pubkey::new_unique(),
+            market_index: 1,
+            size: 1_000_000,
+            is_long: true,
+            entry_price: 3_000_000_000,
+            collateral: 300_000_000,
+            leverage: 10,
+        }
+    }
+
+    fn candidate(id: &str, margin_ratio_bps: u64) -> LiquidationCandidate {
+        LiquidationCandidate {
+            position: position(id),
+            mark_price: 2_800_000_000,
+            price_time: 0,
+            margin_ratio_bps,
+            position_value: 2_800_000_000,
+        }
+    }
+
+    fn ui_account(data: Vec<u8>) -> UiAccount {
+        let account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        UiAccount::encode(&Pubkey::new_unique(), &account, UiAccountEncoding::Base64, None, None)
+    }
+
+    fn sent(sender: &MockSender) -> Vec<(String, LiquidationMode)> {
+        std::mem::take(&mut *sender.sent.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn drain_removes_only_confirmed_liquidations() {
+        let queue = Arc::new(LiquidationQueue::new());
+        queue.enqueue(position("ok"), 2_800_000_000, 0, 0, 2_800_000_000).unwrap();
+        queue.enqueue(position("bad"), 2_800_000_000, 0, 200, 2_800_000_000).unwrap();
+        let sender = Arc::new(MockSender::new(vec![("bad", LiquidationMode::Partial, None)]));
+        let executor = LiquidationExecutor::new().with_sender(sender.clone());
+
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert_eq!(
+            sent(&sender),
+            vec![
+                ("ok".to_string(), LiquidationMode::Full),
+                ("bad".to_string(), LiquidationMode::Partial),
+            ]
+        );
+        assert_eq!(queue.snapshot_ids(), vec!["bad".to_string()]);
+
+        // The failure is cooling down, so the next tick sends nothing.
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert!(sent(&sender).is_empty());
+
+        let failed = executor.failure_log().list(&Default::default()).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        let attempt = &failed[0].attempt;
+        assert_eq!(attempt.position_id, "bad");
+        assert_eq!(attempt.symbol, "ETH-PERP");
+        assert_eq!(attempt.margin_ratio_bps, Some(200));
+        assert_eq!(attempt.error_code, "Timeout");
+        assert_eq!(attempt.attempt_tx, Some(Signature::default().to_string()));
+    }
+
+    #[tokio::test]
+    async fn non_critical_candidates_are_flagged_and_held_for_the_grace_window() {
+        use LiquidationMode::{Full, Partial};
+
+        // 10x on 2,800 of notional: 250 bps maintenance, critical below 125.
+        let now = unix_now();
+        let queue = Arc::new(LiquidationQueue::new());
+        let margins = [("new", 200), ("waiting", 200), ("ripe", 200), ("critical", 100)];
+        for (id, margin_ratio_bps) in margins {
+            queue.enqueue(position(id), 2_800_000_000, 0, margin_ratio_bps, 2_800_000_000).unwrap();
+        }
+        let sender = Arc::new(
+            MockSender::new(vec![])
+                .with_window("new", 0, 60)
+                .with_window("waiting", now - 10, 60)
+                .with_window("ripe", now - 60, 60)
+                .with_window("critical", 0, 60),
+        );
+        let executor = LiquidationExecutor::new().with_sender(sender.clone());
+
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert_eq!(
+            sent(&sender),
+            vec![("critical".to_string(), Partial), ("ripe".to_string(), Partial)]
+        );
+        assert_eq!(*sender.flagged.lock().unwrap(), vec!["new".to_string()]);
+
+        // Flagged and waiting positions stay queued until their windows end.
+        let mut left = queue.snapshot_ids();
+        left.sort();
+        assert_eq!(left, vec!["new".to_string(), "waiting".to_string()]);
+        assert!(queue.due().is_empty());
+
+        // Without a grace period nothing is flagged.
+        queue.enqueue(position("zero"), 2_800_000_000, 0, 0, 2_800_000_000).unwrap();
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert_eq!(sent(&sender), vec![("zero".to_string(), Full)]);
+        assert_eq!(sender.flagged.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_positions_go_to_the_backstop_lp() {
+        let queue = Arc::new(LiquidationQueue::new());
+        queue.enqueue(position("big"), 2_800_000_000, 0, 200, 2_800_000_000).unwrap();
+        let sender = Arc::new(MockSender::new(vec![]));
+        let mut executor = LiquidationExecutor::new().with_sender(sender.clone());
+        executor.backstop_lp = Some(Pubkey::new_unique());
+        executor.backstop_notional_threshold = 2_800_000_000;
+
+        assert_eq!(executor.select_mode(&candidate("big", 200)), LiquidationMode::BackstopTakeover);
+        let mut small = candidate("small", 200);
+        small.position_value = 2_799_999_999;
+        assert_eq!(executor.select_mode(&small), LiquidationMode::Partial);
+
+        // The LP signs the takeover, so nothing is sent and it stays queued.
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert!(sent(&sender).is_empty());
+        assert_eq!(queue.snapshot_ids(), vec!["big".to_string()]);
+
+        // Once the LP has had its wait, the position is closed fully.
+        executor.backstop_wait = Duration::ZERO;
+        queue.hold_until("big", 0); // skips the cooldown
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert_eq!(sent(&sender), vec![("big".to_string(), LiquidationMode::Full)]);
+        assert!(queue.snapshot_ids().is_empty());
+
+        executor.backstop_lp = None;
+        assert_eq!(executor.select_mode(&candidate("big", 200)), LiquidationMode::Partial);
+    }
+
+    #[tokio::test]
+    async fn program_errors_decide_what_happens_to_the_candidate() {
+        use LiquidationMode::{Full, Partial};
+
+        let queue = Arc::new(LiquidationQueue::new());
+        let ids = ["healthy", "closed", "stale", "tiny", "overflow", "grace", "feed"];
+        for (i, id) in ids.into_iter().enumerate() {
+            queue.enqueue(position(id), 2_800_000_000, 100, 100 + i as u64, 1).unwrap();
+        }
+        let sender = Arc::new(MockSender::new(vec![
+            ("healthy", Partial, Some(ProgramErrorCode::PositionHealthy)),
+            ("closed", Partial, Some(ProgramErrorCode::PositionClosed)),
+            ("stale", Partial, Some(ProgramErrorCode::StaleOraclePrice)),
+            ("tiny", Partial, Some(ProgramErrorCode::InvalidLiquidationSize)),
+            ("overflow", Partial, Some(ProgramErrorCode::MathOverflow)),
+            ("grace", Partial, Some(ProgramErrorCode::AtRiskGracePeriodActive)),
+            ("feed", Partial, Some(ProgramErrorCode::PriceFeedMismatch)),
+        ]));
+        let executor = LiquidationExecutor::new().with_sender(sender.clone());
+
+        executor.drain_queue(queue.clone()).await.unwrap();
+        let modes: Vec<LiquidationMode> = sent(&sender).into_iter().map(|(_, m)| m).collect();
+        // The feed mismatch is re-resolved and retried once, right away.
+        assert_eq!(
+            modes,
+            vec![Partial, Partial, Partial, Partial, Full, Partial, Partial, Partial, Partial]
+        );
+
+        let mut left = queue.snapshot_ids();
+        left.sort();
+        assert_eq!(left, vec!["feed", "grace", "overflow", "stale"]);
+
+        let failed = executor.failure_log().list(&Default::default()).await.unwrap();
+        let codes: Vec<&str> = failed.iter().map(|f| f.attempt.error_code.as_str()).collect();
+        assert_eq!(
+            codes,
+            vec![
+                "PriceFeedMismatch",
+                "PriceFeedMismatch",
+                "AtRiskGracePeriodActive",
+                "MathOverflow",
+                "InvalidLiquidationSize",
+                "StaleOraclePrice",
+                "PositionClosed",
+                "PositionHealthy",
+            ]
+        );
+
+        // The stale one goes again as soon as the engine sees a newer price;
+        // the grace-blocked one is held until the window it hit ends.
+        queue.enqueue(position("stale"), 2_800_000_000, 101, 102, 1).unwrap();
+        executor.drain_queue(queue.clone()).await.unwrap();
+        assert_eq!(sent(&sender), vec![("stale".to_string(), Partial)]);
+        assert!(!queue.due().iter().any(|c| c.position.id == "grace"));
+    }
+
+    #[tokio::test]
+    async fn resolves_feed_and_funds_from_the_market() {
+        let (price_feed, insurance_fund) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (program_id, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let response = serde_json::json!({
+            "context": { "slot": 1 },
+            "value": [
+                ui_account(market_data(1, "ETH-PERP", price_feed)),
+                ui_account(market_insurance_fund_data(1, insurance_fund)),
+                null,
+                ui_account(token_account_data(mint, market_address(&program_id, 1))),
+            ],
+        });
+        let rpc = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(RpcRequest::GetMultipleAccounts, response)]),
+        );
+        let liquidator = Keypair::new();
+        let liquidator_key = liquidator.pubkey();
+        let sender = RpcLiquidationSender::new(rpc, liquidator, program_id);
+
+        let mut candidate = candidate("x", 100);
+        assert!(sender.resolve_accounts(&candidate).await.is_err());
+
+        let position = Pubkey::new_unique();
+        candidate.position.id = position.to_string();
+        let accounts = sender.resolve_accounts(&candidate).await.unwrap();
+        assert_eq!(accounts.position, position);
+        assert_eq!(accounts.price_feed, price_feed);
+        assert_eq!(accounts.insurance_fund, insurance_fund);
+        assert_eq!(accounts.market, market_address(&program_id, 1));
+        assert_eq!(accounts.liquidator_stats, None);
+        assert_eq!(accounts.collateral_vault, collateral_vault_address(&program_id, 1));
+        assert_eq!(
+            accounts.insurance_vault,
+            insurance_vault_address(&program_id, &insurance_fund)
+        );
+        assert_eq!(
+            accounts.liquidator_token_account,
+            get_associated_token_address(&liquidator_key, &mint)
+        );
+        assert_eq!(
+            accounts.owner_token_account,
+            get_associated_token_address(&candidate.position.owner, &mint)
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_the_grace_window_from_position_and_market() {
+        let response = serde_json::json!({
+            "context": { "slot": 1 },
+            "value": [
+                ui_account(position_data(1, 1_700_000_000)),
+                ui_account(market_data(1, "ETH-PERP", Pubkey::new_unique())),
+            ],
+        });
+        let rpc = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(RpcRequest::GetMultipleAccounts, response)]),
+        );
+        let sender = RpcLiquidationSender::new(rpc, Keypair::new(), Pubkey::new_unique());
+
+        let mut candidate = candidate("x", 100);
+        candidate.position.id = Pubkey::new_unique().to_string();
+        let window = sender.grace_window(&candidate).await.unwrap();
+        assert_eq!(
+            window,
+            GraceWindow {
+                at_risk_since: 1_700_000_000,
+                grace_secs: 60,
+            }
+        );
+    }
 }
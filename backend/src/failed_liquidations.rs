@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::db::{db_err, pool_from_env};
+use crate::errors::LiquidationError;
+use crate::oracle::unix_now;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1_000;
+// Attempts kept by the in-memory log; older ones are dropped.
+const MEMORY_CAPACITY: usize = 1_000;
+
+// One failed or timed-out `liquidate_*` attempt, as the executor saw it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedAttempt {
+    pub position_id: String,
+    pub liquidator: String,
+    pub symbol: String,
+    pub margin_ratio_bps: Option<u64>,
+    pub error_code: String, // `LiquidationError::code`, e.g. "StaleOraclePrice" or "Timeout"
+    pub error_message: String,
+    pub attempt_tx: Option<String>, // signature of the transaction, if one was signed
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedLiquidation {
+    pub id: i64,
+    #[serde(flatten)]
+    pub attempt: FailedAttempt,
+    pub occurred_at: i64, // unix secs
+}
+
+// Filters for `GET /liquidations/failed`; unset ones match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FailedLiquidationFilter {
+    pub position_id: Option<String>,
+    pub liquidator: Option<String>,
+    pub symbol: Option<String>,
+    pub error_code: Option<String>,
+    pub since: Option<i64>, // unix secs, inclusive
+    pub limit: Option<i64>, // default 100, at most 1000
+}
+
+impl FailedLiquidationFilter {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    fn matches(&self, failure: &FailedLiquidation) -> bool {
+        let attempt = &failure.attempt;
+        let eq =
+            |filter: &Option<String>, value: &str| filter.as_deref().is_none_or(|f| f == value);
+        eq(&self.position_id, &attempt.position_id)
+            && eq(&self.liquidator, &attempt.liquidator)
+            && eq(&self.symbol, &attempt.symbol)
+            && eq(&self.error_code, &attempt.error_code)
+            && self.since.is_none_or(|since| failure.occurred_at >= since)
+    }
+}
+
+// Where the executor records failed attempts for ops to investigate.
+#[async_trait]
+pub trait FailedLiquidationLog: Send + Sync {
+    async fn record(&self, attempt: &FailedAttempt) -> Result<(), LiquidationError>;
+
+    // Newest first.
+    async fn list(
+        &self,
+        filter: &FailedLiquidationFilter,
+    ) -> Result<Vec<FailedLiquidation>, LiquidationError>;
+}
+
+// `failed_liquidations` in Postgres when DATABASE_URL is set; otherwise the
+// most recent attempts in memory.
+pub fn log_from_env() -> Result<Arc<dyn FailedLiquidationLog>, LiquidationError> {
+    Ok(match pool_from_env()? {
+        Some(pool) => Arc::new(PostgresFailedLiquidationLog::new(pool)),
+        None => Arc::new(MemoryFailedLiquidationLog::new()),
+    })
+}
+
+// ------------- IN-MEMORY LOG -------------
+
+#[derive(Default)]
+pub struct MemoryFailedLiquidationLog {
+    // (last id handed out, oldest first)
+    entries: Mutex<(i64, VecDeque<FailedLiquidation>)>,
+}
+
+impl MemoryFailedLiquidationLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl FailedLiquidationLog for MemoryFailedLiquidationLog {
+    async fn record(&self, attempt: &FailedAttempt) -> Result<(), LiquidationError> {
+        let mut guard = self.entries.lock().unwrap();
+        let (last_id, entries) = &mut *guard;
+        *last_id += 1;
+        entries.push_back(FailedLiquidation {
+            id: *last_id,
+            attempt: attempt.clone(),
+            occurred_at: unix_now(),
+        });
+        if entries.len() > MEMORY_CAPACITY {
+            entries.pop_front();
+        }
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        filter: &FailedLiquidationFilter,
+    ) -> Result<Vec<FailedLiquidation>, LiquidationError> {
+        let guard = self.entries.lock().unwrap();
+        Ok(guard
+            .1
+            .iter()
+            .rev()
+            .filter(|failure| filter.matches(failure))
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+// ------------- POSTGRES LOG -------------
+
+const INSERT_SQL: &str = "
+    INSERT INTO failed_liquidations
+        (position_id, liquidator, symbol, margin_ratio, error_code, error_message, attempt_tx)
+    VALUES ($1, $2, $3, $4::BIGINT / 10000.0, $5, $6, $7)";
+
+const SELECT_SQL: &str = "
+    SELECT id, position_id, liquidator, symbol,
+        (margin_ratio * 10000)::BIGINT AS margin_ratio_bps,
+        COALESCE(error_code, '') AS error_code, error_message, attempt_tx,
+        EXTRACT(EPOCH FROM occurred_at)::BIGINT AS occurred_at
+    FROM failed_liquidations
+    WHERE ($1::TEXT IS NULL OR position_id = $1)
+        AND ($2::TEXT IS NULL OR liquidator = $2)
+        AND ($3::TEXT IS NULL OR symbol = $3)
+        AND ($4::TEXT IS NULL OR error_code = $4)
+        AND ($5::BIGINT IS NULL OR occurred_at >= TO_TIMESTAMP($5))
+    ORDER BY occurred_at DESC, id DESC
+    LIMIT $6";
+
+// `failed_liquidations` table (db/migrations/0001_init.sql, 0004_failed_liquidations.sql).
+pub struct PostgresFailedLiquidationLog {
+    pool: Pool,
+}
+
+impl PostgresFailedLiquidationLog {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl FailedLiquidationLog for PostgresFailedLiquidationLog {
+    async fn record(&self, attempt: &FailedAttempt) -> Result<(), LiquidationError> {
+        let client = self.pool.get().await.map_err(db_err)?;
+        client
+            .execute(
+                INSERT_SQL,
+                &[
+                    &attempt.position_id,
+                    &attempt.liquidator,
+                    &attempt.symbol,
+                    &attempt.margin_ratio_bps.map(|bps| bps as i64),
+                    &attempt.error_code,
+                    &attempt.error_message,
+                    &attempt.attempt_tx,
+                ],
+            )
+            .await
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        filter: &FailedLiquidationFilter,
+    ) -> Result<Vec<FailedLiquidation>, LiquidationError> {
+        let client = self.pool.get().await.map_err(db_err)?;
+        let rows = client
+            .query(
+                SELECT_SQL,
+                &[
+                    &filter.position_id,
+                    &filter.liquidator,
+                    &filter.symbol,
+                    &filter.error_code,
+                    &filter.since,
+                    &filter.limit(),
+                ],
+            )
+            .await
+            .map_err(db_err)?;
+        rows.iter().map(failure_from_row).collect()
+    }
+}
+
+fn failure_from_row(row: &Row) -> Result<FailedLiquidation, LiquidationError> {
+    let margin_ratio_bps: Option<i64> = row.try_get("margin_ratio_bps").map_err(db_err)?;
+
+    Ok(FailedLiquidation {
+        id: row.try_get("id").map_err(db_err)?,
+        attempt: FailedAttempt {
+            position_id: row.try_get("position_id").map_err(db_err)?,
+            liquidator: row.try_get("liquidator").map_err(db_err)?,
+            symbol: row.try_get("symbol").map_err(db_err)?,
+            margin_ratio_bps: margin_ratio_bps.map(|bps| bps.max(0) as u64),
+            error_code: row.try_get("error_code").map_err(db_err)?,
+            error_message: row.try_get("error_message").map_err(db_err)?,
+            attempt_tx: row.try_get("attempt_tx").map_err(db_err)?,
+        },
+        occurred_at: row.try_get("occurred_at").map_err(db_err)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(position_id: &str, error_code: &str) -> FailedAttempt {
+        FailedAttempt {
+            position_id: position_id.to_string(),
+            liquidator: "liquidator".to_string(),
+            symbol: "ETH-PERP".to_string(),
+            margin_ratio_bps: Some(120),
+            error_code: error_code.to_string(),
+            error_message: "failed".to_string(),
+            attempt_tx: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_log_filters_newest_first() {
+        let log = MemoryFailedLiquidationLog::new();
+        log.record(&attempt("a", "Timeout")).await.unwrap();
+        log.record(&attempt("b", "StaleOraclePrice")).await.unwrap();
+        log.record(&attempt("a", "StaleOraclePrice")).await.unwrap();
+
+        let all = log.list(&FailedLiquidationFilter::default()).await.unwrap();
+        assert_eq!(all.iter().map(|f| f.id).collect::<Vec<_>>(), vec![3, 2, 1]);
+
+        let filter = FailedLiquidationFilter {
+            position_id: Some("a".to_string()),
+            error_code: Some("StaleOraclePrice".to_string()),
+            ..FailedLiquidationFilter::default()
+        };
+        let matched = log.list(&filter).await.unwrap();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].id, 3);
+
+        let filter = FailedLiquidationFilter {
+            limit: Some(1),
+            since: Some(unix_now() + 60),
+            ..FailedLiquidationFilter::default()
+        };
+        assert!(log.list(&filter).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use anchor_lang::{InstructionData, ToAccountMetas};
+use liquidation_engine::{accounts, instruction};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+// Instruction builders for the `liquidation_engine` program. Accounts and
+// data come from the program's own Anchor types, so account order and
+// encoding can't drift from its `#[derive(Accounts)]` structs.
+
+// `update_price_feed(price)`, signed by the market's insurance fund authority.
+pub fn update_price_feed(
+    program_id: &Pubkey,
+    price_feed: &Pubkey,
+    market: &Pubkey,
+    insurance_fund: &Pubkey,
+    authority: &Pubkey,
+    price: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::UpdatePriceFeed {
+            price_feed: *price_feed,
+            market: *market,
+            insurance_fund: *insurance_fund,
+            authority: *authority,
+        }
+        .to_account_metas(None),
+        data: instruction::UpdatePriceFeed { price }.data(),
+    }
+}
+
+// `flag_at_risk()`: permissionless; starts the position's grace window
+// once it is below the market's warning threshold.
+pub fn flag_at_risk(
+    program_id: &Pubkey,
+    position: &Pubkey,
+    price_feed: &Pubkey,
+    market: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::UpdateAtRisk {
+            position: *position,
+            price_feed: *price_feed,
+            market: *market,
+        }
+        .to_account_metas(None),
+        data: instruction::FlagAtRisk {}.data(),
+    }
+}
+
+// Accounts shared by `liquidate_partial` and `liquidate_full`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiquidationAccounts {
+    pub position: Pubkey,
+    pub liquidator: Pubkey,
+    pub price_feed: Pubkey, // must be the market's bound feed
+    pub insurance_fund: Pubkey,
+    pub market_insurance_fund: Pubkey,
+    pub market: Pubkey,
+    pub liquidator_stats: Option<Pubkey>, // only once `register_liquidator` ran
+    pub collateral_vault: Pubkey,
+    // Bad debt coverage comes out of these.
+    pub market_insurance_vault: Pubkey,
+    pub insurance_vault: Pubkey,
+    pub insurance_vault_authority: Pubkey,
+    pub liquidator_token_account: Pubkey, // receives the reward
+    pub owner_token_account: Pubkey,      // receives the equity left by a full close
+}
+
+// `liquidate_partial(liquidation_size)`; the program caps the size at half
+// the position.
+pub fn liquidate_partial(
+    program_id: &Pubkey,
+    keys: &LiquidationAccounts,
+    liquidation_size: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::LiquidatePartial {
+            position: keys.position,
+            liquidator: keys.liquidator,
+            price_feed: keys.price_feed,
+            insurance_fund: keys.insurance_fund,
+            market_insurance_fund: keys.market_insurance_fund,
+            market: keys.market,
+            liquidator_stats: keys.liquidator_stats,
+            collateral_vault: keys.collateral_vault,
+            market_insurance_vault: keys.market_insurance_vault,
+            insurance_vault: keys.insurance_vault,
+            insurance_vault_authority: keys.insurance_vault_authority,
+            liquidator_token_account: keys.liquidator_token_account,
+            owner_token_account: keys.owner_token_account,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::LiquidatePartial { liquidation_size }.data(),
+    }
+}
+
+// `liquidate_full()`: closes the position, bad debt going to the insurance funds.
+pub fn liquidate_full(program_id: &Pubkey, keys: &LiquidationAccounts) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::LiquidateFull {
+            position: keys.position,
+            liquidator: keys.liquidator,
+            price_feed: keys.price_feed,
+            insurance_fund: keys.insurance_fund,
+            market_insurance_fund: keys.market_insurance_fund,
+            market: keys.market,
+            liquidator_stats: keys.liquidator_stats,
+            collateral_vault: keys.collateral_vault,
+            market_insurance_vault: keys.market_insurance_vault,
+            insurance_vault: keys.insurance_vault,
+            insurance_vault_authority: keys.insurance_vault_authority,
+            liquidator_token_account: keys.liquidator_token_account,
+            owner_token_account: keys.owner_token_account,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::LiquidateFull {}.data(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn omitted_liquidator_stats_take_the_program_id_slot() {
+        let accounts = LiquidationAccounts {
+            position: Pubkey::new_unique(),
+            liquidator: Pubkey::new_unique(),
+            price_feed: Pubkey::new_unique(),
+            insurance_fund: Pubkey::new_unique(),
+            market_insurance_fund: Pubkey::new_unique(),
+            market: Pubkey::new_unique(),
+            liquidator_stats: None,
+            collateral_vault: Pubkey::new_unique(),
+            market_insurance_vault: Pubkey::new_unique(),
+            insurance_vault: Pubkey::new_unique(),
+            insurance_vault_authority: Pubkey::new_unique(),
+            liquidator_token_account: Pubkey::new_unique(),
+            owner_token_account: Pubkey::new_unique(),
+        };
+        let ix = liquidate_full(&liquidation_engine::ID, &accounts);
+
+        let stats = ix.accounts.iter().find(|m| m.pubkey == liquidation_engine::ID).unwrap();
+        assert_eq!(stats.pubkey, liquidation_engine::ID);
+        assert!(!stats.is_writable);
+        assert!(ix.accounts.iter().any(|m| m.pubkey == accounts.liquidator && m.is_signer));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::LiquidationError;
 
+#[derive(Default)]
 pub struct InsuranceFundManager {}
 
 impl InsuranceFundManager {
@@ -0,0 +1,17 @@
+pub mod accounts;
+pub mod instructions;
+pub mod engine;
+pub mod queue;
+pub mod executor;
+pub mod oracle;
+pub mod position_manager;
+pub mod position_loader;
+pub mod position_cache;
+pub mod risk;
+pub mod insurance;
+pub mod liquidator_stats;
+pub mod failed_liquidations;
+pub mod price_pusher;
+pub mod db;
+pub mod models;
+pub mod errors;
@@ -0,0 +1,131 @@
+use std::str::FromStr;
+
+use anchor_lang::{Discriminator, Space};
+use deadpool_postgres::Pool;
+use liquidation_engine::LiquidatorStats;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+use tokio::time::{interval, Duration};
+
+use crate::accounts::{decode_liquidator_stats, LiquidatorStatsAccount, PROGRAM_ID};
+use crate::db::{db_err, pool_from_env};
+use crate::errors::LiquidationError;
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_SYNC_INTERVAL_SECS: u64 = 60;
+
+// On-chain totals are authoritative; the row is overwritten, not accumulated.
+const UPSERT_SQL: &str = "
+    INSERT INTO liquidator_stats
+        (liquidator, total_liquidations, total_reward, last_liquidation_slot)
+    VALUES ($1, $2, $3::BIGINT / 1000000.0, $4)
+    ON CONFLICT (liquidator) DO UPDATE SET
+        total_liquidations = EXCLUDED.total_liquidations,
+        total_reward = EXCLUDED.total_reward,
+        last_liquidation_slot = EXCLUDED.last_liquidation_slot";
+
+// Mirrors every `LiquidatorStats` PDA into the `liquidator_stats` table.
+pub struct LiquidatorStatsSync {
+    rpc: RpcClient,
+    pool: Pool,
+    program_id: Pubkey,
+    sync_interval: Duration,
+}
+
+impl LiquidatorStatsSync {
+    // DATABASE_URL (required), RPC_URL, LIQUIDATION_PROGRAM_ID, STATS_SYNC_INTERVAL_SECS.
+    // Returns `None` when no database is configured.
+    pub fn from_env() -> Result<Option<Self>, LiquidationError> {
+        let Some(pool) = pool_from_env()? else {
+            return Ok(None);
+        };
+
+        let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+        let program_id = std::env::var("LIQUIDATION_PROGRAM_ID")
+            .unwrap_or_else(|_| PROGRAM_ID.to_string());
+        let program_id = Pubkey::from_str(&program_id)
+            .map_err(|e| LiquidationError::Other(format!("invalid program id: {e}")))?;
+        let sync_interval = std::env::var("STATS_SYNC_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SYNC_INTERVAL_SECS);
+
+        Ok(Some(Self {
+            rpc: RpcClient::new(rpc_url),
+            pool,
+            program_id,
+            sync_interval: Duration::from_secs(sync_interval),
+        }))
+    }
+
+    pub async fn start(&self) {
+        let mut timer = interval(self.sync_interval);
+
+        loop {
+            timer.tick().await;
+            if let Err(e) = self.sync_once().await {
+                eprintln!("[STATS] Error syncing liquidator stats: {e:?}");
+            }
+        }
+    }
+
+    pub async fn sync_once(&self) -> Result<usize, LiquidationError> {
+        let stats = self.fetch_stats().await?;
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(db_err)?;
+        let statement = client
+            .prepare(UPSERT_SQL)
+            .await
+            .map_err(db_err)?;
+
+        for entry in &stats {
+            client
+                .execute(
+                    &statement,
+                    &[
+                        &entry.liquidator.to_string(),
+                        &(entry.liquidation_count as i64),
+                        &(entry.total_reward as i64),
+                        &(entry.last_liquidation_slot as i64),
+                    ],
+                )
+                .await
+                .map_err(db_err)?;
+        }
+
+        Ok(stats.len())
+    }
+
+    async fn fetch_stats(&self) -> Result<Vec<LiquidatorStatsAccount>, LiquidationError> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize((8 + LiquidatorStats::INIT_SPACE) as u64),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                    0,
+                    LiquidatorStats::DISCRIMINATOR.to_vec(),
+                )),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            with_context: None,
+        };
+
+        let accounts = self
+            .rpc
+            .get_program_accounts_with_config(&self.program_id, config)
+            .await?;
+
+        accounts
+            .iter()
+            .map(|(_, account)| decode_liquidator_stats(&account.data))
+            .collect()
+    }
+}
@@ -1,21 +1,28 @@
-mod engine;
-mod queue;
-mod executor;
-mod oracle;
-mod position_manager;
-mod insurance;
-mod models;
-mod errors;
-
-use engine::LiquidationEngine;
+use backend::engine::LiquidationEngine;
 use tracing_subscriber::EnvFilter;
 
-use axum::{extract::State, routing::get, Json, Router};
-use serde::Serialize;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use crate::queue::LiquidationQueue;
+use backend::errors::LiquidationError;
+use backend::failed_liquidations::{
+    FailedLiquidation, FailedLiquidationFilter, FailedLiquidationLog,
+};
+use backend::liquidator_stats::LiquidatorStatsSync;
+use backend::models::{self, pubkey_string, Position};
+use backend::oracle::{self, StaticPriceOracle};
+use backend::position_manager::PositionManager;
+use backend::price_pusher::{FeedHeartbeat, PricePusher};
+use backend::queue::LiquidationQueue;
+use backend::risk::{position_risk, PositionRisk};
 
 #[derive(Serialize)]
 struct HealthResponse {
@@ -27,9 +34,36 @@ struct PendingLiquidationsResponse {
     positions: Vec<String>, // IDs of at-risk positions
 }
 
+// Body of `PUT /positions/:id`; amounts in the program's raw fixed-point units.
+#[derive(Deserialize)]
+struct UpsertPositionRequest {
+    #[serde(with = "pubkey_string")]
+    owner: Pubkey,
+    market_index: u16,
+    size: u64,
+    is_long: bool,
+    entry_price: u64,
+    collateral: u64,
+    leverage: u16,
+}
+
+#[derive(Serialize)]
+struct FailedLiquidationsResponse {
+    failures: Vec<FailedLiquidation>, // newest first
+}
+
+#[derive(Serialize)]
+struct PricePusherResponse {
+    enabled: bool,
+    feeds: Vec<FeedHeartbeat>,
+}
+
 #[derive(Clone)]
 struct AppState {
     queue: Arc<LiquidationQueue>,
+    position_manager: Arc<PositionManager>,
+    price_pusher: Option<Arc<PricePusher>>,
+    failed_liquidations: Arc<dyn FailedLiquidationLog>,
 }
 
 #[tokio::main]
@@ -41,21 +75,97 @@ async fn main() {
 
     println!("Starting liquidation backend...");
 
+    // market decimals and minimum notionals come from the on-chain Market accounts
+    match models::load_markets_from_env().await {
+        Ok(count) => println!("Loaded {count} of {} markets from chain", models::MARKETS.len()),
+        Err(e) => eprintln!("Market accounts unavailable, positions cannot be priced: {e}"),
+    }
+
+    // on-chain, Postgres or in-memory demo positions (see PositionManager::from_env)
+    let position_manager = match PositionManager::from_env() {
+        Ok(position_manager) => position_manager,
+        Err(e) => {
+            eprintln!("Position source unavailable, using in-memory positions: {e}");
+            PositionManager::new()
+        }
+    };
+
+    // Pyth, Switchboard, on-chain PriceFeed or static prices (see oracle::from_env)
+    let oracle = match oracle::from_env() {
+        Ok(oracle) => oracle,
+        Err(e) => {
+            eprintln!("Oracle unavailable, using static prices: {e}");
+            Arc::new(StaticPriceOracle::default())
+        }
+    };
+    println!("Using {} oracle", oracle.name());
+
+    // keep on-chain PriceFeeds fresh, if this instance holds the markets' admin key
+    let pusher_oracle = match std::env::var("PRICE_PUSHER_SOURCE") {
+        Ok(source) => oracle::named_from_env(&source),
+        Err(_) => Ok(oracle.clone()),
+    };
+    let price_pusher = match pusher_oracle.and_then(PricePusher::from_env) {
+        Ok(Some(pusher)) => {
+            let pusher = Arc::new(pusher);
+            tokio::spawn(pusher.clone().start());
+            Some(pusher)
+        }
+        Ok(None) => {
+            println!("PRICE_PUSHER_KEYPAIR not set; price pusher disabled");
+            None
+        }
+        Err(e) => {
+            eprintln!("Price pusher disabled: {e}");
+            None
+        }
+    };
+
     // create engine
-    let engine = LiquidationEngine::new();
+    let engine =
+        LiquidationEngine::with_position_manager(Arc::new(position_manager)).with_oracle(oracle);
     let queue = engine.queue.clone();
+    let position_manager = engine.position_manager.clone();
+    let failed_liquidations = engine.executor.failure_log();
 
     // spawn engine loop in background
     tokio::spawn(async move {
         engine.start().await;
     });
 
+    // mirror on-chain liquidator stats into Postgres, if configured
+    match LiquidatorStatsSync::from_env() {
+        Ok(Some(stats_sync)) => {
+            tokio::spawn(async move {
+                stats_sync.start().await;
+            });
+        }
+        Ok(None) => println!("DATABASE_URL not set; liquidator stats sync disabled"),
+        Err(e) => eprintln!("Liquidator stats sync disabled: {e}"),
+    }
+
     // build HTTP API router with shared state
-    let state = AppState { queue };
+    let state = AppState {
+        queue,
+        position_manager,
+        price_pusher,
+        failed_liquidations,
+    };
 
     let app = Router::new()
         .route("/health", get(health_handler))
         .route("/liquidations/pending", get(pending_liquidations_handler))
+        .route("/liquidations/failed", get(failed_liquidations_handler))
+        .route("/oracle/pusher", get(price_pusher_handler))
+        .route(
+            "/positions/:id",
+            get(get_position_handler).put(upsert_position_handler),
+        )
+        .route("/positions/:id/close", post(close_position_handler))
+        .route(
+            "/positions/:id/liquidation-price",
+            get(liquidation_price_handler),
+        )
         .with_state(state);
 
     // bind server
@@ -80,3 +190,93 @@ async fn pending_liquidations_handler(
     })
 }
 
+async fn failed_liquidations_handler(
+    State(state): State<AppState>,
+    Query(filter): Query<FailedLiquidationFilter>,
+) -> Result<Json<FailedLiquidationsResponse>, (StatusCode, String)> {
+    state
+        .failed_liquidations
+        .list(&filter)
+        .await
+        .map(|failures| Json(FailedLiquidationsResponse { failures }))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn price_pusher_handler(State(state): State<AppState>) -> Json<PricePusherResponse> {
+    Json(PricePusherResponse {
+        enabled: state.price_pusher.is_some(),
+        feeds: state
+            .price_pusher
+            .as_ref()
+            .map(|pusher| pusher.heartbeats())
+            .unwrap_or_default(),
+    })
+}
+
+async fn liquidation_price_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PositionRisk>, (StatusCode, String)> {
+    let position = find_open_position(&state, &id).await?;
+
+    position_risk(&position)
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
+
+async fn get_position_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Position>, (StatusCode, String)> {
+    find_open_position(&state, &id).await.map(Json)
+}
+
+async fn upsert_position_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(body): Json<UpsertPositionRequest>,
+) -> Result<Json<Position>, (StatusCode, String)> {
+    let position = Position {
+        id,
+        owner: body.owner,
+        market_index: body.market_index,
+        size: body.size,
+        is_long: body.is_long,
+        entry_price: body.entry_price,
+        collateral: body.collateral,
+        leverage: body.leverage,
+    };
+
+    match state.position_manager.upsert_position(&position).await {
+        Ok(true) => Ok(Json(position)),
+        Ok(false) => Err((
+            StatusCode::CONFLICT,
+            format!("position {} is closed", position.id),
+        )),
+        Err(e @ LiquidationError::Decode(_)) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn close_position_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state.position_manager.close_position(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, format!("position {id} not found"))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn find_open_position(
+    state: &AppState,
+    id: &str,
+) -> Result<Position, (StatusCode, String)> {
+    state
+        .position_manager
+        .get_position(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("position {id} not found")))
+}
@@ -1,21 +1,357 @@
+use std::str::FromStr;
+use std::sync::RwLock;
+
 use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::accounts::{decode_market, market_address, MarketAccount, PositionAccount, PROGRAM_ID};
+use crate::errors::LiquidationError;
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub id: String,
+    #[serde(with = "pubkey_string")]
     pub owner: Pubkey,
-    pub symbol: String,
-    pub size: f64,
+    pub market_index: u16,
+    // Raw on-chain fixed point: size in base units (10^base_decimals),
+    // entry price in 10^price_decimals, collateral in 10^QUOTE_DECIMALS.
+    pub size: u64,
     pub is_long: bool,
-    pub entry_price: f64,
-    pub collateral: f64,
+    pub entry_price: u64,
+    pub collateral: u64,
     pub leverage: u16,
 }
 
+impl Position {
+    pub fn symbol(&self) -> Option<&'static str> {
+        market_symbol(self.market_index)
+    }
+
+    // Rejects positions on markets this backend does not know the decimals of.
+    pub fn from_account(id: String, account: &PositionAccount) -> Result<Self, LiquidationError> {
+        market_info(account.market_index).ok_or_else(|| {
+            LiquidationError::Decode(format!("unknown market index {}", account.market_index))
+        })?;
+
+        Ok(Position {
+            id,
+            owner: account.owner,
+            market_index: account.market_index,
+            size: account.size,
+            is_long: account.is_long,
+            entry_price: account.entry_price,
+            collateral: account.collateral,
+            leverage: account.leverage,
+        })
+    }
+
+    // For calling the program's pure helpers.
+    pub fn to_onchain(&self) -> liquidation_engine::Position {
+        liquidation_engine::Position {
+            owner: self.owner,
+            size: self.size,
+            entry_price: self.entry_price,
+            collateral: self.collateral,
+            market_index: self.market_index,
+            leverage: self.leverage,
+            is_long: self.is_long as u8,
+            closed: 0,
+            version: liquidation_engine::Position::VERSION,
+            _padding: [0; 1],
+            at_risk_since: 0,
+            _reserved: [0; 56],
+        }
+    }
+
+    // (margin ratio bps, position value in quote * 1e6) at `mark_price`,
+    // computed exactly as the program's `liquidate_*` instructions do.
+    pub fn margin_ratio(&self, mark_price: u64) -> Result<(u64, u128), LiquidationError> {
+        let value_scale = self.market()?.value_scale()?;
+        liquidation_math::compute_margin_ratio(
+            self.size,
+            self.entry_price,
+            self.collateral,
+            self.is_long,
+            mark_price,
+            value_scale,
+        )
+        .ok_or_else(|| LiquidationError::Other("margin ratio overflow".to_string()))
+    }
+
+    pub fn market(&self) -> Result<MarketInfo, LiquidationError> {
+        market_info(self.market_index).ok_or_else(|| {
+            LiquidationError::Decode(format!("unknown market index {}", self.market_index))
+        })
+    }
+}
+
+pub use liquidation_math::QUOTE_DECIMALS;
+
+// A market this backend serves. Its parameters live in the on-chain `Market`
+// account (PDA `["market", market_index]`) and are read by `load_markets`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketId {
+    pub market_index: u16,
+    pub symbol: &'static str,
+}
+
+pub const MARKETS: &[MarketId] = &[
+    MarketId {
+        market_index: 0,
+        symbol: "BTC-PERP",
+    },
+    MarketId {
+        market_index: 1,
+        symbol: "ETH-PERP",
+    },
+    MarketId {
+        market_index: 2,
+        symbol: "SOL-PERP",
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketInfo {
+    pub market_index: u16,
+    pub symbol: &'static str,
+    pub price_decimals: u8,
+    pub base_decimals: u8,
+    // Quote notional (10^QUOTE_DECIMALS) below which a position costs more to
+    // liquidate than it pays; `Market.min_position_notional`.
+    pub min_position_notional: u64,
+}
+
+// Parameters of the MARKETS whose `Market` account has been loaded.
+static MARKET_INFO: RwLock<Vec<MarketInfo>> = RwLock::new(Vec::new());
+
+// None until the market's account has been loaded, so positions on it are
+// rejected rather than priced with guessed decimals.
+pub fn market_info(market_index: u16) -> Option<MarketInfo> {
+    MARKET_INFO
+        .read()
+        .unwrap()
+        .iter()
+        .find(|m| m.market_index == market_index)
+        .copied()
+}
+
+pub fn set_market_info(info: MarketInfo) {
+    let mut markets = MARKET_INFO.write().unwrap();
+    markets.retain(|m| m.market_index != info.market_index);
+    markets.push(info);
+}
+
+pub fn market_symbol(market_index: u16) -> Option<&'static str> {
+    MARKETS
+        .iter()
+        .find(|m| m.market_index == market_index)
+        .map(|m| m.symbol)
+}
+
+pub fn market_index_for_symbol(symbol: &str) -> Option<u16> {
+    MARKETS
+        .iter()
+        .find(|m| m.symbol == symbol)
+        .map(|m| m.market_index)
+}
+
+// Reads every MARKETS entry's `Market` account and records its parameters.
+// Returns how many were found; missing ones stay unknown.
+pub async fn load_markets(rpc: &RpcClient, program_id: &Pubkey) -> Result<usize, LiquidationError> {
+    let addresses: Vec<Pubkey> = MARKETS
+        .iter()
+        .map(|m| market_address(program_id, m.market_index))
+        .collect();
+    let accounts = rpc.get_multiple_accounts(&addresses).await?;
+
+    let mut loaded = 0;
+    for account in accounts.into_iter().flatten() {
+        set_market_info(MarketInfo::from_account(&decode_market(&account.data)?)?);
+        loaded += 1;
+    }
+    Ok(loaded)
+}
+
+// RPC_URL, LIQUIDATION_PROGRAM_ID.
+pub async fn load_markets_from_env() -> Result<usize, LiquidationError> {
+    let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+    let program_id =
+        std::env::var("LIQUIDATION_PROGRAM_ID").unwrap_or_else(|_| PROGRAM_ID.to_string());
+    let program_id = Pubkey::from_str(&program_id)
+        .map_err(|e| LiquidationError::Other(format!("invalid program id: {e}")))?;
+    load_markets(&RpcClient::new(rpc_url), &program_id).await
+}
+
+impl MarketInfo {
+    // The account must be one of MARKETS, under the same symbol.
+    pub fn from_account(account: &MarketAccount) -> Result<Self, LiquidationError> {
+        let symbol = market_symbol(account.market_index)
+            .filter(|symbol| *symbol == account.symbol)
+            .ok_or_else(|| {
+                LiquidationError::Decode(format!(
+                    "unexpected market {} ({})",
+                    account.market_index, account.symbol
+                ))
+            })?;
+
+        Ok(MarketInfo {
+            market_index: account.market_index,
+            symbol,
+            price_decimals: account.price_decimals,
+            base_decimals: account.base_decimals,
+            min_position_notional: account.min_position_notional,
+        })
+    }
+
+    // See `liquidation_math::value_scale_for`.
+    pub fn value_scale(&self) -> Result<u128, LiquidationError> {
+        liquidation_math::value_scale_for(self.price_decimals, self.base_decimals).ok_or_else(|| {
+            LiquidationError::Other(format!("invalid market decimals for {}", self.symbol))
+        })
+    }
+}
+
+// Registers MARKETS with the parameters the test `Market` accounts
+// (`accounts::market_data`) give them.
+#[cfg(test)]
+pub(crate) fn load_test_markets() {
+    for m in MARKETS {
+        let data = crate::accounts::market_data(m.market_index, m.symbol, Pubkey::new_unique());
+        set_market_info(MarketInfo::from_account(&decode_market(&data).unwrap()).unwrap());
+    }
+}
+
+// Float conversions are only for display and for external price sources;
+// margin checks stay in fixed point.
+pub fn scale_down(raw: u64, decimals: u8) -> f64 {
+    raw as f64 / 10f64.powi(decimals as i32)
+}
+
+pub fn scale_up(value: f64, decimals: u8) -> u64 {
+    (value * 10f64.powi(decimals as i32)).round() as u64
+}
+
+// Incremental update to the open-position book, published by the position
+// writer so readers need not reload everything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PositionChange {
+    Upserted { position: Position },
+    Closed { id: String },
+}
+
+// Base58 in JSON instead of serde's default 32-number array.
+pub mod pubkey_string {
+    use std::str::FromStr;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use solana_sdk::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Pubkey::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LiquidationCandidate {
     pub position: Position,
-    pub mark_price: f64,
-    pub margin_ratio: f64,
+    pub mark_price: u64,       // 10^price_decimals
+    pub price_time: i64,       // publish time of mark_price, unix secs
+    pub margin_ratio_bps: u64,
+    pub position_value: u128,  // quote * 1e6
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LiquidationMode {
+    Partial,
+    Full,
+    // Position handed to a registered backstop LP (`liquidate_backstop`).
+    BackstopTakeover,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::{UiAccount, UiAccountEncoding};
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::account::Account;
+    use std::collections::HashMap;
+
+    use crate::accounts::market_data;
+
+    fn ui_account(data: Vec<u8>) -> UiAccount {
+        let account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        UiAccount::encode(&Pubkey::new_unique(), &account, UiAccountEncoding::Base64, None, None)
+    }
+
+    #[tokio::test]
+    async fn loads_market_parameters_from_chain() {
+        let markets = serde_json::json!({
+            "context": { "slot": 1 },
+            "value": [
+                ui_account(market_data(0, "BTC-PERP", Pubkey::new_unique())),
+                ui_account(market_data(1, "ETH-PERP", Pubkey::new_unique())),
+                null,
+            ],
+        });
+        let rpc = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(RpcRequest::GetMultipleAccounts, markets)]),
+        );
+
+        assert_eq!(load_markets(&rpc, &Pubkey::new_unique()).await.unwrap(), 2);
+        let btc = market_info(0).unwrap();
+        assert_eq!(btc.symbol, "BTC-PERP");
+        assert_eq!(btc.base_decimals, 8);
+        assert_eq!(btc.min_position_notional, 10_000_000);
+
+        // A Market account under another symbol is not silently adopted
+        let renamed = decode_market(&market_data(1, "DOGE-PERP", Pubkey::new_unique())).unwrap();
+        assert!(MarketInfo::from_account(&renamed).is_err());
+    }
+
+    #[test]
+    fn from_account_keeps_fixed_point_units() {
+        load_test_markets();
+        let account = PositionAccount {
+            owner: Pubkey::new_unique(),
+            market_index: 0,
+            size: 50_000_000, // 0.5 BTC in sats
+            entry_price: 60_000_000_000,
+            collateral: 1_500_000_000,
+            leverage: 20,
+            is_long: true,
+            closed: false,
+            version: liquidation_engine::Position::VERSION,
+            at_risk_since: 0,
+        };
+
+        let position = Position::from_account("p1".to_string(), &account).unwrap();
+        assert_eq!(position.size, 50_000_000);
+        assert_eq!(position.entry_price, 60_000_000_000);
+        assert_eq!(position.collateral, 1_500_000_000);
+        assert_eq!(position.symbol(), Some("BTC-PERP"));
+
+        // 0.5 BTC at 60,000 -> 30,000 notional, 1,500 equity -> 5%
+        let (margin_ratio_bps, position_value) = position.margin_ratio(60_000_000_000).unwrap();
+        assert_eq!(position_value, 30_000_000_000);
+        assert_eq!(margin_ratio_bps, 500);
+
+        let unknown = PositionAccount { market_index: 99, ..account };
+        assert!(Position::from_account("p2".to_string(), &unknown).is_err());
+    }
 }
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+
+use liquidation_engine::MAX_ORACLE_STALENESS_SECS;
+use liquidation_math::BPS_DENOM;
+
+use crate::errors::LiquidationError;
+
+use super::{unix_now, OraclePrice, PriceOracle};
+
+const DEFAULT_MAX_DEVIATION_BPS: u64 = 100; // 1%
+
+// Median of several sources. A source counts only if it answered, is no
+// older than the program would accept, and sits within `max_deviation_bps`
+// of the median of the fresh answers. Fewer than `min_sources` such sources
+// is an error, so the engine skips that market rather than act on one feed.
+pub struct AggregatedOracle {
+    sources: Vec<Arc<dyn PriceOracle>>,
+    min_sources: usize,
+    max_deviation_bps: u64,
+    max_staleness_secs: i64,
+}
+
+impl AggregatedOracle {
+    // Defaults to a strict majority of `sources` and the program's staleness limit.
+    pub fn new(sources: Vec<Arc<dyn PriceOracle>>) -> Self {
+        let min_sources = sources.len() / 2 + 1;
+        Self {
+            sources,
+            min_sources,
+            max_deviation_bps: DEFAULT_MAX_DEVIATION_BPS,
+            max_staleness_secs: MAX_ORACLE_STALENESS_SECS,
+        }
+    }
+
+    pub fn with_min_sources(mut self, min_sources: usize) -> Self {
+        self.min_sources = min_sources.max(1);
+        self
+    }
+
+    pub fn with_max_deviation_bps(mut self, max_deviation_bps: u64) -> Self {
+        self.max_deviation_bps = max_deviation_bps;
+        self
+    }
+
+    pub fn with_max_staleness_secs(mut self, max_staleness_secs: i64) -> Self {
+        self.max_staleness_secs = max_staleness_secs;
+        self
+    }
+
+    fn aggregate(
+        &self,
+        symbol: &str,
+        fresh: Vec<OraclePrice>,
+    ) -> Result<OraclePrice, LiquidationError> {
+        let too_few = |agreeing: usize| {
+            LiquidationError::Oracle(format!(
+                "{agreeing} of {} oracle sources agree on {symbol}, need {}",
+                self.sources.len(),
+                self.min_sources,
+            ))
+        };
+        if fresh.len() < self.min_sources {
+            return Err(too_few(fresh.len()));
+        }
+
+        let reference = median(fresh.iter().map(|p| p.price).collect());
+        let agreeing: Vec<OraclePrice> = fresh
+            .into_iter()
+            .filter(|p| within_bps(p.price, reference, self.max_deviation_bps))
+            .collect();
+        if agreeing.len() < self.min_sources {
+            return Err(too_few(agreeing.len()));
+        }
+
+        // Reported as old as its oldest input, so downstream staleness checks
+        // stay conservative.
+        Ok(OraclePrice {
+            price: median(agreeing.iter().map(|p| p.price).collect()),
+            publish_time: agreeing.iter().map(|p| p.publish_time).min().unwrap_or_default(),
+        })
+    }
+}
+
+#[async_trait]
+impl PriceOracle for AggregatedOracle {
+    fn name(&self) -> &str {
+        "aggregate"
+    }
+
+    async fn get_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError> {
+        let answers = join_all(self.sources.iter().map(|source| source.get_price(symbol))).await;
+        let now = unix_now();
+
+        let mut fresh = Vec::with_capacity(answers.len());
+        for (source, answer) in self.sources.iter().zip(answers) {
+            match answer {
+                Ok(price) if now - price.publish_time <= self.max_staleness_secs => {
+                    fresh.push(price)
+                }
+                Ok(price) => eprintln!(
+                    "[ORACLE] Ignoring stale {} price for {symbol} ({}s old)",
+                    source.name(),
+                    now - price.publish_time,
+                ),
+                Err(e) => eprintln!("[ORACLE] {} failed for {symbol}: {e}", source.name()),
+            }
+        }
+        self.aggregate(symbol, fresh)
+    }
+}
+
+// Lower median for even counts keeps the result one of the reported prices.
+fn median(mut prices: Vec<u64>) -> u64 {
+    prices.sort_unstable();
+    prices.get(prices.len().saturating_sub(1) / 2).copied().unwrap_or_default()
+}
+
+fn within_bps(price: u64, reference: u64, max_deviation_bps: u64) -> bool {
+    let deviation = price.abs_diff(reference) as u128 * BPS_DENOM as u128;
+    deviation <= reference as u128 * max_deviation_bps as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedOracle(Option<OraclePrice>);
+
+    #[async_trait]
+    impl PriceOracle for FixedOracle {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn get_price(&self, _symbol: &str) -> Result<OraclePrice, LiquidationError> {
+            self.0.ok_or_else(|| LiquidationError::Oracle("down".to_string()))
+        }
+    }
+
+    fn source(price: u64, age_secs: i64) -> Arc<dyn PriceOracle> {
+        Arc::new(FixedOracle(Some(OraclePrice {
+            price,
+            publish_time: unix_now() - age_secs,
+        })))
+    }
+
+    fn down() -> Arc<dyn PriceOracle> {
+        Arc::new(FixedOracle(None))
+    }
+
+    #[tokio::test]
+    async fn median_ignores_outliers_stale_and_failed_sources() {
+        let oracle = AggregatedOracle::new(vec![
+            source(60_000_000_000, 0),
+            source(60_030_000_000, 5),
+            source(59_990_000_000, 1),
+            source(75_000_000_000, 0),                             // outlier
+            source(50_000_000_000, MAX_ORACLE_STALENESS_SECS + 1), // stale
+            down(),
+        ])
+        .with_min_sources(3);
+
+        let price = oracle.get_price("BTC-PERP").await.unwrap();
+        assert_eq!(price.price, 60_000_000_000);
+        assert!(unix_now() - price.publish_time >= 5);
+    }
+
+    #[tokio::test]
+    async fn too_few_agreeing_sources_is_an_error() {
+        // Two fresh sources 10% apart, so only one is within 1% of the median.
+        let disagree = AggregatedOracle::new(vec![
+            source(60_000_000_000, 0),
+            source(66_000_000_000, 0),
+            source(60_000_000_000, 120),
+        ]);
+        assert!(matches!(
+            disagree.get_price("BTC-PERP").await,
+            Err(LiquidationError::Oracle(_))
+        ));
+
+        let one_left = AggregatedOracle::new(vec![source(60_000_000_000, 0), down(), down()]);
+        assert!(one_left.get_price("BTC-PERP").await.is_err());
+    }
+}
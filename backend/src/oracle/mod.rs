@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::errors::LiquidationError;
+use crate::models::market_info;
+
+mod aggregate;
+mod onchain;
+mod pyth;
+mod static_source;
+mod switchboard;
+
+pub use aggregate::AggregatedOracle;
+pub use onchain::OnChainPriceFeedOracle;
+pub use pyth::PythHermesOracle;
+pub use static_source::StaticPriceOracle;
+pub use switchboard::SwitchboardOracle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: u64,        // in the market's price decimals, like `PriceFeed.price`
+    pub publish_time: i64, // unix seconds, as reported by the source
+}
+
+#[async_trait]
+pub trait PriceOracle: Send + Sync {
+    // Short label for logs, e.g. "pyth".
+    fn name(&self) -> &str;
+
+    async fn get_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError>;
+
+    async fn get_mark_price(&self, symbol: &str) -> Result<u64, LiquidationError> {
+        Ok(self.get_price(symbol).await?.price)
+    }
+}
+
+// ORACLE_SOURCE selects the implementation (default `static`):
+//   static      ORACLE_STATIC_PRICES="BTC-PERP=60000,..." (optional)
+//   pyth        PYTH_HERMES_URL, PYTH_FEED_IDS (optional), PYTH_STREAM=0 disables the WebSocket
+//   switchboard SWITCHBOARD_CROSSBAR_URL, SWITCHBOARD_FEEDS="BTC-PERP=<feed hash>,..."
+//   onchain     RPC_URL, WS_URL, LIQUIDATION_PROGRAM_ID; feeds are read from each
+//               `Market.price_feed` unless PRICE_FEED_ACCOUNTS="BTC-PERP=<pubkey>,..."
+//   aggregate   ORACLE_SOURCES="pyth,switchboard,onchain", each configured as above;
+//               ORACLE_MIN_SOURCES (default: majority), ORACLE_MAX_DEVIATION_BPS (default 100)
+// Spawns background tasks, so call from within the runtime.
+pub fn from_env() -> Result<Arc<dyn PriceOracle>, LiquidationError> {
+    let source = std::env::var("ORACLE_SOURCE").unwrap_or_else(|_| "static".to_string());
+    named_from_env(&source)
+}
+
+// Like `from_env`, for a source name taken from elsewhere (e.g. PRICE_PUSHER_SOURCE).
+pub fn named_from_env(source: &str) -> Result<Arc<dyn PriceOracle>, LiquidationError> {
+    if source != "aggregate" {
+        return source_from_env(source);
+    }
+
+    let names = std::env::var("ORACLE_SOURCES")
+        .map_err(|_| LiquidationError::Oracle("ORACLE_SOURCES is not set".to_string()))?;
+    let sources = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(source_from_env)
+        .collect::<Result<Vec<_>, _>>()?;
+    if sources.is_empty() {
+        return Err(LiquidationError::Oracle("ORACLE_SOURCES is empty".to_string()));
+    }
+
+    let mut oracle = AggregatedOracle::new(sources);
+    if let Some(min_sources) = env_number("ORACLE_MIN_SOURCES")? {
+        oracle = oracle.with_min_sources(min_sources);
+    }
+    if let Some(max_deviation_bps) = env_number("ORACLE_MAX_DEVIATION_BPS")? {
+        oracle = oracle.with_max_deviation_bps(max_deviation_bps);
+    }
+    Ok(Arc::new(oracle))
+}
+
+fn source_from_env(source: &str) -> Result<Arc<dyn PriceOracle>, LiquidationError> {
+    let oracle: Arc<dyn PriceOracle> = match source {
+        "static" => Arc::new(StaticPriceOracle::from_env()?),
+        "pyth" => {
+            let oracle = Arc::new(PythHermesOracle::from_env()?);
+            if std::env::var("PYTH_STREAM").as_deref() != Ok("0") {
+                tokio::spawn(oracle.clone().start_stream());
+            }
+            oracle
+        }
+        "switchboard" => Arc::new(SwitchboardOracle::from_env()?),
+        "onchain" => {
+            let oracle = Arc::new(OnChainPriceFeedOracle::from_env()?);
+            tokio::spawn(oracle.clone().start());
+            oracle
+        }
+        other => {
+            return Err(LiquidationError::Oracle(format!("unknown ORACLE_SOURCE {other}")));
+        }
+    };
+    Ok(oracle)
+}
+
+fn env_number<T: std::str::FromStr>(name: &str) -> Result<Option<T>, LiquidationError> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| LiquidationError::Oracle(format!("bad {name}: {value}"))),
+        Err(_) => Ok(None),
+    }
+}
+
+// Converts `mantissa * 10^expo` (Pyth's representation) to the market's
+// price decimals, rounding down.
+pub fn to_market_price(symbol: &str, mantissa: i64, expo: i32) -> Result<u64, LiquidationError> {
+    let decimals = price_decimals(symbol)?;
+    if mantissa < 0 {
+        return Err(LiquidationError::Oracle(format!("negative price for {symbol}")));
+    }
+
+    let shift = expo + decimals as i32;
+    let value = if shift >= 0 {
+        10u128
+            .checked_pow(shift as u32)
+            .and_then(|scale| (mantissa as u128).checked_mul(scale))
+    } else {
+        10u128
+            .checked_pow(shift.unsigned_abs())
+            .map(|scale| mantissa as u128 / scale)
+            .or(Some(0))
+    };
+
+    value
+        .and_then(|v| u64::try_from(v).ok())
+        .ok_or_else(|| LiquidationError::Oracle(format!("price out of range for {symbol}")))
+}
+
+pub fn price_decimals(symbol: &str) -> Result<u8, LiquidationError> {
+    crate::models::market_index_for_symbol(symbol)
+        .and_then(market_info)
+        .map(|m| m.price_decimals)
+        .ok_or_else(|| LiquidationError::Oracle(format!("unknown market {symbol}")))
+}
+
+// "BTC-PERP=abc,ETH-PERP=def" -> {symbol: value}.
+pub fn parse_symbol_map(spec: &str) -> Result<HashMap<String, String>, LiquidationError> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .map(|(symbol, value)| (symbol.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| {
+                    LiquidationError::Oracle(format!("expected SYMBOL=value, got {entry}"))
+                })
+        })
+        .collect()
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// Serves `app` on an ephemeral local port, standing in for a feed's API.
+#[cfg(test)]
+pub(crate) async fn spawn_stub_server(app: axum::Router) -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::load_test_markets;
+
+    #[test]
+    fn to_market_price_rescales_exponent() {
+        load_test_markets();
+        // Pyth BTC: 67,123.45678901 with expo -8 -> 6 decimals, rounded down
+        assert_eq!(to_market_price("BTC-PERP", 6_712_345_678_901, -8).unwrap(), 67_123_456_789);
+        // Coarser source than the market
+        assert_eq!(to_market_price("ETH-PERP", 3_000, 0).unwrap(), 3_000_000_000);
+        assert!(to_market_price("ETH-PERP", -1, 0).is_err());
+        assert!(to_market_price("DOGE-PERP", 1, 0).is_err());
+    }
+
+    #[test]
+    fn parse_symbol_map_accepts_whitespace() {
+        let map = parse_symbol_map(" BTC-PERP=a , ETH-PERP=b,").unwrap();
+        assert_eq!(map["BTC-PERP"], "a");
+        assert_eq!(map["ETH-PERP"], "b");
+        assert!(parse_symbol_map("BTC-PERP").is_err());
+    }
+}
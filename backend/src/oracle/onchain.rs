@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures_util::stream::{select_all, StreamExt};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::pubkey::Pubkey;
+use tokio::time::{sleep, Duration};
+
+use crate::accounts::{decode_market, decode_price_feed, market_address, PROGRAM_ID};
+use crate::errors::LiquidationError;
+use crate::models::MARKETS;
+
+use super::{parse_symbol_map, OraclePrice, PriceOracle};
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_WS_URL: &str = "ws://127.0.0.1:8900";
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(2);
+
+// Reads the program's `PriceFeed` accounts directly: the price the
+// `liquidate_*` instructions will see, with `last_updated` as publish time.
+// Feeds come from each `Market.price_feed` (or PRICE_FEED_ACCOUNTS) and are
+// kept current with `accountSubscribe` once `start` runs; until then, or
+// while the subscription is down, every read goes to the RPC node.
+pub struct OnChainPriceFeedOracle {
+    rpc: RpcClient,
+    ws_url: String,
+    program_id: Pubkey,
+    pinned: bool,                            // feeds fixed by config, not read from markets
+    feeds: RwLock<HashMap<String, Pubkey>>,  // symbol -> PriceFeed account
+    latest: RwLock<HashMap<Pubkey, OraclePrice>>,
+}
+
+impl OnChainPriceFeedOracle {
+    pub fn new(rpc: RpcClient, ws_url: String, program_id: Pubkey) -> Self {
+        Self {
+            rpc,
+            ws_url,
+            program_id,
+            pinned: false,
+            feeds: RwLock::new(HashMap::new()),
+            latest: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Uses `feeds` instead of looking them up from the markets.
+    pub fn with_feeds(mut self, feeds: HashMap<String, Pubkey>) -> Self {
+        self.pinned = true;
+        self.feeds = RwLock::new(feeds);
+        self
+    }
+
+    // RPC_URL, WS_URL, LIQUIDATION_PROGRAM_ID; PRICE_FEED_ACCOUNTS="BTC-PERP=<pubkey>,..."
+    // pins the feeds instead of reading them from the markets.
+    pub fn from_env() -> Result<Self, LiquidationError> {
+        let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+        let ws_url = std::env::var("WS_URL").unwrap_or_else(|_| DEFAULT_WS_URL.to_string());
+        let program_id = std::env::var("LIQUIDATION_PROGRAM_ID")
+            .unwrap_or_else(|_| PROGRAM_ID.to_string());
+        let program_id = Pubkey::from_str(&program_id)
+            .map_err(|e| LiquidationError::Oracle(format!("invalid program id: {e}")))?;
+        let oracle = Self::new(RpcClient::new(rpc_url), ws_url, program_id);
+
+        let Ok(spec) = std::env::var("PRICE_FEED_ACCOUNTS") else {
+            return Ok(oracle);
+        };
+        let feeds = parse_symbol_map(&spec)?
+            .into_iter()
+            .map(|(symbol, account)| {
+                Pubkey::from_str(&account).map(|pubkey| (symbol, pubkey)).map_err(|e| {
+                    LiquidationError::Oracle(format!("bad feed account {account}: {e}"))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(oracle.with_feeds(feeds))
+    }
+
+    // Looks up the feeds, then follows them, starting over after every
+    // dropped subscription so a rebound `Market.price_feed` is picked up.
+    pub async fn start(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.load_market_feeds().await {
+                eprintln!("[ORACLE] Error loading market price feeds: {e}");
+            }
+
+            if let Err(e) = self.subscribe().await {
+                eprintln!("[ORACLE] PriceFeed subscription ended: {e}");
+            }
+            self.latest.write().unwrap().clear();
+            sleep(RESUBSCRIBE_DELAY).await;
+        }
+    }
+
+    // Reads `price_feed` from every known market. Markets without one are
+    // left out: the program would accept any feed for them.
+    pub async fn load_market_feeds(&self) -> Result<usize, LiquidationError> {
+        if self.pinned {
+            return Ok(self.feeds.read().unwrap().len());
+        }
+
+        let addresses: Vec<Pubkey> = MARKETS
+            .iter()
+            .map(|m| market_address(&self.program_id, m.market_index))
+            .collect();
+        let accounts = self.rpc.get_multiple_accounts(&addresses).await?;
+
+        let mut feeds = HashMap::new();
+        for (market, account) in MARKETS.iter().zip(accounts) {
+            let Some(account) = account else {
+                eprintln!("[ORACLE] No on-chain market for {}", market.symbol);
+                continue;
+            };
+            let price_feed = decode_market(&account.data)?.price_feed;
+            if price_feed == Pubkey::default() {
+                eprintln!("[ORACLE] {} has no price feed bound", market.symbol);
+                continue;
+            }
+            feeds.insert(market.symbol.to_string(), price_feed);
+        }
+
+        let count = feeds.len();
+        *self.feeds.write().unwrap() = feeds;
+        Ok(count)
+    }
+
+    // Applies `accountSubscribe` notifications for every feed until one of
+    // the subscriptions closes.
+    pub async fn subscribe(&self) -> Result<(), LiquidationError> {
+        let feeds: Vec<Pubkey> = self.feeds.read().unwrap().values().copied().collect();
+        if feeds.is_empty() {
+            return Err(LiquidationError::Oracle("no price feeds to follow".to_string()));
+        }
+
+        let client = PubsubClient::new(&self.ws_url)
+            .await
+            .map_err(|e| LiquidationError::Oracle(format!("pubsub connect: {e}")))?;
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        };
+
+        let mut streams = Vec::with_capacity(feeds.len());
+        let mut unsubscribes = Vec::with_capacity(feeds.len());
+        for feed in feeds {
+            let (updates, unsubscribe) = client
+                .account_subscribe(&feed, Some(config.clone()))
+                .await
+                .map_err(|e| LiquidationError::Oracle(format!("accountSubscribe: {e}")))?;
+            streams.push(updates.map(move |update| (feed, update.value)));
+            unsubscribes.push(unsubscribe);
+        }
+
+        let mut updates = select_all(streams);
+        while let Some((feed, account)) = updates.next().await {
+            match account.data.decode() {
+                Some(data) => self.apply_account(feed, &data),
+                None => eprintln!("[ORACLE] Undecodable update for {feed}"),
+            }
+        }
+
+        drop(updates);
+        for unsubscribe in unsubscribes {
+            unsubscribe().await;
+        }
+        Ok(())
+    }
+
+    pub fn apply_account(&self, feed: Pubkey, data: &[u8]) {
+        match decode_price_feed(data) {
+            Ok(account) => {
+                self.latest.write().unwrap().insert(feed, to_oracle_price(&account));
+            }
+            Err(e) => eprintln!("[ORACLE] Skipping PriceFeed {feed}: {e}"),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceOracle for OnChainPriceFeedOracle {
+    fn name(&self) -> &str {
+        "onchain"
+    }
+
+    async fn get_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError> {
+        let feed = self
+            .feeds
+            .read()
+            .unwrap()
+            .get(symbol)
+            .copied()
+            .ok_or_else(|| LiquidationError::Oracle(format!("no PriceFeed account for {symbol}")))?;
+        if let Some(price) = self.latest.read().unwrap().get(&feed) {
+            return Ok(*price);
+        }
+
+        let data = self.rpc.get_account_data(&feed).await?;
+        Ok(to_oracle_price(&decode_price_feed(&data)?))
+    }
+}
+
+// Already in the market's price units: the program uses it as-is.
+fn to_oracle_price(account: &crate::accounts::PriceFeedAccount) -> OraclePrice {
+    OraclePrice {
+        price: account.price,
+        publish_time: account.last_updated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::UiAccount;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::account::Account;
+
+    use crate::accounts::{account_data, market_data};
+
+    fn price_feed_data(price: u64, last_updated: i64) -> Vec<u8> {
+        account_data(&liquidation_engine::PriceFeed {
+            price,
+            last_updated,
+            version: liquidation_engine::PriceFeed::VERSION,
+            _reserved: [0; 32],
+        })
+    }
+
+    fn ui_account(pubkey: &Pubkey, data: Vec<u8>) -> UiAccount {
+        let account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        UiAccount::encode(pubkey, &account, UiAccountEncoding::Base64, None, None)
+    }
+
+    fn oracle(mocks: HashMap<RpcRequest, serde_json::Value>) -> OnChainPriceFeedOracle {
+        let rpc = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        OnChainPriceFeedOracle::new(rpc, DEFAULT_WS_URL.to_string(), Pubkey::new_unique())
+    }
+
+    #[tokio::test]
+    async fn decodes_pinned_price_feed_account() {
+        let feed = Pubkey::new_unique();
+        let response = serde_json::json!({
+            "context": { "slot": 1 },
+            "value": ui_account(&feed, price_feed_data(61_000_000_000, 1_700_000_000)),
+        });
+        let oracle = oracle(HashMap::from([(RpcRequest::GetAccountInfo, response)]))
+            .with_feeds(HashMap::from([("BTC-PERP".to_string(), feed)]));
+
+        let price = oracle.get_price("BTC-PERP").await.unwrap();
+        assert_eq!(price, OraclePrice { price: 61_000_000_000, publish_time: 1_700_000_000 });
+    }
+
+    #[tokio::test]
+    async fn follows_the_feed_each_market_references() {
+        let btc_feed = Pubkey::new_unique();
+        let markets = serde_json::json!({
+            "context": { "slot": 1 },
+            "value": [
+                ui_account(&Pubkey::new_unique(), market_data(0, "BTC-PERP", btc_feed)),
+                ui_account(&Pubkey::new_unique(), market_data(1, "ETH-PERP", Pubkey::default())),
+                null,
+            ],
+        });
+        let oracle = oracle(HashMap::from([(RpcRequest::GetMultipleAccounts, markets)]));
+
+        assert_eq!(oracle.load_market_feeds().await.unwrap(), 1);
+        // Unbound market: the program accepts any feed, so there is none to follow.
+        assert!(oracle.get_price("ETH-PERP").await.is_err());
+
+        // Subscription updates are served without an RPC read.
+        oracle.apply_account(btc_feed, &price_feed_data(62_000_000_000, 1_700_000_060));
+        let price = oracle.get_price("BTC-PERP").await.unwrap();
+        assert_eq!(price, OraclePrice { price: 62_000_000_000, publish_time: 1_700_000_060 });
+    }
+}
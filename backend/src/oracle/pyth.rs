@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::errors::LiquidationError;
+
+use super::{parse_symbol_map, to_market_price, OraclePrice, PriceOracle};
+
+const DEFAULT_HERMES_URL: &str = "https://hermes.pyth.network";
+// Pyth price feed ids (hex, without 0x) for the markets in `models::MARKETS`.
+const DEFAULT_FEED_IDS: &[(&str, &str)] = &[
+    ("BTC-PERP", "e62df6c8b4a85fe1a67db44dc12de5db330f7ac66b72dc658afedf0f4a415b43"),
+    ("ETH-PERP", "ff61491a931112ddf1bd8147cd1b641375f79f5825126d665480874634fd0ace"),
+    ("SOL-PERP", "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d"),
+];
+// Streamed prices newer than this are served without an HTTP round trip.
+const STREAM_CACHE_TTL: Duration = Duration::from_secs(2);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize)]
+struct HermesPrice {
+    price: String, // i64 mantissa, sent as a string
+    expo: i32,
+    publish_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HermesPriceFeed {
+    id: String,
+    price: HermesPrice,
+}
+
+// GET /v2/updates/price/latest
+#[derive(Debug, Deserialize)]
+struct HermesLatest {
+    parsed: Vec<HermesPriceFeed>,
+}
+
+// WebSocket messages; only `price_update` carries data.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum HermesMessage {
+    PriceUpdate { price_feed: HermesPriceFeed },
+    #[serde(other)]
+    Other,
+}
+
+// Pyth prices from a Hermes server: latest price over HTTP, optionally kept
+// warm by the `/ws` price stream.
+pub struct PythHermesOracle {
+    http: reqwest::Client,
+    base_url: String,
+    feed_ids: HashMap<String, String>, // symbol -> feed id
+    streamed: RwLock<HashMap<String, (OraclePrice, Instant)>>,
+}
+
+impl PythHermesOracle {
+    pub fn new(base_url: String, feed_ids: HashMap<String, String>) -> Self {
+        let feed_ids = feed_ids
+            .into_iter()
+            .map(|(symbol, id)| (symbol, normalize_id(&id)))
+            .collect();
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            feed_ids,
+            streamed: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // PYTH_HERMES_URL; PYTH_FEED_IDS="BTC-PERP=<id>,..." overrides the defaults.
+    pub fn from_env() -> Result<Self, LiquidationError> {
+        let base_url =
+            std::env::var("PYTH_HERMES_URL").unwrap_or_else(|_| DEFAULT_HERMES_URL.to_string());
+        let mut feed_ids: HashMap<String, String> = DEFAULT_FEED_IDS
+            .iter()
+            .map(|(symbol, id)| (symbol.to_string(), id.to_string()))
+            .collect();
+        if let Ok(spec) = std::env::var("PYTH_FEED_IDS") {
+            feed_ids.extend(parse_symbol_map(&spec)?);
+        }
+        Ok(Self::new(base_url, feed_ids))
+    }
+
+    // Follows the Hermes WebSocket until the process exits, reconnecting on errors.
+    pub async fn start_stream(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.stream_once().await {
+                eprintln!("[ORACLE] Pyth stream error: {e}");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    pub async fn stream_once(&self) -> Result<(), LiquidationError> {
+        let ws_url = format!("{}/ws", self.base_url.replacen("http", "ws", 1));
+        let (mut ws, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+            .await
+            .map_err(|e| LiquidationError::Oracle(format!("pyth ws connect: {e}")))?;
+
+        let subscribe = serde_json::json!({
+            "type": "subscribe",
+            "ids": self.feed_ids.values().collect::<Vec<_>>(),
+        });
+        ws.send(Message::Text(subscribe.to_string()))
+            .await
+            .map_err(|e| LiquidationError::Oracle(format!("pyth ws subscribe: {e}")))?;
+
+        while let Some(message) = ws.next().await {
+            let message = message.map_err(|e| LiquidationError::Oracle(format!("pyth ws: {e}")))?;
+            let Message::Text(text) = message else {
+                continue;
+            };
+            match serde_json::from_str::<HermesMessage>(&text) {
+                Ok(HermesMessage::PriceUpdate { price_feed }) => self.record_stream(&price_feed),
+                Ok(HermesMessage::Other) => {}
+                Err(e) => eprintln!("[ORACLE] Ignoring Pyth message {text}: {e}"),
+            }
+        }
+        Ok(())
+    }
+
+    fn record_stream(&self, feed: &HermesPriceFeed) {
+        let id = normalize_id(&feed.id);
+        let Some(symbol) = self.symbol_for(&id) else {
+            return;
+        };
+        match to_oracle_price(symbol, &feed.price) {
+            Ok(price) => {
+                self.streamed
+                    .write()
+                    .unwrap()
+                    .insert(symbol.to_string(), (price, Instant::now()));
+            }
+            Err(e) => eprintln!("[ORACLE] Bad Pyth update for {symbol}: {e}"),
+        }
+    }
+
+    fn symbol_for(&self, id: &str) -> Option<&str> {
+        self.feed_ids
+            .iter()
+            .find(|(_, feed_id)| feed_id.as_str() == id)
+            .map(|(symbol, _)| symbol.as_str())
+    }
+
+    async fn fetch_latest(
+        &self,
+        symbol: &str,
+        feed_id: &str,
+    ) -> Result<OraclePrice, LiquidationError> {
+        let url = format!("{}/v2/updates/price/latest", self.base_url);
+        let latest: HermesLatest = self
+            .http
+            .get(url)
+            .query(&[("ids[]", feed_id), ("parsed", "true")])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LiquidationError::Oracle(format!("pyth http: {e}")))?
+            .json()
+            .await
+            .map_err(|e| LiquidationError::Oracle(format!("pyth response: {e}")))?;
+
+        let feed = latest
+            .parsed
+            .iter()
+            .find(|feed| normalize_id(&feed.id) == feed_id)
+            .ok_or_else(|| {
+                LiquidationError::Oracle(format!("pyth returned no price for {symbol}"))
+            })?;
+        to_oracle_price(symbol, &feed.price)
+    }
+}
+
+#[async_trait]
+impl PriceOracle for PythHermesOracle {
+    fn name(&self) -> &str {
+        "pyth"
+    }
+
+    async fn get_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError> {
+        if let Some((price, received)) = self.streamed.read().unwrap().get(symbol) {
+            if received.elapsed() < STREAM_CACHE_TTL {
+                return Ok(*price);
+            }
+        }
+
+        let feed_id = self
+            .feed_ids
+            .get(symbol)
+            .ok_or_else(|| LiquidationError::Oracle(format!("no pyth feed for {symbol}")))?;
+        self.fetch_latest(symbol, feed_id).await
+    }
+}
+
+fn normalize_id(id: &str) -> String {
+    id.trim_start_matches("0x").to_ascii_lowercase()
+}
+
+fn to_oracle_price(symbol: &str, price: &HermesPrice) -> Result<OraclePrice, LiquidationError> {
+    let mantissa = price
+        .price
+        .parse::<i64>()
+        .map_err(|e| LiquidationError::Oracle(format!("bad pyth price {}: {e}", price.price)))?;
+    Ok(OraclePrice {
+        price: to_market_price(symbol, mantissa, price.expo)?,
+        publish_time: price.publish_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::ws::{Message as WsMessage, WebSocketUpgrade};
+    use axum::routing::get;
+    use axum::{Json, Router};
+
+    use crate::models::load_test_markets;
+    use crate::oracle::spawn_stub_server;
+
+    const BTC_ID: &str = "e62df6c8b4a85fe1a67db44dc12de5db330f7ac66b72dc658afedf0f4a415b43";
+
+    fn btc_feed(price: &str) -> serde_json::Value {
+        let price = serde_json::json!({
+            "price": price, "conf": "100", "expo": -8, "publish_time": 1_700_000_000,
+        });
+        serde_json::json!({ "id": BTC_ID, "price": price, "ema_price": price })
+    }
+
+    fn oracle(base_url: String) -> PythHermesOracle {
+        let feed_ids = HashMap::from([("BTC-PERP".to_string(), format!("0x{BTC_ID}"))]);
+        PythHermesOracle::new(base_url, feed_ids)
+    }
+
+    #[tokio::test]
+    async fn fetches_latest_price_over_http() {
+        load_test_markets();
+        let app = Router::new().route(
+            "/v2/updates/price/latest",
+            get(|| async { Json(serde_json::json!({ "parsed": [btc_feed("6712345678901")] })) }),
+        );
+        let oracle = oracle(format!("http://{}", spawn_stub_server(app).await));
+
+        let price = oracle.get_price("BTC-PERP").await.unwrap();
+        assert_eq!(price.price, 67_123_456_789);
+        assert_eq!(price.publish_time, 1_700_000_000);
+        assert!(oracle.get_price("ETH-PERP").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_updates_are_served_from_cache() {
+        load_test_markets();
+        let app = Router::new().route(
+            "/ws",
+            get(|ws: WebSocketUpgrade| async {
+                ws.on_upgrade(|mut socket| async move {
+                    // subscribe request, then one update
+                    let _ = socket.recv().await;
+                    let ack = serde_json::json!({ "type": "response", "status": "success" });
+                    let update = serde_json::json!({
+                        "type": "price_update",
+                        "price_feed": btc_feed("6000000000000"),
+                    });
+                    let _ = socket.send(WsMessage::Text(ack.to_string())).await;
+                    let _ = socket.send(WsMessage::Text(update.to_string())).await;
+                    let _ = socket.send(WsMessage::Close(None)).await;
+                })
+            }),
+        );
+        // No HTTP route: a cache miss would fail.
+        let oracle = oracle(format!("http://{}", spawn_stub_server(app).await));
+
+        oracle.stream_once().await.unwrap();
+        assert_eq!(oracle.get_mark_price("BTC-PERP").await.unwrap(), 60_000_000_000);
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::errors::LiquidationError;
+use crate::models::scale_up;
+
+use super::{parse_symbol_map, price_decimals, unix_now, OraclePrice, PriceOracle};
+
+// Demo prices used when nothing else is configured.
+const DEFAULT_PRICES: &[(&str, f64)] = &[
+    ("BTC-PERP", 60_000.0),
+    ("ETH-PERP", 3_000.0),
+    ("SOL-PERP", 1_000.0),
+];
+
+// Fixed prices, always reported as just published. For local runs and tests.
+pub struct StaticPriceOracle {
+    prices: RwLock<HashMap<String, u64>>,
+}
+
+impl Default for StaticPriceOracle {
+    fn default() -> Self {
+        let oracle = Self::empty();
+        for (symbol, price) in DEFAULT_PRICES {
+            // Markets whose `Market` account wasn't loaded have no decimals; skip them.
+            let _ = oracle.set_price(symbol, *price);
+        }
+        oracle
+    }
+}
+
+impl StaticPriceOracle {
+    pub fn empty() -> Self {
+        Self {
+            prices: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // ORACLE_STATIC_PRICES="BTC-PERP=60000,ETH-PERP=3000" overrides the defaults.
+    pub fn from_env() -> Result<Self, LiquidationError> {
+        let oracle = Self::default();
+        if let Ok(spec) = std::env::var("ORACLE_STATIC_PRICES") {
+            for (symbol, price) in parse_symbol_map(&spec)? {
+                let price = price
+                    .parse::<f64>()
+                    .map_err(|e| LiquidationError::Oracle(format!("bad price for {symbol}: {e}")))?;
+                oracle.set_price(&symbol, price)?;
+            }
+        }
+        Ok(oracle)
+    }
+
+    // `price` in whole quote units per base unit, e.g. 60000.0.
+    pub fn set_price(&self, symbol: &str, price: f64) -> Result<(), LiquidationError> {
+        let price = scale_up(price, price_decimals(symbol)?);
+        self.prices.write().unwrap().insert(symbol.to_string(), price);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PriceOracle for StaticPriceOracle {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    async fn get_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError> {
+        let price = self
+            .prices
+            .read()
+            .unwrap()
+            .get(symbol)
+            .copied()
+            .ok_or_else(|| LiquidationError::Oracle(format!("no static price for {symbol}")))?;
+        Ok(OraclePrice {
+            price,
+            publish_time: unix_now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::load_test_markets;
+
+    #[tokio::test]
+    async fn defaults_are_in_market_decimals() {
+        load_test_markets();
+        let oracle = StaticPriceOracle::default();
+        assert_eq!(oracle.get_mark_price("BTC-PERP").await.unwrap(), 60_000_000_000);
+
+        oracle.set_price("BTC-PERP", 59_000.5).unwrap();
+        assert_eq!(oracle.get_mark_price("BTC-PERP").await.unwrap(), 59_000_500_000);
+        assert!(StaticPriceOracle::empty().get_price("BTC-PERP").await.is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::errors::LiquidationError;
+use crate::models::scale_up;
+
+use super::{parse_symbol_map, price_decimals, unix_now, OraclePrice, PriceOracle};
+
+const DEFAULT_CROSSBAR_URL: &str = "https://crossbar.switchboard.xyz";
+
+// GET /simulate/<feed hash> returns one entry per requested feed.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulatedFeed {
+    feed_hash: String,
+    results: Vec<f64>,
+}
+
+// Switchboard on-demand feeds, evaluated by a Crossbar server. A simulation
+// is computed on request, so it is reported as published now.
+pub struct SwitchboardOracle {
+    http: reqwest::Client,
+    base_url: String,
+    feeds: HashMap<String, String>, // symbol -> feed hash
+}
+
+impl SwitchboardOracle {
+    pub fn new(base_url: String, feeds: HashMap<String, String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            feeds,
+        }
+    }
+
+    // SWITCHBOARD_CROSSBAR_URL, SWITCHBOARD_FEEDS="BTC-PERP=<feed hash>,..." (required).
+    pub fn from_env() -> Result<Self, LiquidationError> {
+        let base_url = std::env::var("SWITCHBOARD_CROSSBAR_URL")
+            .unwrap_or_else(|_| DEFAULT_CROSSBAR_URL.to_string());
+        let feeds = std::env::var("SWITCHBOARD_FEEDS")
+            .map_err(|_| LiquidationError::Oracle("SWITCHBOARD_FEEDS is not set".to_string()))?;
+        Ok(Self::new(base_url, parse_symbol_map(&feeds)?))
+    }
+}
+
+#[async_trait]
+impl PriceOracle for SwitchboardOracle {
+    fn name(&self) -> &str {
+        "switchboard"
+    }
+
+    async fn get_price(&self, symbol: &str) -> Result<OraclePrice, LiquidationError> {
+        let feed_hash = self
+            .feeds
+            .get(symbol)
+            .ok_or_else(|| LiquidationError::Oracle(format!("no switchboard feed for {symbol}")))?;
+
+        let url = format!("{}/simulate/{feed_hash}", self.base_url);
+        let feeds: Vec<SimulatedFeed> = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LiquidationError::Oracle(format!("switchboard http: {e}")))?
+            .json()
+            .await
+            .map_err(|e| LiquidationError::Oracle(format!("switchboard response: {e}")))?;
+
+        let value = feeds
+            .iter()
+            .find(|feed| &feed.feed_hash == feed_hash)
+            .and_then(|feed| feed.results.first().copied())
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .ok_or_else(|| {
+                LiquidationError::Oracle(format!("switchboard returned no price for {symbol}"))
+            })?;
+
+        Ok(OraclePrice {
+            price: scale_up(value, price_decimals(symbol)?),
+            publish_time: unix_now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Path;
+    use axum::routing::get;
+    use axum::{Json, Router};
+
+    use crate::models::load_test_markets;
+    use crate::oracle::spawn_stub_server;
+
+    #[tokio::test]
+    async fn reads_simulated_feed_result() {
+        load_test_markets();
+        let app = Router::new().route(
+            "/simulate/:hash",
+            get(|Path(hash): Path<String>| async move {
+                Json(serde_json::json!([{ "feedHash": hash, "results": [3012.5] }]))
+            }),
+        );
+        let base_url = format!("http://{}", spawn_stub_server(app).await);
+        let feeds = HashMap::from([("ETH-PERP".to_string(), "0xabc".to_string())]);
+        let oracle = SwitchboardOracle::new(base_url, feeds);
+
+        assert_eq!(oracle.get_mark_price("ETH-PERP").await.unwrap(), 3_012_500_000);
+        assert!(oracle.get_price("BTC-PERP").await.is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::stream::{BoxStream, StreamExt};
+use redis::aio::MultiplexedConnection;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::OnceCell;
+
+use crate::errors::LiquidationError;
+use crate::models::{Position, PositionChange};
+use crate::position_manager::PositionSource;
+
+// Ids of every open position.
+const OPEN_SET_KEY: &str = "positions:open";
+// JSON `PositionChange` per upsert / close.
+pub const CHANGES_CHANNEL: &str = "positions:changes";
+
+fn position_key(id: &str) -> String {
+    format!("positions:{id}")
+}
+
+// Write-through Redis cache in front of a durable source (Postgres). Open
+// positions live as hashes under `positions:<id>`, indexed by the
+// `positions:open` set, and every write is published on `positions:changes`
+// so readers can update incrementally. Reads never touch the inner source
+// after the cache has been warmed from it once.
+pub struct RedisPositionSource {
+    client: redis::Client,
+    inner: Arc<dyn PositionSource>,
+    conn: OnceCell<MultiplexedConnection>,
+    warmed: OnceCell<()>,
+}
+
+impl RedisPositionSource {
+    pub fn new(client: redis::Client, inner: Arc<dyn PositionSource>) -> Self {
+        Self {
+            client,
+            inner,
+            conn: OnceCell::new(),
+            warmed: OnceCell::new(),
+        }
+    }
+
+    async fn conn(&self) -> Result<MultiplexedConnection, LiquidationError> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| self.client.get_multiplexed_tokio_connection())
+            .await?;
+        Ok(conn.clone())
+    }
+
+    // Rebuilds the open set from the inner source, once per process.
+    async fn ensure_warm(&self) -> Result<(), LiquidationError> {
+        self.warmed
+            .get_or_try_init(|| async {
+                let positions = self.inner.get_open_positions().await?;
+                let mut pipe = redis::pipe();
+                pipe.atomic().del(OPEN_SET_KEY).ignore();
+                for position in &positions {
+                    let key = position_key(&position.id);
+                    pipe.del(&key).ignore();
+                    pipe.hset_multiple(&key, &position_fields(position)).ignore();
+                    pipe.sadd(OPEN_SET_KEY, &position.id).ignore();
+                }
+                pipe.query_async::<_, ()>(&mut self.conn().await?).await?;
+                Ok::<_, LiquidationError>(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn publish_write(
+        &self,
+        change: &PositionChange,
+        pipe: &mut redis::Pipeline,
+    ) -> Result<(), LiquidationError> {
+        let payload = serde_json::to_string(change)
+            .map_err(|e| LiquidationError::Other(format!("encode position change: {e}")))?;
+        pipe.publish(CHANGES_CHANNEL, payload).ignore();
+        pipe.query_async::<_, ()>(&mut self.conn().await?).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PositionSource for RedisPositionSource {
+    async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
+        self.ensure_warm().await?;
+        let mut conn = self.conn().await?;
+
+        let ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(OPEN_SET_KEY)
+            .query_async(&mut conn)
+            .await?;
+        let mut pipe = redis::pipe();
+        for id in &ids {
+            pipe.hgetall(position_key(id));
+        }
+        let hashes: Vec<HashMap<String, String>> = pipe.query_async(&mut conn).await?;
+
+        ids.into_iter()
+            .zip(hashes)
+            // A hash can vanish between SMEMBERS and HGETALL if the position closed.
+            .filter(|(_, fields)| !fields.is_empty())
+            .map(|(id, fields)| position_from_fields(id, &fields))
+            .collect()
+    }
+
+    async fn get_position(&self, id: &str) -> Result<Option<Position>, LiquidationError> {
+        self.ensure_warm().await?;
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(position_key(id))
+            .query_async(&mut self.conn().await?)
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        position_from_fields(id.to_string(), &fields).map(Some)
+    }
+
+    async fn upsert_position(&self, position: &Position) -> Result<bool, LiquidationError> {
+        self.ensure_warm().await?;
+        if !self.inner.upsert_position(position).await? {
+            return Ok(false);
+        }
+
+        let key = position_key(&position.id);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .del(&key)
+            .ignore()
+            .hset_multiple(&key, &position_fields(position))
+            .ignore()
+            .sadd(OPEN_SET_KEY, &position.id)
+            .ignore();
+        let change = PositionChange::Upserted {
+            position: position.clone(),
+        };
+        self.publish_write(&change, &mut pipe).await?;
+        Ok(true)
+    }
+
+    async fn close_position(&self, id: &str) -> Result<bool, LiquidationError> {
+        self.ensure_warm().await?;
+        if !self.inner.close_position(id).await? {
+            return Ok(false);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .del(position_key(id))
+            .ignore()
+            .srem(OPEN_SET_KEY, id)
+            .ignore();
+        let change = PositionChange::Closed { id: id.to_string() };
+        self.publish_write(&change, &mut pipe).await?;
+        Ok(true)
+    }
+
+    async fn subscribe_changes(
+        &self,
+    ) -> Result<Option<BoxStream<'static, PositionChange>>, LiquidationError> {
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(CHANGES_CHANNEL).await?;
+
+        let changes = pubsub.into_on_message().filter_map(|msg| async move {
+            let payload: String = msg.get_payload().ok()?;
+            match serde_json::from_str(&payload) {
+                Ok(change) => Some(change),
+                Err(e) => {
+                    eprintln!("[POSITIONS] Ignoring malformed change {payload}: {e}");
+                    None
+                }
+            }
+        });
+        Ok(Some(changes.boxed()))
+    }
+}
+
+fn position_fields(position: &Position) -> [(&'static str, String); 7] {
+    [
+        ("owner", position.owner.to_string()),
+        ("market_index", position.market_index.to_string()),
+        ("size", position.size.to_string()),
+        ("is_long", (position.is_long as u8).to_string()),
+        ("entry_price", position.entry_price.to_string()),
+        ("collateral", position.collateral.to_string()),
+        ("leverage", position.leverage.to_string()),
+    ]
+}
+
+fn position_from_fields(
+    id: String,
+    fields: &HashMap<String, String>,
+) -> Result<Position, LiquidationError> {
+    fn field<T: FromStr>(
+        fields: &HashMap<String, String>,
+        name: &str,
+    ) -> Result<T, LiquidationError> {
+        fields
+            .get(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| LiquidationError::Decode(format!("bad or missing cached field {name}")))
+    }
+
+    Ok(Position {
+        owner: field::<Pubkey>(fields, "owner")?,
+        market_index: field(fields, "market_index")?,
+        size: field(fields, "size")?,
+        is_long: field::<u8>(fields, "is_long")? != 0,
+        entry_price: field(fields, "entry_price")?,
+        collateral: field(fields, "collateral")?,
+        leverage: field(fields, "leverage")?,
+        id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_hash_round_trips() {
+        let position = Position {
+            id: "pos-1".to_string(),
+            owner: Pubkey::new_unique(),
+            market_index: 0,
+            size: 100_000_000,
+            is_long: false,
+            entry_price: 60_000_000_000,
+            collateral: 50_000_000,
+            leverage: 500,
+        };
+
+        let fields: HashMap<String, String> = position_fields(&position)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        assert_eq!(position_from_fields("pos-1".to_string(), &fields).unwrap(), position);
+
+        let mut missing = fields.clone();
+        missing.remove("collateral");
+        assert!(position_from_fields("pos-1".to_string(), &missing).is_err());
+    }
+
+    #[test]
+    fn change_messages_are_tagged_json() {
+        let change = PositionChange::Closed { id: "pos-1".to_string() };
+        let payload = serde_json::to_string(&change).unwrap();
+        assert_eq!(payload, r#"{"type":"closed","id":"pos-1"}"#);
+        assert_eq!(serde_json::from_str::<PositionChange>(&payload).unwrap(), change);
+    }
+}
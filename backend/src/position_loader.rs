@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use anchor_lang::Discriminator;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::pubkey::Pubkey;
+use tokio::time::{sleep, Duration};
+
+use crate::accounts::{decode_position, POSITION_CLOSED_OFFSET, PROGRAM_ID};
+use crate::errors::LiquidationError;
+use crate::models::Position;
+use crate::position_manager::PositionSource;
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_WS_URL: &str = "ws://127.0.0.1:8900";
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(2);
+
+// Read-only `PositionSource` over the program's `Position` accounts: an
+// initial `getProgramAccounts` scan, then kept live by `programSubscribe`.
+// Position ids are the account addresses.
+pub struct OnChainPositionSource {
+    rpc: RpcClient,
+    ws_url: String,
+    program_id: Pubkey,
+    index: RwLock<HashMap<Pubkey, Position>>,
+}
+
+impl OnChainPositionSource {
+    pub fn new(rpc: RpcClient, ws_url: String, program_id: Pubkey) -> Self {
+        Self {
+            rpc,
+            ws_url,
+            program_id,
+            index: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // RPC_URL, WS_URL, LIQUIDATION_PROGRAM_ID.
+    pub fn from_env() -> Result<Self, LiquidationError> {
+        let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+        let ws_url = std::env::var("WS_URL").unwrap_or_else(|_| DEFAULT_WS_URL.to_string());
+        let program_id = std::env::var("LIQUIDATION_PROGRAM_ID")
+            .unwrap_or_else(|_| PROGRAM_ID.to_string());
+        let program_id = Pubkey::from_str(&program_id)
+            .map_err(|e| LiquidationError::Other(format!("invalid program id: {e}")))?;
+
+        Ok(Self::new(RpcClient::new(rpc_url), ws_url, program_id))
+    }
+
+    // Follows account updates, resubscribing (and so rescanning) after every
+    // dropped subscription so nothing missed in between goes stale.
+    pub async fn start(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.subscribe().await {
+                eprintln!("[POSITIONS] Subscription ended: {e:?}");
+            }
+            sleep(RESUBSCRIBE_DELAY).await;
+        }
+    }
+
+    // Replaces the index with every open `Position` account.
+    pub async fn load(&self) -> Result<usize, LiquidationError> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                discriminator_filter(),
+                // `closed == 0`; the offset only holds for the zero-copy layouts,
+                // so legacy v0 accounts should be migrated with `migrate_account`.
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(POSITION_CLOSED_OFFSET, vec![0])),
+            ]),
+            account_config: account_config(),
+            with_context: None,
+        };
+
+        let accounts = self
+            .rpc
+            .get_program_accounts_with_config(&self.program_id, config)
+            .await?;
+
+        let mut index = HashMap::with_capacity(accounts.len());
+        for (pubkey, account) in &accounts {
+            if let Some(position) = open_position(pubkey, &account.data) {
+                index.insert(*pubkey, position);
+            }
+        }
+
+        let count = index.len();
+        *self.index.write().unwrap() = index;
+        Ok(count)
+    }
+
+    // Subscribes before scanning, so an update landing during the scan is
+    // buffered and replayed on top of it rather than lost, then applies
+    // notifications until the stream closes.
+    pub async fn subscribe(&self) -> Result<(), LiquidationError> {
+        let client = PubsubClient::new(&self.ws_url)
+            .await
+            .map_err(|e| LiquidationError::Other(format!("pubsub connect: {e}")))?;
+
+        // No `closed` filter here: the update that closes a position must arrive to evict it.
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![discriminator_filter()]),
+            account_config: account_config(),
+            with_context: None,
+        };
+        let (mut updates, unsubscribe) = client
+            .program_subscribe(&self.program_id, Some(config))
+            .await
+            .map_err(|e| LiquidationError::Other(format!("programSubscribe: {e}")))?;
+
+        let mut buffered = Vec::new();
+        let scan = self.load();
+        tokio::pin!(scan);
+        let scanned = loop {
+            tokio::select! {
+                result = &mut scan => break result,
+                Some(update) = updates.next() => buffered.push(update.value),
+            }
+        };
+        match scanned {
+            Ok(count) => println!("[POSITIONS] Indexed {count} open on-chain positions"),
+            Err(e) => eprintln!("[POSITIONS] Error scanning positions: {e:?}"),
+        }
+        for account in &buffered {
+            self.apply_keyed_account(account);
+        }
+
+        while let Some(update) = updates.next().await {
+            self.apply_keyed_account(&update.value);
+        }
+
+        unsubscribe().await;
+        Ok(())
+    }
+
+    fn apply_keyed_account(&self, keyed: &RpcKeyedAccount) {
+        let Ok(pubkey) = Pubkey::from_str(&keyed.pubkey) else {
+            return;
+        };
+        match keyed.account.data.decode() {
+            Some(data) => self.apply_account(pubkey, &data),
+            None => eprintln!("[POSITIONS] Undecodable update for {pubkey}"),
+        }
+    }
+
+    // Inserts, updates or evicts one account from the index.
+    pub fn apply_account(&self, pubkey: Pubkey, data: &[u8]) {
+        let mut index = self.index.write().unwrap();
+        match open_position(&pubkey, data) {
+            Some(position) => index.insert(pubkey, position),
+            None => index.remove(&pubkey),
+        };
+    }
+}
+
+#[async_trait]
+impl PositionSource for OnChainPositionSource {
+    async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
+        Ok(self.index.read().unwrap().values().cloned().collect())
+    }
+
+    async fn get_position(&self, id: &str) -> Result<Option<Position>, LiquidationError> {
+        let Ok(pubkey) = Pubkey::from_str(id) else {
+            return Ok(None);
+        };
+        Ok(self.index.read().unwrap().get(&pubkey).cloned())
+    }
+
+    async fn upsert_position(&self, _position: &Position) -> Result<bool, LiquidationError> {
+        Err(read_only())
+    }
+
+    async fn close_position(&self, _id: &str) -> Result<bool, LiquidationError> {
+        Err(read_only())
+    }
+}
+
+// `None` for closed positions, unknown markets and accounts that do not decode.
+fn open_position(pubkey: &Pubkey, data: &[u8]) -> Option<Position> {
+    let account = match decode_position(data) {
+        Ok(account) => account,
+        Err(e) => {
+            eprintln!("[POSITIONS] Skipping {pubkey}: {e}");
+            return None;
+        }
+    };
+    if account.closed {
+        return None;
+    }
+    Position::from_account(pubkey.to_string(), &account).ok()
+}
+
+fn discriminator_filter() -> RpcFilterType {
+    let discriminator = liquidation_engine::Position::DISCRIMINATOR;
+    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, discriminator.to_vec()))
+}
+
+fn account_config() -> RpcAccountInfoConfig {
+    RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        ..RpcAccountInfoConfig::default()
+    }
+}
+
+fn read_only() -> LiquidationError {
+    LiquidationError::Other("on-chain positions are read-only".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::UiAccount;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::account::Account;
+
+    use crate::accounts::zero_copy_position_data;
+    use crate::models::load_test_markets;
+
+    fn position_data(owner: Pubkey, size: u64, closed: bool) -> Vec<u8> {
+        let data = zero_copy_position_data(&liquidation_engine::Position {
+            owner,
+            size,
+            entry_price: 3_000_000_000,
+            collateral: 100_000_000,
+            market_index: 1, // ETH-PERP
+            leverage: 10,
+            is_long: 1,
+            closed: closed as u8,
+            version: liquidation_engine::Position::VERSION,
+            _padding: [0],
+            at_risk_since: 0,
+            _reserved: [0; 56],
+        });
+        assert_eq!(data[POSITION_CLOSED_OFFSET], closed as u8);
+        data
+    }
+
+    fn keyed_account(program_id: &Pubkey, pubkey: &Pubkey, data: Vec<u8>) -> RpcKeyedAccount {
+        let account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: *program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+        RpcKeyedAccount {
+            pubkey: pubkey.to_string(),
+            account: UiAccount::encode(pubkey, &account, UiAccountEncoding::Base64, None, None),
+        }
+    }
+
+    #[tokio::test]
+    async fn load_indexes_open_positions_from_rpc() {
+        load_test_markets();
+        let program_id = Pubkey::from_str(PROGRAM_ID).unwrap();
+        let open = Pubkey::new_unique();
+        let closed = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let response = vec![
+            keyed_account(&program_id, &open, position_data(owner, 2_000_000, false)),
+            // A node ignoring the memcmp filter must not leak closed positions in.
+            keyed_account(&program_id, &closed, position_data(owner, 1_000_000, true)),
+        ];
+        let mut mocks = HashMap::new();
+        mocks.insert(RpcRequest::GetProgramAccounts, serde_json::to_value(response).unwrap());
+        let rpc = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+
+        let source = OnChainPositionSource::new(rpc, DEFAULT_WS_URL.to_string(), program_id);
+        assert_eq!(source.load().await.unwrap(), 1);
+
+        let position = source.get_position(&open.to_string()).await.unwrap().unwrap();
+        assert_eq!(position.owner, owner);
+        assert_eq!(position.size, 2_000_000);
+        assert!(source.get_position(&closed.to_string()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn account_updates_insert_and_evict() {
+        load_test_markets();
+        let program_id = Pubkey::from_str(PROGRAM_ID).unwrap();
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        let source = OnChainPositionSource::new(rpc, DEFAULT_WS_URL.to_string(), program_id);
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        source.apply_account(pubkey, &position_data(owner, 1_000_000, false));
+        source.apply_account(pubkey, &position_data(owner, 500_000, false));
+        let positions = source.get_open_positions().await.unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].size, 500_000);
+
+        // Closed by `liquidate_full`
+        source.apply_account(pubkey, &position_data(owner, 0, true));
+        assert!(source.get_open_positions().await.unwrap().is_empty());
+
+        assert!(source.upsert_position(&positions[0]).await.is_err());
+    }
+}
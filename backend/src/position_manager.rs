@@ -1,31 +1,349 @@
-use crate::errors::LiquidationError;
-use crate::models::Position;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use futures_util::stream::BoxStream;
 use solana_sdk::pubkey::Pubkey;
+use tokio_postgres::Row;
+
+use crate::db::{db_err, pool_from_env};
+use crate::errors::LiquidationError;
+use crate::models::{Position, PositionChange};
+use crate::position_cache::RedisPositionSource;
+use crate::position_loader::OnChainPositionSource;
+
+// Where the engine and the HTTP API read positions from. Closed positions are
+// never returned and cannot be upserted again.
+#[async_trait]
+pub trait PositionSource: Send + Sync {
+    async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError>;
+
+    async fn get_position(&self, id: &str) -> Result<Option<Position>, LiquidationError>;
 
-pub struct PositionManager {}
+    // Returns false if `position.id` was already closed.
+    async fn upsert_position(&self, position: &Position) -> Result<bool, LiquidationError>;
+
+    // Returns false if there was no open position with this id.
+    async fn close_position(&self, id: &str) -> Result<bool, LiquidationError>;
+
+    // Every change made after this returns, if the source can publish them;
+    // `None` means readers must reload `get_open_positions` instead.
+    async fn subscribe_changes(
+        &self,
+    ) -> Result<Option<BoxStream<'static, PositionChange>>, LiquidationError> {
+        Ok(None)
+    }
+}
+
+pub struct PositionManager {
+    source: Arc<dyn PositionSource>,
+}
+
+impl Default for PositionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl PositionManager {
+    // In-memory source seeded with one undercollateralized demo position.
     pub fn new() -> Self {
-        Self {}
+        Self::with_source(Arc::new(MockPositionSource::with_demo_position()))
+    }
+
+    pub fn with_source(source: Arc<dyn PositionSource>) -> Self {
+        Self { source }
+    }
+
+    // POSITION_SOURCE=onchain follows the program's accounts (spawning the
+    // loader, so call from within the runtime); otherwise Postgres when
+    // DATABASE_URL is set, cached in Redis when REDIS_URL is also set; else
+    // the in-memory mock.
+    pub fn from_env() -> Result<Self, LiquidationError> {
+        if std::env::var("POSITION_SOURCE").as_deref() == Ok("onchain") {
+            let source = Arc::new(OnChainPositionSource::from_env()?);
+            tokio::spawn(source.clone().start());
+            return Ok(Self::with_source(source));
+        }
+
+        let Some(pool) = pool_from_env()? else {
+            return Ok(Self::new());
+        };
+        let postgres = Arc::new(PostgresPositionSource::new(pool));
+        Ok(match std::env::var("REDIS_URL") {
+            Ok(redis_url) => Self::with_source(Arc::new(RedisPositionSource::new(
+                redis::Client::open(redis_url)?,
+                postgres,
+            ))),
+            Err(_) => Self::with_source(postgres),
+        })
     }
 
     pub async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
-        // TODO: later: load from Redis / Postgres.
-        // For now: return one undercollateralized BTC-PERP position so we see the engine work.
+        self.source.get_open_positions().await
+    }
+
+    pub async fn get_position(&self, id: &str) -> Result<Option<Position>, LiquidationError> {
+        self.source.get_position(id).await
+    }
+
+    pub async fn upsert_position(&self, position: &Position) -> Result<bool, LiquidationError> {
+        // Unknown markets have no decimals to do margin math with.
+        position.market()?;
+        self.source.upsert_position(position).await
+    }
+
+    pub async fn close_position(&self, id: &str) -> Result<bool, LiquidationError> {
+        self.source.close_position(id).await
+    }
+
+    pub async fn subscribe_changes(
+        &self,
+    ) -> Result<Option<BoxStream<'static, PositionChange>>, LiquidationError> {
+        self.source.subscribe_changes().await
+    }
+}
 
-        let owner = Pubkey::new_unique();
+// ------------- IN-MEMORY SOURCE -------------
 
+#[derive(Default)]
+pub struct MockPositionSource {
+    // id -> (position, closed)
+    positions: Mutex<HashMap<String, (Position, bool)>>,
+}
+
+impl MockPositionSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // One undercollateralized BTC-PERP position so we see the engine work.
+    pub fn with_demo_position() -> Self {
+        let source = Self::new();
         let pos = Position {
             id: "pos-1".to_string(),
-            owner,
-            symbol: "BTC-PERP".to_string(),
-            size: 1.0,          // 1 contract
+            owner: Pubkey::new_unique(),
+            market_index: 0,    // BTC-PERP
+            size: 100_000_000,  // 1 BTC in sats
             is_long: true,
-            entry_price: 60_000.0,
-            collateral: 50.0,   // very small collateral, so margin will be tiny
+            entry_price: 60_000_000_000, // 60,000 at 6 price decimals
+            collateral: 50_000_000, // $50: very small collateral, so margin will be tiny
             leverage: 500,      // high leverage -> low maintenance margin ratio
         };
+        source
+            .positions
+            .lock()
+            .unwrap()
+            .insert(pos.id.clone(), (pos, false));
+        source
+    }
+}
+
+#[async_trait]
+impl PositionSource for MockPositionSource {
+    async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
+        let positions = self.positions.lock().unwrap();
+        Ok(positions
+            .values()
+            .filter(|(_, closed)| !closed)
+            .map(|(position, _)| position.clone())
+            .collect())
+    }
+
+    async fn get_position(&self, id: &str) -> Result<Option<Position>, LiquidationError> {
+        let positions = self.positions.lock().unwrap();
+        Ok(positions
+            .get(id)
+            .filter(|(_, closed)| !closed)
+            .map(|(position, _)| position.clone()))
+    }
+
+    async fn upsert_position(&self, position: &Position) -> Result<bool, LiquidationError> {
+        let mut positions = self.positions.lock().unwrap();
+        if matches!(positions.get(&position.id), Some((_, true))) {
+            return Ok(false);
+        }
+        positions.insert(position.id.clone(), (position.clone(), false));
+        Ok(true)
+    }
+
+    async fn close_position(&self, id: &str) -> Result<bool, LiquidationError> {
+        let mut positions = self.positions.lock().unwrap();
+        match positions.get_mut(id) {
+            Some((_, closed)) if !*closed => {
+                *closed = true;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+// ------------- POSTGRES SOURCE -------------
+
+const SELECT_COLUMNS: &str =
+    "SELECT id, owner, market_index, size, is_long, entry_price, collateral, leverage \
+     FROM positions";
+
+// A closed row is left untouched, so a late update cannot reopen it.
+const UPSERT_SQL: &str = "
+    INSERT INTO positions
+        (id, owner, market_index, size, is_long, entry_price, collateral, leverage)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    ON CONFLICT (id) DO UPDATE SET
+        owner = EXCLUDED.owner,
+        market_index = EXCLUDED.market_index,
+        size = EXCLUDED.size,
+        is_long = EXCLUDED.is_long,
+        entry_price = EXCLUDED.entry_price,
+        collateral = EXCLUDED.collateral,
+        leverage = EXCLUDED.leverage,
+        updated_at = NOW()
+    WHERE NOT positions.closed";
+
+const CLOSE_SQL: &str = "
+    UPDATE positions SET closed = TRUE, closed_at = NOW(), updated_at = NOW()
+    WHERE id = $1 AND NOT closed";
+
+// `positions` table (db/migrations/0003_positions.sql).
+pub struct PostgresPositionSource {
+    pool: Pool,
+}
+
+impl PostgresPositionSource {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Object, LiquidationError> {
+        self.pool.get().await.map_err(db_err)
+    }
+}
+
+#[async_trait]
+impl PositionSource for PostgresPositionSource {
+    async fn get_open_positions(&self) -> Result<Vec<Position>, LiquidationError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(&format!("{SELECT_COLUMNS} WHERE NOT closed"), &[])
+            .await
+            .map_err(db_err)?;
+        rows.iter().map(position_from_row).collect()
+    }
+
+    async fn get_position(&self, id: &str) -> Result<Option<Position>, LiquidationError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(&format!("{SELECT_COLUMNS} WHERE id = $1 AND NOT closed"), &[&id])
+            .await
+            .map_err(db_err)?;
+        row.as_ref().map(position_from_row).transpose()
+    }
 
-        Ok(vec![pos])
+    async fn upsert_position(&self, position: &Position) -> Result<bool, LiquidationError> {
+        let client = self.client().await?;
+        let written = client
+            .execute(
+                UPSERT_SQL,
+                &[
+                    &position.id,
+                    &position.owner.to_string(),
+                    &(position.market_index as i32),
+                    &to_bigint(position.size, "size")?,
+                    &position.is_long,
+                    &to_bigint(position.entry_price, "entry_price")?,
+                    &to_bigint(position.collateral, "collateral")?,
+                    &(position.leverage as i32),
+                ],
+            )
+            .await
+            .map_err(db_err)?;
+        Ok(written == 1)
+    }
+
+    async fn close_position(&self, id: &str) -> Result<bool, LiquidationError> {
+        let client = self.client().await?;
+        let closed = client.execute(CLOSE_SQL, &[&id]).await.map_err(db_err)?;
+        Ok(closed == 1)
+    }
+}
+
+fn position_from_row(row: &Row) -> Result<Position, LiquidationError> {
+    let owner: String = row.try_get("owner").map_err(db_err)?;
+    let market_index: i32 = row.try_get("market_index").map_err(db_err)?;
+    let leverage: i32 = row.try_get("leverage").map_err(db_err)?;
+
+    Ok(Position {
+        id: row.try_get("id").map_err(db_err)?,
+        owner: Pubkey::from_str(&owner)
+            .map_err(|e| LiquidationError::Decode(format!("invalid owner {owner}: {e}")))?,
+        market_index: u16::try_from(market_index)
+            .map_err(|_| LiquidationError::Decode(format!("invalid market_index {market_index}")))?,
+        size: from_bigint(row, "size")?,
+        is_long: row.try_get("is_long").map_err(db_err)?,
+        entry_price: from_bigint(row, "entry_price")?,
+        collateral: from_bigint(row, "collateral")?,
+        leverage: u16::try_from(leverage)
+            .map_err(|_| LiquidationError::Decode(format!("invalid leverage {leverage}")))?,
+    })
+}
+
+// BIGINT is signed; amounts past i64::MAX are rejected rather than wrapped.
+fn to_bigint(value: u64, column: &str) -> Result<i64, LiquidationError> {
+    i64::try_from(value)
+        .map_err(|_| LiquidationError::Db(format!("{column} out of range: {value}")))
+}
+
+fn from_bigint(row: &Row, column: &str) -> Result<u64, LiquidationError> {
+    let value: i64 = row.try_get(column).map_err(db_err)?;
+    u64::try_from(value)
+        .map_err(|_| LiquidationError::Decode(format!("negative {column}: {value}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::load_test_markets;
+
+    #[tokio::test]
+    async fn closed_positions_are_hidden_and_stay_closed() {
+        load_test_markets();
+        let manager = PositionManager::new();
+        let mut position = manager.get_position("pos-1").await.unwrap().unwrap();
+        let owner = position.owner;
+
+        // Stable across reads, unlike a fresh mock every tick.
+        let open = manager.get_open_positions().await.unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].owner, owner);
+
+        position.collateral += 1_000_000;
+        assert!(manager.upsert_position(&position).await.unwrap());
+        let updated = manager.get_position("pos-1").await.unwrap().unwrap();
+        assert_eq!(updated.collateral, position.collateral);
+
+        assert!(manager.close_position("pos-1").await.unwrap());
+        assert!(!manager.close_position("pos-1").await.unwrap());
+        assert!(manager.get_position("pos-1").await.unwrap().is_none());
+        assert!(manager.get_open_positions().await.unwrap().is_empty());
+        assert!(!manager.upsert_position(&position).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn upsert_rejects_unknown_market() {
+        let manager = PositionManager::with_source(Arc::new(MockPositionSource::new()));
+        let position = Position {
+            id: "pos-x".to_string(),
+            owner: Pubkey::new_unique(),
+            market_index: 99,
+            size: 1,
+            is_long: true,
+            entry_price: 1,
+            collateral: 1,
+            leverage: 1,
+        };
+        assert!(manager.upsert_position(&position).await.is_err());
     }
 }
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use tokio::time::{interval, sleep, Duration, Instant};
+
+use liquidation_engine::MAX_ORACLE_STALENESS_SECS;
+use liquidation_math::BPS_DENOM;
+
+use crate::accounts::{decode_market, market_address, PROGRAM_ID};
+use crate::errors::LiquidationError;
+use crate::instructions::update_price_feed;
+use crate::models::{pubkey_string, MARKETS};
+use crate::oracle::{unix_now, PriceOracle};
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_CHECK_INTERVAL_MS: u64 = 1_000;
+// Well inside the program's 30s staleness limit, leaving room to confirm.
+const DEFAULT_HEARTBEAT_SECS: i64 = 20;
+const DEFAULT_DEVIATION_BPS: u64 = 50;
+const RELOAD_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PusherConfig {
+    pub check_interval: Duration,
+    pub heartbeat_secs: i64,  // push at least this often, even if the price is flat
+    pub deviation_bps: u64,   // push early once the price moved this far from the last push
+}
+
+impl Default for PusherConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_millis(DEFAULT_CHECK_INTERVAL_MS),
+            heartbeat_secs: DEFAULT_HEARTBEAT_SECS,
+            deviation_bps: DEFAULT_DEVIATION_BPS,
+        }
+    }
+}
+
+// One market's feed and the accounts `update_price_feed` needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushTarget {
+    pub symbol: &'static str,
+    pub market: Pubkey,
+    pub insurance_fund: Pubkey,
+    pub price_feed: Pubkey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushReason {
+    First,
+    Heartbeat,
+    Deviation,
+}
+
+// Per-feed push state, served by `GET /oracle/pusher`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedHeartbeat {
+    pub symbol: String,
+    #[serde(with = "pubkey_string")]
+    pub price_feed: Pubkey,
+    pub last_price: Option<u64>,     // last confirmed push, market price decimals
+    pub last_pushed_at: Option<i64>, // unix secs the push was sent
+    pub pushes: u64,
+    pub failures: u64,
+    pub last_latency_ms: Option<u64>, // send to confirmation
+    pub max_latency_ms: u64,
+    pub avg_latency_ms: u64,
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    total_latency_ms: u64,
+}
+
+impl FeedHeartbeat {
+    fn new(target: &PushTarget) -> Self {
+        Self {
+            symbol: target.symbol.to_string(),
+            price_feed: target.price_feed,
+            last_price: None,
+            last_pushed_at: None,
+            pushes: 0,
+            failures: 0,
+            last_latency_ms: None,
+            max_latency_ms: 0,
+            avg_latency_ms: 0,
+            last_error: None,
+            total_latency_ms: 0,
+        }
+    }
+
+    fn record_push(&mut self, price: u64, sent_at: i64, latency_ms: u64) {
+        self.last_price = Some(price);
+        self.last_pushed_at = Some(sent_at);
+        self.pushes += 1;
+        self.last_latency_ms = Some(latency_ms);
+        self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+        self.total_latency_ms += latency_ms;
+        self.avg_latency_ms = self.total_latency_ms / self.pushes;
+        self.last_error = None;
+    }
+
+    fn record_failure(&mut self, error: String) {
+        self.failures += 1;
+        self.last_error = Some(error);
+    }
+
+    // Whether `price`, observed at `now`, should be pushed.
+    pub fn push_reason(&self, price: u64, now: i64, config: &PusherConfig) -> Option<PushReason> {
+        let (Some(last_price), Some(last_pushed_at)) = (self.last_price, self.last_pushed_at) else {
+            return Some(PushReason::First);
+        };
+        if now - last_pushed_at >= config.heartbeat_secs {
+            return Some(PushReason::Heartbeat);
+        }
+        let moved = price.abs_diff(last_price) as u128 * BPS_DENOM as u128;
+        let threshold = last_price as u128 * config.deviation_bps as u128;
+        (moved >= threshold).then_some(PushReason::Deviation)
+    }
+}
+
+// Lands a new price on chain.
+#[async_trait]
+pub trait PriceSubmitter: Send + Sync {
+    async fn submit(&self, target: &PushTarget, price: u64) -> Result<(), LiquidationError>;
+}
+
+// Sends `update_price_feed` signed by the market admin key and waits for confirmation.
+pub struct RpcPriceSubmitter {
+    rpc: RpcClient,
+    authority: Keypair,
+    program_id: Pubkey,
+}
+
+impl RpcPriceSubmitter {
+    pub fn new(rpc: RpcClient, authority: Keypair, program_id: Pubkey) -> Self {
+        Self {
+            rpc,
+            authority,
+            program_id,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSubmitter for RpcPriceSubmitter {
+    async fn submit(&self, target: &PushTarget, price: u64) -> Result<(), LiquidationError> {
+        let ix = update_price_feed(
+            &self.program_id,
+            &target.price_feed,
+            &target.market,
+            &target.insurance_fund,
+            &self.authority.pubkey(),
+            price,
+        );
+        let blockhash = self.rpc.get_latest_blockhash().await?;
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.authority.pubkey()),
+            &[&self.authority],
+            blockhash,
+        );
+        self.rpc.send_and_confirm_transaction(&tx).await?;
+        Ok(())
+    }
+}
+
+// Keeps every bound `PriceFeed` fresh from an off-chain `PriceOracle`: pushes
+// on a heartbeat so `last_updated` never ages past the program's limit, and
+// early when the price moves more than `deviation_bps`.
+pub struct PricePusher {
+    oracle: Arc<dyn PriceOracle>,
+    submitter: Arc<dyn PriceSubmitter>,
+    rpc: RpcClient,
+    program_id: Pubkey,
+    config: PusherConfig,
+    heartbeats: RwLock<HashMap<String, FeedHeartbeat>>,
+}
+
+impl PricePusher {
+    pub fn new(
+        oracle: Arc<dyn PriceOracle>,
+        submitter: Arc<dyn PriceSubmitter>,
+        rpc: RpcClient,
+        program_id: Pubkey,
+        config: PusherConfig,
+    ) -> Self {
+        Self {
+            oracle,
+            submitter,
+            rpc,
+            program_id,
+            config,
+            heartbeats: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Enabled by PRICE_PUSHER_KEYPAIR (the markets' admin key). RPC_URL,
+    // LIQUIDATION_PROGRAM_ID, PRICE_PUSHER_HEARTBEAT_SECS, PRICE_PUSHER_DEVIATION_BPS
+    // and PRICE_PUSHER_INTERVAL_MS are optional.
+    pub fn from_env(oracle: Arc<dyn PriceOracle>) -> Result<Option<Self>, LiquidationError> {
+        let Ok(keypair_path) = std::env::var("PRICE_PUSHER_KEYPAIR") else {
+            return Ok(None);
+        };
+        // Pushing the feeds' own price back to them would never refresh anything.
+        if oracle.name() == "onchain" {
+            return Err(LiquidationError::Oracle(
+                "the price pusher needs an off-chain oracle source".to_string(),
+            ));
+        }
+        let authority = read_keypair_file(&keypair_path)
+            .map_err(|e| LiquidationError::Other(format!("read {keypair_path}: {e}")))?;
+
+        let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+        let program_id = std::env::var("LIQUIDATION_PROGRAM_ID")
+            .unwrap_or_else(|_| PROGRAM_ID.to_string());
+        let program_id = Pubkey::from_str(&program_id)
+            .map_err(|e| LiquidationError::Other(format!("invalid program id: {e}")))?;
+
+        let mut config = PusherConfig::default();
+        if let Some(secs) = env_number("PRICE_PUSHER_HEARTBEAT_SECS") {
+            config.heartbeat_secs = secs;
+        }
+        if let Some(bps) = env_number("PRICE_PUSHER_DEVIATION_BPS") {
+            config.deviation_bps = bps;
+        }
+        if let Some(ms) = env_number("PRICE_PUSHER_INTERVAL_MS") {
+            config.check_interval = Duration::from_millis(ms);
+        }
+        if config.heartbeat_secs >= MAX_ORACLE_STALENESS_SECS {
+            eprintln!(
+                "[PUSHER] Heartbeat of {}s lets feeds go stale (limit {}s)",
+                config.heartbeat_secs, MAX_ORACLE_STALENESS_SECS
+            );
+        }
+
+        let submitter = RpcPriceSubmitter::new(
+            RpcClient::new(rpc_url.clone()),
+            authority,
+            program_id,
+        );
+        Ok(Some(Self::new(
+            oracle,
+            Arc::new(submitter),
+            RpcClient::new(rpc_url),
+            program_id,
+            config,
+        )))
+    }
+
+    // Waits until at least one market has a feed bound, then pushes forever.
+    // Feeds bound later are picked up after a restart.
+    pub async fn start(self: Arc<Self>) {
+        let targets = loop {
+            match self.load_targets().await {
+                Ok(targets) if !targets.is_empty() => break targets,
+                Ok(_) => eprintln!("[PUSHER] No market has a price feed bound yet"),
+                Err(e) => eprintln!("[PUSHER] Error loading markets: {e}"),
+            }
+            sleep(RELOAD_DELAY).await;
+        };
+        println!("[PUSHER] Pushing {} price feeds", targets.len());
+
+        let mut timer = interval(self.config.check_interval);
+        loop {
+            timer.tick().await;
+            self.run_once(&targets).await;
+        }
+    }
+
+    // Markets with a bound `price_feed`, read from chain.
+    pub async fn load_targets(&self) -> Result<Vec<PushTarget>, LiquidationError> {
+        let addresses: Vec<Pubkey> = MARKETS
+            .iter()
+            .map(|m| market_address(&self.program_id, m.market_index))
+            .collect();
+        let accounts = self.rpc.get_multiple_accounts(&addresses).await?;
+
+        let mut targets = Vec::new();
+        for ((info, address), account) in MARKETS.iter().zip(addresses).zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            let market = decode_market(&account.data)?;
+            if market.price_feed == Pubkey::default() {
+                continue;
+            }
+            targets.push(PushTarget {
+                symbol: info.symbol,
+                market: address,
+                insurance_fund: market.insurance_fund,
+                price_feed: market.price_feed,
+            });
+        }
+        Ok(targets)
+    }
+
+    // Checks every feed once; failures are recorded and retried next tick.
+    pub async fn run_once(&self, targets: &[PushTarget]) {
+        for target in targets {
+            if let Err(e) = self.check_feed(target).await {
+                eprintln!("[PUSHER] {}: {e}", target.symbol);
+            }
+        }
+    }
+
+    async fn check_feed(&self, target: &PushTarget) -> Result<(), LiquidationError> {
+        let price = self.oracle.get_mark_price(target.symbol).await?;
+        let now = unix_now();
+        let reason = self
+            .heartbeats
+            .write()
+            .unwrap()
+            .entry(target.symbol.to_string())
+            .or_insert_with(|| FeedHeartbeat::new(target))
+            .push_reason(price, now, &self.config);
+        let Some(reason) = reason else {
+            return Ok(());
+        };
+
+        let started = Instant::now();
+        let result = self.submitter.submit(target, price).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let mut heartbeats = self.heartbeats.write().unwrap();
+        let heartbeat = heartbeats
+            .entry(target.symbol.to_string())
+            .or_insert_with(|| FeedHeartbeat::new(target));
+        match result {
+            Ok(()) => {
+                heartbeat.record_push(price, now, latency_ms);
+                println!(
+                    "[PUSHER] {} -> {price} ({reason:?}, {latency_ms} ms)",
+                    target.symbol
+                );
+                Ok(())
+            }
+            Err(e) => {
+                heartbeat.record_failure(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    pub fn heartbeats(&self) -> Vec<FeedHeartbeat> {
+        let mut heartbeats: Vec<FeedHeartbeat> =
+            self.heartbeats.read().unwrap().values().cloned().collect();
+        heartbeats.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        heartbeats
+    }
+}
+
+fn env_number<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::models::load_test_markets;
+    use crate::oracle::StaticPriceOracle;
+
+    #[derive(Default)]
+    struct RecordingSubmitter {
+        pushed: Mutex<Vec<(&'static str, u64)>>,
+        fail: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl PriceSubmitter for RecordingSubmitter {
+        async fn submit(&self, target: &PushTarget, price: u64) -> Result<(), LiquidationError> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(LiquidationError::Other("blockhash not found".to_string()));
+            }
+            self.pushed.lock().unwrap().push((target.symbol, price));
+            Ok(())
+        }
+    }
+
+    fn target() -> PushTarget {
+        PushTarget {
+            symbol: "BTC-PERP",
+            market: Pubkey::new_unique(),
+            insurance_fund: Pubkey::new_unique(),
+            price_feed: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn pushes_on_first_heartbeat_and_deviation() {
+        let config = PusherConfig::default();
+        let mut heartbeat = FeedHeartbeat::new(&target());
+        assert_eq!(heartbeat.push_reason(100_000, 0, &config), Some(PushReason::First));
+
+        heartbeat.record_push(100_000, 1_000, 40);
+        // 0.49% moved, heartbeat not due
+        assert_eq!(heartbeat.push_reason(100_490, 1_019, &config), None);
+        assert_eq!(heartbeat.push_reason(99_500, 1_019, &config), Some(PushReason::Deviation));
+        assert_eq!(heartbeat.push_reason(100_000, 1_020, &config), Some(PushReason::Heartbeat));
+    }
+
+    #[tokio::test]
+    async fn run_once_tracks_pushes_failures_and_latency() {
+        load_test_markets();
+        let oracle = Arc::new(StaticPriceOracle::default());
+        let submitter = Arc::new(RecordingSubmitter::default());
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        let pusher = PricePusher::new(
+            oracle.clone(),
+            submitter.clone(),
+            rpc,
+            Pubkey::new_unique(),
+            PusherConfig::default(),
+        );
+        let targets = [target()];
+
+        pusher.run_once(&targets).await;
+        // Unchanged price inside the heartbeat: nothing to send
+        pusher.run_once(&targets).await;
+        assert_eq!(*submitter.pushed.lock().unwrap(), vec![("BTC-PERP", 60_000_000_000)]);
+
+        oracle.set_price("BTC-PERP", 61_000.0).unwrap();
+        submitter.fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        pusher.run_once(&targets).await;
+
+        let heartbeat = &pusher.heartbeats()[0];
+        assert_eq!(heartbeat.pushes, 1);
+        assert_eq!(heartbeat.failures, 1);
+        assert_eq!(heartbeat.last_price, Some(60_000_000_000));
+        assert!(heartbeat.last_latency_ms.is_some());
+        assert_eq!(heartbeat.last_error.as_deref(), Some("Other: blockhash not found"));
+    }
+}
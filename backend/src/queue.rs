@@ -5,13 +5,23 @@ use std::time::{Duration, Instant};
 
 use crate::errors::LiquidationError;
 use crate::models::{LiquidationCandidate, Position};
+use crate::oracle::unix_now;
 
+// A failed attempt is not retried for this long.
 const COOLDOWN: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone)]
 struct QueueItem {
     candidate: LiquidationCandidate,
-    last_attempt: Instant,
+    last_attempt: Option<Instant>,
+    // Set when the feed was stale on chain: not due again until the
+    // candidate is re-checked at a price published after this.
+    stale_price_time: Option<i64>,
+    // Unix time the position's at-risk grace window ends on chain; not due
+    // before then.
+    held_until: Option<i64>,
+    // First time the candidate was left to the backstop LP.
+    backstop_since: Option<Instant>,
 }
 
 impl PartialEq for QueueItem {
@@ -23,13 +33,16 @@ impl Eq for QueueItem {}
 
 impl PartialOrd for QueueItem {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        // lower margin ratio => higher priority
-        other.candidate.margin_ratio.partial_cmp(&self.candidate.margin_ratio)
+        Some(self.cmp(other))
     }
 }
 impl Ord for QueueItem {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+        // lower margin ratio => higher priority
+        other
+            .candidate
+            .margin_ratio_bps
+            .cmp(&self.candidate.margin_ratio_bps)
     }
 }
 
@@ -37,6 +50,12 @@ pub struct LiquidationQueue {
     heap: Mutex<BinaryHeap<QueueItem>>,
 }
 
+impl Default for LiquidationQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LiquidationQueue {
     pub fn new() -> Self {
         Self {
@@ -44,24 +63,122 @@ impl LiquidationQueue {
         }
     }
 
+    // One entry per position: re-queueing refreshes the candidate but keeps
+    // its attempt state, so the cooldown survives every tick's re-check.
     pub fn enqueue(
         &self,
         position: Position,
-        mark_price: f64,
-        margin_ratio: f64,
+        mark_price: u64,
+        price_time: i64,
+        margin_ratio_bps: u64,
+        position_value: u128,
     ) -> Result<(), LiquidationError> {
         let mut heap = self.heap.lock().unwrap();
+        let (mut last_attempt, mut stale_price_time, mut held_until) = (None, None, None);
+        let mut backstop_since = None;
+        heap.retain(|item| {
+            if item.candidate.position.id == position.id {
+                last_attempt = item.last_attempt;
+                stale_price_time = item.stale_price_time;
+                held_until = item.held_until;
+                backstop_since = item.backstop_since;
+                false
+            } else {
+                true
+            }
+        });
         heap.push(QueueItem {
             candidate: LiquidationCandidate {
                 position,
                 mark_price,
-                margin_ratio,
+                price_time,
+                margin_ratio_bps,
+                position_value,
             },
-            last_attempt: Instant::now(),
+            last_attempt,
+            stale_price_time,
+            held_until,
+            backstop_since,
         });
         Ok(())
     }
 
+    // Candidates not attempted within COOLDOWN (or waiting on a newer price
+    // or a grace window), lowest margin first. They stay queued until `remove`d.
+    pub fn due(&self) -> Vec<LiquidationCandidate> {
+        let now = unix_now();
+        let heap = self.heap.lock().unwrap();
+        let mut items: Vec<&QueueItem> = heap
+            .iter()
+            .filter(|item| item.last_attempt.is_none_or(|at| at.elapsed() >= COOLDOWN))
+            .filter(|item| {
+                item.stale_price_time.is_none_or(|stale| item.candidate.price_time > stale)
+            })
+            .filter(|item| item.held_until.is_none_or(|until| now >= until))
+            .collect();
+        items.sort_by(|a, b| b.cmp(a));
+        items.into_iter().map(|item| item.candidate.clone()).collect()
+    }
+
+    pub fn mark_attempted(&self, id: &str) {
+        self.update(id, |item| item.last_attempt = Some(Instant::now()));
+    }
+
+    // Holds the candidate back until it is re-queued at a newer price,
+    // then lets it go without waiting out the cooldown.
+    pub fn await_price_refresh(&self, id: &str) {
+        self.update(id, |item| {
+            item.stale_price_time = Some(item.candidate.price_time);
+            item.last_attempt = None;
+        });
+    }
+
+    // Holds the candidate back until `until` (unix secs), when its grace
+    // window ends, then lets it go without waiting out the cooldown.
+    pub fn hold_until(&self, id: &str, until: i64) {
+        self.update(id, |item| {
+            item.held_until = Some(until);
+            item.last_attempt = None;
+        });
+    }
+
+    // How long the candidate has been left to the backstop LP, starting the
+    // clock on the first call.
+    pub fn backstop_wait(&self, id: &str) -> Duration {
+        let now = Instant::now();
+        let mut since = now;
+        self.update(id, |item| since = *item.backstop_since.get_or_insert(now));
+        now.duration_since(since)
+    }
+
+    fn update(&self, id: &str, mut f: impl FnMut(&mut QueueItem)) {
+        let mut heap = self.heap.lock().unwrap();
+        *heap = heap
+            .drain()
+            .map(|mut item| {
+                if item.candidate.position.id == id {
+                    f(&mut item);
+                }
+                item
+            })
+            .collect();
+    }
+
+    // Drops a position that was liquidated or is healthy again.
+    pub fn remove(&self, id: &str) -> Option<LiquidationCandidate> {
+        let mut heap = self.heap.lock().unwrap();
+        let mut removed = None;
+        heap.retain(|item| {
+            if item.candidate.position.id == id {
+                removed = Some(item.candidate.clone());
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
     pub fn pop(&self) -> Option<LiquidationCandidate> {
         let mut heap = self.heap.lock().unwrap();
         heap.pop().map(|item| item.candidate)
@@ -81,3 +198,76 @@ impl LiquidationQueue {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn position(id: &str) -> Position {
+        Position {
+            id: id.to_string(),
+            owner: Pubkey::new_unique(),
+            market_index: 1,
+            size: 1_000_000,
+            is_long: true,
+            entry_price: 3_000_000_000,
+            collateral: 300_000_000,
+            leverage: 10,
+        }
+    }
+
+    fn due_ids(queue: &LiquidationQueue) -> Vec<String> {
+        queue.due().into_iter().map(|c| c.position.id).collect()
+    }
+
+    #[test]
+    fn keeps_one_entry_per_position_and_cools_down_attempts() {
+        let queue = LiquidationQueue::new();
+        queue.enqueue(position("a"), 1, 0, 400, 10).unwrap();
+        queue.enqueue(position("b"), 1, 0, 100, 10).unwrap();
+        queue.enqueue(position("a"), 1, 0, 300, 10).unwrap();
+        assert_eq!(due_ids(&queue), vec!["b".to_string(), "a".to_string()]);
+
+        // A failed attempt stays queued but is skipped, even after a re-check.
+        queue.mark_attempted("b");
+        queue.enqueue(position("b"), 1, 0, 50, 10).unwrap();
+        assert_eq!(due_ids(&queue), vec!["a".to_string()]);
+        assert_eq!(queue.snapshot_ids().len(), 2);
+
+        assert_eq!(queue.remove("a").unwrap().margin_ratio_bps, 300);
+        assert!(queue.remove("a").is_none());
+        assert!(due_ids(&queue).is_empty());
+    }
+
+    #[test]
+    fn stale_price_failures_wait_for_a_newer_price() {
+        let queue = LiquidationQueue::new();
+        queue.enqueue(position("a"), 1, 100, 300, 10).unwrap();
+        queue.mark_attempted("a");
+        queue.await_price_refresh("a");
+        assert!(due_ids(&queue).is_empty());
+
+        queue.enqueue(position("a"), 1, 100, 300, 10).unwrap();
+        assert!(due_ids(&queue).is_empty());
+
+        // No cooldown once the price moved on.
+        queue.enqueue(position("a"), 1, 101, 300, 10).unwrap();
+        assert_eq!(due_ids(&queue), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn grace_holds_last_until_the_window_ends() {
+        let queue = LiquidationQueue::new();
+        queue.enqueue(position("a"), 1, 0, 300, 10).unwrap();
+        queue.enqueue(position("b"), 1, 0, 300, 10).unwrap();
+        queue.mark_attempted("a");
+        queue.mark_attempted("b");
+        queue.hold_until("a", unix_now() + 60);
+        queue.hold_until("b", unix_now() - 1);
+
+        // Re-checks keep the hold; an elapsed one skips the cooldown.
+        queue.enqueue(position("a"), 1, 1, 300, 10).unwrap();
+        assert_eq!(due_ids(&queue), vec!["b".to_string()]);
+    }
+}
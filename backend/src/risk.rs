@@ -0,0 +1,66 @@
+use serde::Serialize;
+
+use crate::errors::LiquidationError;
+use crate::models::{scale_down, Position};
+
+// Same math as the program's `simulate_liquidation_price`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionRisk {
+    pub position_id: String,
+    pub symbol: Option<&'static str>,
+    pub is_long: bool,
+    // Longs are liquidatable below, shorts above.
+    pub liquidation_price: f64,
+    pub bankruptcy_price: f64,
+}
+
+pub fn position_risk(position: &Position) -> Result<PositionRisk, LiquidationError> {
+    let market = position.market()?;
+    let value_scale = market.value_scale()?;
+    let onchain = position.to_onchain();
+
+    let liquidation_price = liquidation_engine::liquidation_price(
+        &onchain,
+        &liquidation_engine::MAINTENANCE_TIERS,
+        value_scale,
+    )
+    .map_err(|e| LiquidationError::Other(format!("liquidation price: {e}")))?;
+    let bankruptcy_price = liquidation_engine::bankruptcy_price(&onchain, value_scale)
+        .map_err(|e| LiquidationError::Other(format!("bankruptcy price: {e}")))?;
+
+    Ok(PositionRisk {
+        position_id: position.id.clone(),
+        symbol: position.symbol(),
+        is_long: position.is_long,
+        liquidation_price: scale_down(liquidation_price, market.price_decimals),
+        bankruptcy_price: scale_down(bankruptcy_price, market.price_decimals),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::models::load_test_markets;
+
+    #[test]
+    fn long_liquidation_price_sits_between_bankruptcy_and_entry() {
+        load_test_markets();
+        let position = Position {
+            id: "pos-1".to_string(),
+            owner: Pubkey::new_unique(),
+            market_index: 1, // ETH-PERP
+            size: 1_000_000,          // 1 ETH
+            is_long: true,
+            entry_price: 100_000_000, // 100
+            collateral: 10_000_000,   // 10
+            leverage: 10,
+        };
+
+        let risk = position_risk(&position).unwrap();
+        assert_eq!(risk.bankruptcy_price, 90.0);
+        // 90 / (1 - 2.5%)
+        assert!((risk.liquidation_price - 92.307693).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,75 @@
+// Fixed-point helpers with an explicit rounding direction.
+//
+// Rounding policy: whenever a division loses precision, round against the
+// position owner so residue never leaks from the protocol across many
+// liquidations:
+//
+// * position value (notional)   -> Ceil  (lower margin ratio, larger reward)
+// * realized / unrealized PnL   -> Floor (toward -inf: gains smaller, losses larger)
+// * margin ratio (bps)          -> Floor (liquidatable no later than exact math)
+// * liquidator reward           -> Ceil
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+}
+
+// `a * b / denom` rounded as requested. `None` on overflow or `denom == 0`.
+pub fn mul_div_u128(a: u128, b: u128, denom: u128, rounding: Rounding) -> Option<u128> {
+    let product = a.checked_mul(b)?;
+    div_u128(product, denom, rounding)
+}
+
+pub fn div_u128(numerator: u128, denom: u128, rounding: Rounding) -> Option<u128> {
+    let quotient = numerator.checked_div(denom)?;
+    match rounding {
+        // quotient * denom <= numerator, so this cannot overflow
+        Rounding::Ceil if quotient * denom != numerator => quotient.checked_add(1),
+        _ => Some(quotient),
+    }
+}
+
+// `a * b / denom` for signed values. `denom` must be positive; Floor rounds
+// toward negative infinity and Ceil toward positive infinity.
+pub fn mul_div_i128(a: i128, b: i128, denom: i128, rounding: Rounding) -> Option<i128> {
+    let product = a.checked_mul(b)?;
+    div_i128(product, denom, rounding)
+}
+
+pub fn div_i128(numerator: i128, denom: i128, rounding: Rounding) -> Option<i128> {
+    if denom <= 0 {
+        return None;
+    }
+    let floor = numerator.checked_div_euclid(denom)?;
+    match rounding {
+        Rounding::Floor => Some(floor),
+        Rounding::Ceil if numerator.rem_euclid(denom) != 0 => floor.checked_add(1),
+        Rounding::Ceil => Some(floor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_div_u128_rounding() {
+        assert_eq!(div_u128(7, 2, Rounding::Floor), Some(3));
+        assert_eq!(div_u128(7, 2, Rounding::Ceil), Some(4));
+        assert_eq!(div_u128(8, 2, Rounding::Ceil), Some(4));
+        assert_eq!(div_u128(1, 0, Rounding::Floor), None);
+        assert_eq!(mul_div_u128(u128::MAX, 2, 3, Rounding::Floor), None);
+    }
+
+    #[test]
+    fn test_div_i128_rounding() {
+        assert_eq!(div_i128(7, 2, Rounding::Floor), Some(3));
+        assert_eq!(div_i128(7, 2, Rounding::Ceil), Some(4));
+        assert_eq!(div_i128(-7, 2, Rounding::Floor), Some(-4));
+        assert_eq!(div_i128(-7, 2, Rounding::Ceil), Some(-3));
+        assert_eq!(div_i128(-8, 2, Rounding::Floor), Some(-4));
+        assert_eq!(div_i128(1, 0, Rounding::Floor), None);
+        assert_eq!(div_i128(1, -1, Rounding::Floor), None);
+    }
+}
@@ -0,0 +1,211 @@
+// Margin math shared by the on-chain program and the off-chain backend, so
+// both sides agree to the unit on whether a position is liquidatable.
+//
+// Everything is integer fixed point: quote amounts carry QUOTE_DECIMALS,
+// prices and sizes carry their market's decimals (see `value_scale_for`).
+// Helpers return `None` on overflow; the program maps that to MathOverflow.
+#![no_std]
+
+mod fixed;
+pub use fixed::{div_i128, div_u128, mul_div_i128, mul_div_u128, Rounding};
+
+pub const BPS_DENOM: u64 = 10_000;
+pub const SCALE: u128 = 1_000_000; // fixed-point scale for quote amounts (collateral, notional)
+pub const QUOTE_DECIMALS: u8 = 6;
+pub const MAX_MARKET_DECIMALS: u8 = 18;
+
+// ------------- MAINTENANCE TIERS -------------
+
+pub struct MaintenanceTiers {
+    pub notional: &'static [(u128, u64)], // (inclusive notional bound, bps), ascending
+    pub notional_ceiling_bps: u64,        // above the last bound
+}
+
+// The tiers `liquidate_*` enforce.
+pub const MAINTENANCE_TIERS: MaintenanceTiers = MaintenanceTiers {
+    notional: &NOTIONAL_MAINTENANCE_TIERS,
+    notional_ceiling_bps: NOTIONAL_MAINTENANCE_CEILING_BPS,
+};
+
+impl MaintenanceTiers {
+    pub fn notional_bps(&self, notional: u128) -> u64 {
+        self.notional
+            .iter()
+            .find(|(max_notional, _)| notional <= *max_notional)
+            .map(|(_, bps)| *bps)
+            .unwrap_or(self.notional_ceiling_bps)
+    }
+
+    // Stricter of the leverage tier and the notional tier.
+    pub fn maintenance_bps(&self, leverage: u16, notional: u128) -> u64 {
+        maintenance_bps_for_leverage(leverage).max(self.notional_bps(notional))
+    }
+}
+
+pub fn maintenance_bps_for_leverage(leverage: u16) -> u64 {
+    match leverage {
+        1..=20 => 250,      // 2.5%
+        21..=50 => 100,     // 1.0%
+        51..=100 => 50,     // 0.5%
+        101..=500 => 25,    // 0.25%
+        501..=1000 => 10,   // 0.1%
+        _ => 250,
+    }
+}
+
+// Upper notional bound (quote * 1e6, inclusive) -> maintenance bps.
+// Anything above the last bound uses NOTIONAL_MAINTENANCE_CEILING_BPS.
+const NOTIONAL_MAINTENANCE_TIERS: [(u128, u64); 4] = [
+    (1_000_000 * SCALE, 10),    // up to $1M: 0.1%
+    (5_000_000 * SCALE, 50),    // up to $5M: 0.5%
+    (20_000_000 * SCALE, 100),  // up to $20M: 1.0%
+    (50_000_000 * SCALE, 250),  // up to $50M: 2.5%
+];
+const NOTIONAL_MAINTENANCE_CEILING_BPS: u64 = 500; // above $50M: 5%
+
+// Initial margin is 1 / leverage, never looser than maintenance.
+pub fn initial_margin_bps(leverage: u16, notional: u128) -> u64 {
+    let leverage_bps = BPS_DENOM / leverage.max(1) as u64;
+    leverage_bps.max(MAINTENANCE_TIERS.maintenance_bps(leverage, notional))
+}
+
+// ------------- POSITION MATH -------------
+
+// Divisor turning `size * price` into quote units. `None` if the market's
+// decimals are out of range or coarser than the quote currency.
+pub fn value_scale_for(price_decimals: u8, base_decimals: u8) -> Option<u128> {
+    if price_decimals > MAX_MARKET_DECIMALS || base_decimals > MAX_MARKET_DECIMALS {
+        return None;
+    }
+    let exponent = (price_decimals + base_decimals).checked_sub(QUOTE_DECIMALS)?;
+    Some(10u128.pow(exponent as u32))
+}
+
+// Rounded up (see fixed.rs for the rounding policy).
+pub fn position_value_for_size(size: u64, price: u64, value_scale: u128) -> Option<u128> {
+    mul_div_u128(size as u128, price as u128, value_scale, Rounding::Ceil)
+}
+
+pub fn realized_pnl_for_size(
+    size: u64,
+    entry_price: u64,
+    mark_price: u64,
+    is_long: bool,
+    value_scale: u128,
+) -> Option<i128> {
+    let diff: i128 = if is_long {
+        mark_price as i128 - entry_price as i128
+    } else {
+        entry_price as i128 - mark_price as i128
+    };
+
+    // Floor: gains round down, losses round up.
+    mul_div_i128(diff, size as i128, value_scale as i128, Rounding::Floor)
+}
+
+// Returns (margin ratio bps, position value). A zero-value position reports
+// u64::MAX; non-positive equity reports 0.
+pub fn compute_margin_ratio(
+    size: u64,
+    entry_price: u64,
+    collateral: u64,
+    is_long: bool,
+    mark_price: u64,
+    value_scale: u128,
+) -> Option<(u64, u128)> {
+    let position_value = position_value_for_size(size, mark_price, value_scale)?;
+    if position_value == 0 {
+        return Some((u64::MAX, position_value));
+    }
+
+    let unrealized = realized_pnl_for_size(size, entry_price, mark_price, is_long, value_scale)?;
+    let equity = collateral as i128 + unrealized;
+
+    if equity <= 0 {
+        return Some((0, position_value));
+    }
+
+    let ratio_bps = mul_div_u128(
+        equity as u128,
+        BPS_DENOM as u128,
+        position_value,
+        Rounding::Floor,
+    )?;
+
+    Some((ratio_bps.min(u64::MAX as u128) as u64, position_value))
+}
+
+// Collateral after settling `pnl`, floored at zero.
+pub fn apply_pnl(collateral: u64, pnl: i128) -> Option<u64> {
+    let after = (collateral as i128).checked_add(pnl)?;
+    if after <= 0 {
+        Some(0)
+    } else {
+        Some(after as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_bps_for_leverage() {
+        assert_eq!(maintenance_bps_for_leverage(10), 250);
+        assert_eq!(maintenance_bps_for_leverage(30), 100);
+        assert_eq!(maintenance_bps_for_leverage(75), 50);
+        assert_eq!(maintenance_bps_for_leverage(200), 25);
+        assert_eq!(maintenance_bps_for_leverage(700), 10);
+    }
+
+    #[test]
+    fn test_maintenance_tiers_notional_bps() {
+        assert_eq!(MAINTENANCE_TIERS.notional_bps(100 * SCALE), 10);
+        assert_eq!(MAINTENANCE_TIERS.notional_bps(1_000_000 * SCALE), 10);
+        assert_eq!(MAINTENANCE_TIERS.notional_bps(2_000_000 * SCALE), 50);
+        assert_eq!(MAINTENANCE_TIERS.notional_bps(10_000_000 * SCALE), 100);
+        assert_eq!(MAINTENANCE_TIERS.notional_bps(30_000_000 * SCALE), 250);
+        assert_eq!(MAINTENANCE_TIERS.notional_bps(100_000_000 * SCALE), 500);
+    }
+
+    #[test]
+    fn test_initial_margin_bps() {
+        assert_eq!(initial_margin_bps(10, 100 * SCALE), 1_000);
+        assert_eq!(initial_margin_bps(100, 100 * SCALE), 100);
+        // 1000x -> 10 bps, same as maintenance
+        assert_eq!(initial_margin_bps(1_000, 100 * SCALE), 10);
+        // Never below the notional maintenance tier
+        assert_eq!(initial_margin_bps(1_000, 100_000_000 * SCALE), 500);
+        // Unset leverage treated as 1x
+        assert_eq!(initial_margin_bps(0, 100 * SCALE), BPS_DENOM);
+    }
+
+    #[test]
+    fn test_realized_pnl_rounds_against_user() {
+        // 1 unit (1e-6) at a 1 unit price move: exact PnL is 1e-6 of a quote unit
+        assert_eq!(realized_pnl_for_size(1, 10, 11, true, SCALE), Some(0));
+        assert_eq!(realized_pnl_for_size(1, 11, 10, true, SCALE), Some(-1));
+        assert_eq!(realized_pnl_for_size(1, 10, 11, false, SCALE), Some(-1));
+    }
+
+    #[test]
+    fn test_value_scale_for_rejects_bad_decimals() {
+        assert_eq!(value_scale_for(6, 8), Some(100 * SCALE));
+        assert_eq!(value_scale_for(2, 2), None);
+        assert_eq!(value_scale_for(19, 0), None);
+    }
+
+    #[test]
+    fn test_compute_margin_ratio_edges() {
+        // Zero size: nothing to liquidate
+        assert_eq!(compute_margin_ratio(0, 10, 10, true, 10, SCALE), Some((u64::MAX, 0)));
+        // Underwater long reports zero margin
+        let entry_price = 100 * SCALE as u64;
+        let mark_price = 50 * SCALE as u64;
+        let (ratio, _) =
+            compute_margin_ratio(SCALE as u64, entry_price, SCALE as u64, true, mark_price, SCALE)
+                .unwrap();
+        assert_eq!(ratio, 0);
+        assert_eq!(apply_pnl(5, -10), Some(0));
+    }
+}
@@ -0,0 +1,3 @@
+-- On-chain LiquidatorStats PDAs report the last liquidation slot, not a timestamp.
+ALTER TABLE liquidator_stats
+    ADD COLUMN last_liquidation_slot BIGINT;
@@ -0,0 +1,17 @@
+-- Open and closed positions tracked by the backend's PositionManager.
+-- Amounts are the program's raw fixed-point integers (see docs/LIQUIDATION_MECHANICS.md §1).
+CREATE TABLE positions (
+    id TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    market_index INTEGER NOT NULL,
+    size BIGINT NOT NULL CHECK (size >= 0),
+    is_long BOOLEAN NOT NULL,
+    entry_price BIGINT NOT NULL CHECK (entry_price >= 0),
+    collateral BIGINT NOT NULL CHECK (collateral >= 0),
+    leverage INTEGER NOT NULL,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX positions_open_idx ON positions (market_index) WHERE NOT closed;
@@ -0,0 +1,9 @@
+-- Written by the backend's LiquidationExecutor for every failed or timed-out attempt.
+-- error_code is the program's ErrorCode name (e.g. StaleOraclePrice) or the failure
+-- kind (Timeout, Rpc, ...); see LiquidationError::code.
+ALTER TABLE failed_liquidations
+    ADD COLUMN error_code TEXT;
+
+CREATE INDEX failed_liquidations_occurred_at_idx ON failed_liquidations (occurred_at DESC);
+CREATE INDEX failed_liquidations_position_idx
+    ON failed_liquidations (position_id, occurred_at DESC);
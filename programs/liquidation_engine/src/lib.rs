@@ -1,12 +1,30 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+use liquidation_math::{
+    div_u128, initial_margin_bps, maintenance_bps_for_leverage, mul_div_u128, Rounding,
+};
+pub use liquidation_math::{BPS_DENOM, MAX_MARKET_DECIMALS, QUOTE_DECIMALS, SCALE};
 
 // IMPORTANT: replace this with the program ID you copied earlier
 declare_id!("EHGrMFLNaYrKDt6cp5b3iABFwUsS5mCNa1EfwNARWm5n");
 
-pub const BPS_DENOM: u64 = 10_000;
 pub const LIQUIDATOR_REWARD_BPS: u64 = 250; // 2.5%
 pub const MAX_ORACLE_STALENESS_SECS: i64 = 30;
-pub const SCALE: u128 = 1_000_000; // fixed-point scale for prices
+pub const CRITICAL_MAINTENANCE_FRACTION_BPS: u64 = 5_000; // < 50% of maintenance: no grace period
+pub const MAX_INSURANCE_WITHDRAWAL_BPS: u64 = 2_000; // 20% of balance per epoch, hard ceiling
+pub const MAX_SYMBOL_LEN: usize = 16;
+pub const MAX_LEVERAGE: u16 = 1_000;
+pub const MARKET_SEED: &[u8] = b"market";
+pub const MARKET_INSURANCE_SEED: &[u8] = b"market_insurance";
+pub const BACKSTOP_LP_SEED: &[u8] = b"backstop_lp";
+pub const LIQUIDATOR_STATS_SEED: &[u8] = b"liquidator_stats";
+pub const COLLATERAL_VAULT_SEED: &[u8] = b"collateral_vault";
+pub const MARKET_INSURANCE_VAULT_SEED: &[u8] = b"market_insurance_vault";
+pub const INSURANCE_VAULT_SEED: &[u8] = b"insurance_vault";
+pub const INSURANCE_VAULT_AUTHORITY_SEED: &[u8] = b"insurance_vault_authority";
 
 #[program]
 pub mod liquidation_engine {
@@ -18,10 +36,11 @@ pub mod liquidation_engine {
         liquidation_size: u64,
     ) -> Result<()> {
         let clock = Clock::get()?;
-        let position = &mut ctx.accounts.position;
+        let position = &mut *ctx.accounts.position.load_mut()?;
         let price_feed = &ctx.accounts.price_feed;
+        let value_scale = ctx.accounts.market.value_scale()?;
 
-        require!(!position.closed, ErrorCode::PositionClosed);
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
 
         // 1) Check oracle price is fresh
         let age = clock.unix_timestamp - price_feed.last_updated;
@@ -30,52 +49,132 @@ pub mod liquidation_engine {
         let mark_price = price_feed.price;
 
         // 2) Check if position is liquidatable
-        let (margin_ratio_bps, _) = compute_margin_ratio(
+        let (margin_ratio_bps, position_value) = compute_margin_ratio(
             position.size,
             position.entry_price,
             position.collateral,
-            position.is_long,
+            position.is_long(),
             mark_price,
+            value_scale,
         )?;
 
-        let maintenance_bps = maintenance_bps_for_leverage(position.leverage);
+        let maintenance_bps = effective_maintenance_bps(position.leverage, position_value);
         require!(margin_ratio_bps < maintenance_bps, ErrorCode::PositionHealthy);
 
+        // 2b) Non-critical positions get a grace window after being flagged
+        require!(
+            liquidation_grace_elapsed(
+                position.at_risk_since,
+                ctx.accounts.market.at_risk_grace_secs,
+                margin_ratio_bps,
+                maintenance_bps,
+                clock.unix_timestamp,
+            ),
+            ErrorCode::AtRiskGracePeriodActive
+        );
+
         // 3) Compute liquidation size (50% or user-specified smaller)
+        require!(liquidation_size > 0, ErrorCode::InvalidLiquidationSize);
         let half_size = position.size / 2;
         let liq_size = half_size.min(liquidation_size);
-        require!(liq_size > 0, ErrorCode::InvalidLiquidationSize);
+
+        // 3b) Escalate to a full close if the remainder would be dust
+        if partial_leaves_dust(
+            position.size,
+            liq_size,
+            mark_price,
+            value_scale,
+            ctx.accounts.market.min_position_notional,
+        )? {
+            let full = settle_full_liquidation(
+                position,
+                &mut ctx.accounts.insurance_fund,
+                &mut ctx.accounts.market_insurance_fund,
+                mark_price,
+                position_value,
+                value_scale,
+            )?;
+            ctx.accounts
+                .market
+                .reduce_open_interest(position.is_long(), full.liquidated_size);
+            if let Some(stats) = ctx.accounts.liquidator_stats.as_mut() {
+                stats.record(full.reward_paid, clock.slot)?;
+            }
+            cover_from_market_fund(
+                &ctx.accounts.token_program,
+                &ctx.accounts.market_insurance_fund,
+                &ctx.accounts.market_insurance_vault,
+                &ctx.accounts.collateral_vault,
+                full.covered_by_market,
+            )?;
+            cover_from_global_fund(
+                &ctx.accounts.token_program,
+                &ctx.accounts.insurance_fund.key(),
+                &ctx.accounts.insurance_vault,
+                &ctx.accounts.insurance_vault_authority,
+                ctx.bumps.insurance_vault_authority,
+                &ctx.accounts.collateral_vault,
+                full.covered_by_global,
+            )?;
+            pay_out_of_collateral_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.market,
+                &ctx.accounts.collateral_vault,
+                &[
+                    (&ctx.accounts.liquidator_token_account, full.reward_paid),
+                    (&ctx.accounts.owner_token_account, full.user_refund),
+                ],
+            )?;
+            emit!(full.into_record(
+                ctx.accounts.liquidator.key(),
+                margin_ratio_bps,
+                clock.unix_timestamp,
+            ));
+            return Ok(());
+        }
 
         // 4) Realize PnL on liquidated portion
         let pnl_on_liq = realized_pnl_for_size(
             liq_size,
             position.entry_price,
             mark_price,
-            position.is_long,
+            position.is_long(),
+            value_scale,
         )?;
-        let liq_value = position_value_for_size(liq_size, mark_price)?;
+        let liq_value = position_value_for_size(liq_size, mark_price, value_scale)?;
 
         // 5) Compute liquidator reward
-        let liquidator_reward = (liq_value
-            .checked_mul(LIQUIDATOR_REWARD_BPS as u128)
-            .ok_or(error!(ErrorCode::MathOverflow))?
-            / BPS_DENOM as u128) as u64;
+        let liquidator_reward = liquidator_reward_for_value(liq_value)?;
 
         // 6) Update collateral: apply PnL then pay reward out of equity
         let equity_after_pnl = apply_pnl(position.collateral, pnl_on_liq)?;
-        let remaining_equity = equity_after_pnl.saturating_sub(liquidator_reward);
+        let reward_paid = liquidator_reward.min(equity_after_pnl);
+        let remaining_equity = equity_after_pnl - reward_paid;
 
         position.collateral = remaining_equity;
         position.size = position
             .size
             .checked_sub(liq_size)
             .ok_or(error!(ErrorCode::MathOverflow))?;
+        ctx.accounts
+            .market
+            .reduce_open_interest(position.is_long(), liq_size);
+
+        if let Some(stats) = ctx.accounts.liquidator_stats.as_mut() {
+            stats.record(reward_paid, clock.slot)?;
+        }
+        pay_out_of_collateral_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.market,
+            &ctx.accounts.collateral_vault,
+            &[(&ctx.accounts.liquidator_token_account, reward_paid)],
+        )?;
 
         // 7) Emit event (for off-chain DB)
         emit!(LiquidationRecord {
             position_owner: position.owner,
             liquidator: ctx.accounts.liquidator.key(),
-            symbol: position.symbol.clone(),
+            market_index: position.market_index,
             liquidated_size: liq_size,
             liquidation_price: mark_price,
             margin_before: margin_ratio_bps,
@@ -83,12 +182,17 @@ pub mod liquidation_engine {
                 position.size,
                 position.entry_price,
                 position.collateral,
-                position.is_long,
+                position.is_long(),
                 mark_price,
+                value_scale,
             )?
             .0,
-            liquidator_reward,
+            liquidator_reward: reward_paid,
             bad_debt: 0,
+            covered_by_market_fund: 0,
+            covered_by_global_fund: 0,
+            market_fund_utilization: 0,
+            global_fund_utilization: 0,
             timestamp: clock.unix_timestamp,
         });
 
@@ -98,11 +202,13 @@ pub mod liquidation_engine {
     // --------- FULL LIQUIDATION ---------
     pub fn liquidate_full(ctx: Context<LiquidateFull>) -> Result<()> {
         let clock = Clock::get()?;
-        let position = &mut ctx.accounts.position;
+        let position = &mut *ctx.accounts.position.load_mut()?;
         let price_feed = &ctx.accounts.price_feed;
         let insurance = &mut ctx.accounts.insurance_fund;
+        let market_insurance = &mut ctx.accounts.market_insurance_fund;
+        let value_scale = ctx.accounts.market.value_scale()?;
 
-        require!(!position.closed, ErrorCode::PositionClosed);
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
 
         // 1) Oracle freshness
         let age = clock.unix_timestamp - price_feed.last_updated;
@@ -115,322 +221,3669 @@ pub mod liquidation_engine {
             position.size,
             position.entry_price,
             position.collateral,
-            position.is_long,
+            position.is_long(),
             mark_price,
+            value_scale,
         )?;
-        let maintenance_bps = maintenance_bps_for_leverage(position.leverage);
+        let maintenance_bps = effective_maintenance_bps(position.leverage, position_value);
         require!(margin_ratio_bps < maintenance_bps, ErrorCode::PositionHealthy);
 
-        // 3) Full PnL
-        let pnl_full = realized_pnl_for_size(
-            position.size,
-            position.entry_price,
+        // 2b) Non-critical positions get a grace window after being flagged
+        require!(
+            liquidation_grace_elapsed(
+                position.at_risk_since,
+                ctx.accounts.market.at_risk_grace_secs,
+                margin_ratio_bps,
+                maintenance_bps,
+                clock.unix_timestamp,
+            ),
+            ErrorCode::AtRiskGracePeriodActive
+        );
+
+        // 3) Realize PnL, pay the reward and cover any bad debt
+        let full = settle_full_liquidation(
+            position,
+            insurance,
+            market_insurance,
             mark_price,
-            position.is_long,
+            position_value,
+            value_scale,
         )?;
 
-        let equity_after_pnl = apply_pnl(position.collateral, pnl_full)?;
-
-        // 4) Reward based on full notional
-        let liq_value = position_value;
-        let liquidator_reward = (liq_value
-            .checked_mul(LIQUIDATOR_REWARD_BPS as u128)
-            .ok_or(error!(ErrorCode::MathOverflow))?
-            / BPS_DENOM as u128) as u64;
-
-        let mut reward_paid: u64 = 0;
-        let mut bad_debt: u64 = 0;
-        let mut _user_refund: u64 = 0;
-
-        if equity_after_pnl >= liquidator_reward {
-            reward_paid = liquidator_reward;
-            _user_refund = equity_after_pnl - liquidator_reward;
-        } else {
-            // not enough margin to fully pay reward
-            reward_paid = equity_after_pnl;
-            bad_debt = liquidator_reward - equity_after_pnl;
-        }
-
-        // 5) Cover bad debt from insurance fund
-        if bad_debt > 0 {
-            let covered = insurance.balance.min(bad_debt);
-            insurance.balance = insurance.balance.saturating_sub(covered);
-            insurance.total_bad_debt_covered = insurance
-                .total_bad_debt_covered
-                .saturating_add(covered);
-        }
-
-        // Update utilization
-        if insurance.total_contributions > 0 {
-            insurance.utilization_ratio = insurance
-                .total_bad_debt_covered
-                .saturating_mul(BPS_DENOM)
-                / insurance.total_contributions;
+        ctx.accounts
+            .market
+            .reduce_open_interest(position.is_long(), full.liquidated_size);
+        if let Some(stats) = ctx.accounts.liquidator_stats.as_mut() {
+            stats.record(full.reward_paid, clock.slot)?;
         }
 
-        // 6) Close position logically
-        let old_size = position.size;
-        position.size = 0;
-        position.collateral = 0;
-        position.closed = true;
+        // 4) Move the insurance funds' coverage into the vault, then pay the
+        // reward and refund the remaining equity to the owner
+        cover_from_market_fund(
+            &ctx.accounts.token_program,
+            &ctx.accounts.market_insurance_fund,
+            &ctx.accounts.market_insurance_vault,
+            &ctx.accounts.collateral_vault,
+            full.covered_by_market,
+        )?;
+        cover_from_global_fund(
+            &ctx.accounts.token_program,
+            &ctx.accounts.insurance_fund.key(),
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.insurance_vault_authority,
+            ctx.bumps.insurance_vault_authority,
+            &ctx.accounts.collateral_vault,
+            full.covered_by_global,
+        )?;
+        pay_out_of_collateral_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.market,
+            &ctx.accounts.collateral_vault,
+            &[
+                (&ctx.accounts.liquidator_token_account, full.reward_paid),
+                (&ctx.accounts.owner_token_account, full.user_refund),
+            ],
+        )?;
 
-        emit!(LiquidationRecord {
-            position_owner: position.owner,
-            liquidator: ctx.accounts.liquidator.key(),
-            symbol: position.symbol.clone(),
-            liquidated_size: old_size,
-            liquidation_price: mark_price,
-            margin_before: margin_ratio_bps,
-            margin_after: 0,
-            liquidator_reward: reward_paid,
-            bad_debt,
-            timestamp: clock.unix_timestamp,
-        });
+        emit!(full.into_record(
+            ctx.accounts.liquidator.key(),
+            margin_ratio_bps,
+            clock.unix_timestamp,
+        ));
 
         Ok(())
     }
-}
-
-// ------------- ACCOUNTS / STRUCTS -------------
 
-#[account]
-pub struct Position {
-    pub owner: Pubkey,
-    pub symbol: String,    // e.g. "BTC-PERP"
-    pub size: u64,         // contracts, or base amount in 1e6
-    pub is_long: bool,
-    pub entry_price: u64,  // price * 1e6
-    pub collateral: u64,   // quote * 1e6
-    pub leverage: u16,
-    pub closed: bool,
-}
+    // --------- POSITION OPENING ---------
+    // Opens a position at the current mark, with `collateral` transferred from
+    // the owner into the market's vault. Subject to initial margin and the
+    // market's open interest / per-position notional caps.
+    pub fn open_position(
+        ctx: Context<OpenPosition>,
+        size: u64,
+        is_long: bool,
+        collateral: u64,
+        leverage: u16,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let position_key = ctx.accounts.position.key();
+        let position = &mut *ctx.accounts.position.load_init()?;
+        let price_feed = &ctx.accounts.price_feed;
+        let market = &mut ctx.accounts.market;
+        let value_scale = market.value_scale()?;
 
-#[account]
-pub struct InsuranceFund {
-    pub authority: Pubkey,
-    pub balance: u64,                // total funds
-    pub total_contributions: u64,
-    pub total_bad_debt_covered: u64,
-    pub utilization_ratio: u64,      // basis points
-}
+        require!(size > 0, ErrorCode::InvalidPositionSize);
+        require!(collateral > 0, ErrorCode::InvalidCollateralAmount);
+        require!(
+            (1..=MAX_LEVERAGE).contains(&leverage),
+            ErrorCode::InvalidLeverage
+        );
 
-#[account]
-pub struct PriceFeed {
-    pub price: u64,          // mark price * 1e6
-    pub last_updated: i64,   // unix timestamp
-}
+        // 1) Oracle freshness
+        let age = clock.unix_timestamp - price_feed.last_updated;
+        require!(age <= MAX_ORACLE_STALENESS_SECS, ErrorCode::StaleOraclePrice);
 
-// This matches your assignment's LiquidationRecord structure (as an event)
-#[event]
-pub struct LiquidationRecord {
-    pub position_owner: Pubkey,
-    pub liquidator: Pubkey,
-    pub symbol: String,
-    pub liquidated_size: u64,
-    pub liquidation_price: u64,
-    pub margin_before: u64,  // bps
-    pub margin_after: u64,   // bps
-    pub liquidator_reward: u64,
-    pub bad_debt: u64,
-    pub timestamp: i64,
-}
+        let mark_price = price_feed.price;
 
-// ------------- INSTRUCTION CONTEXTS -------------
+        position.owner = ctx.accounts.owner.key();
+        position.market_index = market.market_index;
+        position.size = size;
+        position.entry_price = mark_price;
+        position.collateral = collateral;
+        position.leverage = leverage;
+        position.is_long = is_long as u8;
+        position.closed = 0;
+        position.version = Position::VERSION;
+
+        // 2) Caps and initial margin
+        let position_value = position_value_for_size(size, mark_price, value_scale)?;
+        market.add_open_interest(is_long, size, position_value)?;
+        check_initial_margin(position, mark_price, value_scale)?;
+
+        // 3) Fund the collateral
+        transfer_to_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.collateral_vault,
+            &ctx.accounts.owner,
+            collateral,
+        )?;
 
-#[derive(Accounts)]
-pub struct LiquidatePartial<'info> {
-    #[account(mut)]
-    pub position: Account<'info, Position>,
+        emit!(PositionOpened {
+            owner: position.owner,
+            position: position_key,
+            market_index: position.market_index,
+            size,
+            entry_price: mark_price,
+            collateral,
+            leverage,
+            is_long,
+            timestamp: clock.unix_timestamp,
+        });
 
-    #[account(mut)]
-    pub liquidator: Signer<'info>,
+        Ok(())
+    }
 
-    #[account()]
-    pub price_feed: Account<'info, PriceFeed>,
-}
+    // Adds size at the current mark; the entry price becomes the size-weighted average.
+    pub fn increase_position(ctx: Context<IncreasePosition>, additional_size: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let position_key = ctx.accounts.position.key();
+        let position = &mut *ctx.accounts.position.load_mut()?;
+        let price_feed = &ctx.accounts.price_feed;
+        let market = &mut ctx.accounts.market;
+        let value_scale = market.value_scale()?;
 
-#[derive(Accounts)]
-pub struct LiquidateFull<'info> {
-    #[account(mut)]
-    pub position: Account<'info, Position>,
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
+        require!(additional_size > 0, ErrorCode::InvalidPositionSize);
 
-    #[account(mut)]
-    pub liquidator: Signer<'info>,
+        // 1) Oracle freshness
+        let age = clock.unix_timestamp - price_feed.last_updated;
+        require!(age <= MAX_ORACLE_STALENESS_SECS, ErrorCode::StaleOraclePrice);
 
-    #[account()]
-    pub price_feed: Account<'info, PriceFeed>,
+        let mark_price = price_feed.price;
 
-    #[account(mut)]
-    pub insurance_fund: Account<'info, InsuranceFund>,
-}
+        position.entry_price = average_entry_price(
+            position.size,
+            position.entry_price,
+            additional_size,
+            mark_price,
+            position.is_long(),
+        )?;
+        position.size = position
+            .size
+            .checked_add(additional_size)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
 
-// ------------- HELPER FUNCTIONS -------------
+        // 2) Caps and initial margin on the enlarged position
+        let position_value = position_value_for_size(position.size, mark_price, value_scale)?;
+        market.add_open_interest(position.is_long(), additional_size, position_value)?;
+        check_initial_margin(position, mark_price, value_scale)?;
+
+        emit!(PositionIncreased {
+            owner: position.owner,
+            position: position_key,
+            market_index: position.market_index,
+            additional_size,
+            size_after: position.size,
+            entry_price_after: position.entry_price,
+            timestamp: clock.unix_timestamp,
+        });
 
-fn maintenance_bps_for_leverage(leverage: u16) -> u64 {
-    match leverage {
-        1..=20 => 250,      // 2.5%
-        21..=50 => 100,     // 1.0%
-        51..=100 => 50,     // 0.5%
-        101..=500 => 25,    // 0.25%
-        501..=1000 => 10,   // 0.1%
-        _ => 250,
+        Ok(())
     }
-}
 
-fn position_value_for_size(size: u64, price: u64) -> Result<u128> {
-    let s = size as u128;
-    let p = price as u128;
-    let v = s
-        .checked_mul(p)
-        .ok_or(error!(ErrorCode::MathOverflow))?;
-    Ok(v / SCALE)
-}
+    pub fn set_open_interest_limits(
+        ctx: Context<UpdateMarket>,
+        max_open_interest: u64,
+        max_notional_per_position: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.max_open_interest = max_open_interest;
+        market.max_notional_per_position = max_notional_per_position;
+        Ok(())
+    }
 
-fn realized_pnl_for_size(
-    size: u64,
-    entry_price: u64,
-    mark_price: u64,
-    is_long: bool,
-) -> Result<i128> {
-    let size_u = size as u128;
-    let entry_u = entry_price as u128;
-    let mark_u = mark_price as u128;
+    // --------- AT-RISK WARNING ---------
+    // Permissionless. Starts the grace window for a position whose margin is
+    // below the market's warning threshold (maintenance + warning_buffer_bps).
+    pub fn flag_at_risk(ctx: Context<UpdateAtRisk>) -> Result<()> {
+        let clock = Clock::get()?;
+        let position_key = ctx.accounts.position.key();
+        let position = &mut *ctx.accounts.position.load_mut()?;
+        let (margin_ratio_bps, warning_bps) = at_risk_margins(
+            position,
+            &ctx.accounts.price_feed,
+            &ctx.accounts.market,
+            clock.unix_timestamp,
+        )?;
 
-    let diff: i128 = if is_long {
-        mark_u as i128 - entry_u as i128
-    } else {
-        entry_u as i128 - mark_u as i128
-    };
+        require!(!position.is_flagged_at_risk(), ErrorCode::AlreadyFlaggedAtRisk);
+        require!(margin_ratio_bps < warning_bps, ErrorCode::PositionNotAtRisk);
 
-    let pnl = diff
-        .checked_mul(size_u as i128)
-        .ok_or(error!(ErrorCode::MathOverflow))?
-        / SCALE as i128;
+        position.at_risk_since = clock.unix_timestamp;
 
-    Ok(pnl)
-}
+        emit!(PositionAtRisk {
+            owner: position.owner,
+            position: position_key,
+            margin_ratio_bps,
+            warning_threshold_bps: warning_bps,
+            flagged: true,
+            timestamp: clock.unix_timestamp,
+        });
 
-fn compute_margin_ratio(
-    size: u64,
-    entry_price: u64,
-    collateral: u64,
-    is_long: bool,
-    mark_price: u64,
-) -> Result<(u64, u128)> {
-    let position_value = position_value_for_size(size, mark_price)?;
-    if position_value == 0 {
-        return Ok((u64::MAX, position_value));
+        Ok(())
     }
 
-    let unrealized = realized_pnl_for_size(size, entry_price, mark_price, is_long)?;
-    let equity = collateral as i128 + unrealized;
+    // Permissionless. Clears a stale flag once the position is back above the
+    // warning threshold, so a later dip gets a fresh grace window.
+    pub fn clear_at_risk(ctx: Context<UpdateAtRisk>) -> Result<()> {
+        let clock = Clock::get()?;
+        let position_key = ctx.accounts.position.key();
+        let position = &mut *ctx.accounts.position.load_mut()?;
+        let (margin_ratio_bps, warning_bps) = at_risk_margins(
+            position,
+            &ctx.accounts.price_feed,
+            &ctx.accounts.market,
+            clock.unix_timestamp,
+        )?;
 
-    if equity <= 0 {
-        return Ok((0, position_value));
-    }
+        require!(position.is_flagged_at_risk(), ErrorCode::NotFlaggedAtRisk);
+        require!(margin_ratio_bps >= warning_bps, ErrorCode::PositionNotRecovered);
 
-    let equity_u = equity as u128;
-    let ratio_bps = equity_u
-        .checked_mul(BPS_DENOM as u128)
-        .ok_or(error!(ErrorCode::MathOverflow))?
-        / position_value;
+        position.at_risk_since = 0;
 
-    Ok((ratio_bps as u64, position_value))
-}
+        emit!(PositionAtRisk {
+            owner: position.owner,
+            position: position_key,
+            margin_ratio_bps,
+            warning_threshold_bps: warning_bps,
+            flagged: false,
+            timestamp: clock.unix_timestamp,
+        });
 
-fn apply_pnl(collateral: u64, pnl: i128) -> Result<u64> {
-    let base = collateral as i128;
-    let after = base
-        .checked_add(pnl)
-        .ok_or(error!(ErrorCode::MathOverflow))?;
-    if after <= 0 {
-        Ok(0)
-    } else {
-        Ok(after as u64)
+        Ok(())
     }
-}
 
-// ------------- ERRORS -------------
+    // --------- COLLATERAL MANAGEMENT ---------
+    pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let position_key = ctx.accounts.position.key();
+        let position = &mut *ctx.accounts.position.load_mut()?;
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Math overflow")]
-    MathOverflow,
-    #[msg("Position is healthy, cannot be liquidated")]
-    PositionHealthy,
-    #[msg("Oracle price is stale")]
-    StaleOraclePrice,
-    #[msg("Invalid liquidation size")]
-    InvalidLiquidationSize,
-    #[msg("Position already closed")]
-    PositionClosed,
-}
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
+        require!(amount > 0, ErrorCode::InvalidCollateralAmount);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        position.collateral = position
+            .collateral
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
 
-    #[test]
-    fn test_maintenance_bps_for_leverage() {
-        assert_eq!(maintenance_bps_for_leverage(10), 250);
-        assert_eq!(maintenance_bps_for_leverage(30), 100);
-        assert_eq!(maintenance_bps_for_leverage(75), 50);
-        assert_eq!(maintenance_bps_for_leverage(200), 25);
-        assert_eq!(maintenance_bps_for_leverage(700), 10);
-    }
+        transfer_to_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.collateral_vault,
+            &ctx.accounts.owner,
+            amount,
+        )?;
 
-    #[test]
-    fn test_realized_pnl_long_gain() {
-        // 1 contract (scaled), entry 10,000, mark 11,000
-        let size = SCALE as u64;              // 1 * 1e6
-        let entry_price = 10 * SCALE as u64;  // 10 * 1e6
-        let mark_price = 11 * SCALE as u64;   // 11 * 1e6
+        emit!(CollateralDeposited {
+            owner: position.owner,
+            position: position_key,
+            amount,
+            collateral_after: position.collateral,
+            timestamp: clock.unix_timestamp,
+        });
 
-        let pnl = realized_pnl_for_size(size, entry_price, mark_price, true).unwrap();
-        // Expect +1 (scaled)
-        assert_eq!(pnl, SCALE as i128);
+        Ok(())
     }
 
-    #[test]
-    fn test_realized_pnl_short_gain() {
-        // short from 10k to 9k
-        let size = SCALE as u64;
-        let entry_price = 10 * SCALE as u64;
-        let mark_price = 9 * SCALE as u64;
+    // Withdrawal is only allowed while the position stays at or above initial margin.
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let position_key = ctx.accounts.position.key();
+        let position = &mut *ctx.accounts.position.load_mut()?;
+        let price_feed = &ctx.accounts.price_feed;
+        let value_scale = ctx.accounts.market.value_scale()?;
 
-        let pnl = realized_pnl_for_size(size, entry_price, mark_price, false).unwrap();
-        // Expect +1 (scaled)
-        assert_eq!(pnl, SCALE as i128);
-    }
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
+        require!(amount > 0, ErrorCode::InvalidCollateralAmount);
+        require!(
+            amount <= position.collateral,
+            ErrorCode::InsufficientCollateral
+        );
 
-    #[test]
-    fn test_apply_pnl_positive_and_negative() {
-        let collateral = 5 * SCALE as u64;
+        // 1) Oracle freshness
+        let age = clock.unix_timestamp - price_feed.last_updated;
+        require!(age <= MAX_ORACLE_STALENESS_SECS, ErrorCode::StaleOraclePrice);
 
-        // +2
-        let after_gain = apply_pnl(collateral, 2 * SCALE as i128).unwrap();
-        assert_eq!(after_gain, 7 * SCALE as u64);
+        let mark_price = price_feed.price;
+        let collateral_after = position.collateral - amount;
 
-        // -10 (should clamp at 0, not underflow)
-        let after_loss = apply_pnl(collateral, -10 * SCALE as i128).unwrap();
-        assert_eq!(after_loss, 0);
+        // 2) Post-withdrawal margin must satisfy initial margin
+        let (margin_ratio_bps, position_value) = compute_margin_ratio(
+            position.size,
+            position.entry_price,
+            collateral_after,
+            position.is_long(),
+            mark_price,
+            value_scale,
+        )?;
+        let initial_bps = initial_margin_bps(position.leverage, position_value);
+        require!(
+            margin_ratio_bps >= initial_bps,
+            ErrorCode::InitialMarginViolated
+        );
+
+        position.collateral = collateral_after;
+
+        // 3) Pay out of the market's vault, which the Market PDA owns
+        pay_out_of_collateral_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.market,
+            &ctx.accounts.collateral_vault,
+            &[(&ctx.accounts.owner_token_account, amount)],
+        )?;
+
+        emit!(CollateralWithdrawn {
+            owner: position.owner,
+            position: position_key,
+            amount,
+            collateral_after,
+            margin_after: margin_ratio_bps,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
     }
 
-    #[test]
-    fn test_compute_margin_ratio_basic() {
-        // size 1, price 10, collateral 1 -> equity 1, value 10 -> MR = 10%
-        let size = SCALE as u64;
-        let price = 10 * SCALE as u64;
-        let collateral = 1 * SCALE as u64;
+    // --------- BACKSTOP LP TAKEOVER ---------
+    // Instead of closing at mark, a registered backstop LP assumes the whole
+    // position and its collateral, plus collateral the LP transfers in.
+    pub fn liquidate_backstop(ctx: Context<LiquidateBackstop>, lp_collateral: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let lp_position_key = ctx.accounts.lp_position.key();
+        let position = &mut *ctx.accounts.position.load_mut()?;
+        let lp_position = &mut *ctx.accounts.lp_position.load_init()?;
+        let price_feed = &ctx.accounts.price_feed;
+        let backstop_lp = &mut ctx.accounts.backstop_lp;
+        let value_scale = ctx.accounts.market.value_scale()?;
 
-        let (mr_bps, position_value) =
-            compute_margin_ratio(size, price, collateral, true, price).unwrap();
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
 
-        // Position value ~= 10 * SCALE
-        assert_eq!(position_value, 10 * SCALE as u128);
+        // 1) Oracle freshness
+        let age = clock.unix_timestamp - price_feed.last_updated;
+        require!(age <= MAX_ORACLE_STALENESS_SECS, ErrorCode::StaleOraclePrice);
 
-        // 10% margin -> 1000 bps
-        assert_eq!(mr_bps, 1000);
-    }
-}
+        let mark_price = price_feed.price;
 
+        // 2) Liquidation condition
+        let (margin_ratio_bps, position_value) = compute_margin_ratio(
+            position.size,
+            position.entry_price,
+            position.collateral,
+            position.is_long(),
+            mark_price,
+            value_scale,
+        )?;
+        let maintenance_bps = effective_maintenance_bps(position.leverage, position_value);
+        require!(margin_ratio_bps < maintenance_bps, ErrorCode::PositionHealthy);
+
+        // 2b) Non-critical positions get a grace window after being flagged
+        require!(
+            liquidation_grace_elapsed(
+                position.at_risk_since,
+                ctx.accounts.market.at_risk_grace_secs,
+                margin_ratio_bps,
+                maintenance_bps,
+                clock.unix_timestamp,
+            ),
+            ErrorCode::AtRiskGracePeriodActive
+        );
+
+        // 3) LP capacity
+        require!(
+            position_value <= backstop_lp.max_position_notional as u128,
+            ErrorCode::BackstopCapacityExceeded
+        );
+
+        // 4) Hand the position over at the bankruptcy price, backed by
+        //    `lp_collateral` deposited into the vault
+        require!(lp_collateral > 0, ErrorCode::InvalidCollateralAmount);
+        *lp_position =
+            backstop_takeover_position(position, backstop_lp.owner, lp_collateral, value_scale)?;
+        let takeover_price = lp_position.entry_price;
+
+        // The LP must not inherit a position that is itself liquidatable.
+        let (lp_margin_bps, _) = compute_margin_ratio(
+            lp_position.size,
+            lp_position.entry_price,
+            lp_position.collateral,
+            lp_position.is_long(),
+            mark_price,
+            value_scale,
+        )?;
+        require!(
+            lp_margin_bps >= maintenance_bps,
+            ErrorCode::BackstopUndercollateralized
+        );
+
+        backstop_lp.positions_taken = backstop_lp.positions_taken.saturating_add(1);
+        backstop_lp.total_notional_taken = backstop_lp
+            .total_notional_taken
+            .saturating_add(position_value.min(u64::MAX as u128) as u64);
+
+        transfer_to_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.lp_token_account,
+            &ctx.accounts.collateral_vault,
+            &ctx.accounts.lp,
+            lp_collateral,
+        )?;
+
+        // 5) Close the user's position; its collateral stays in the vault to
+        //    pay the LP's unrealized PnL
+        let old_size = position.size;
+        position.size = 0;
+        position.collateral = 0;
+        position.closed = 1;
+
+        emit!(BackstopTakeover {
+            position_owner: position.owner,
+            backstop_lp: backstop_lp.owner,
+            lp_position: lp_position_key,
+            market_index: position.market_index,
+            size: old_size,
+            takeover_price,
+            mark_price,
+            lp_collateral,
+            timestamp: clock.unix_timestamp,
+        });
+
+        emit!(LiquidationRecord {
+            position_owner: position.owner,
+            liquidator: backstop_lp.owner,
+            market_index: position.market_index,
+            liquidated_size: old_size,
+            liquidation_price: takeover_price,
+            margin_before: margin_ratio_bps,
+            margin_after: 0,
+            liquidator_reward: 0,
+            bad_debt: 0,
+            covered_by_market_fund: 0,
+            covered_by_global_fund: 0,
+            market_fund_utilization: 0,
+            global_fund_utilization: 0,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn register_backstop_lp(
+        ctx: Context<RegisterBackstopLp>,
+        max_position_notional: u64,
+    ) -> Result<()> {
+        let backstop_lp = &mut ctx.accounts.backstop_lp;
+
+        backstop_lp.owner = ctx.accounts.lp.key();
+        backstop_lp.insurance_fund = ctx.accounts.insurance_fund.key();
+        backstop_lp.max_position_notional = max_position_notional;
+        backstop_lp.positions_taken = 0;
+        backstop_lp.total_notional_taken = 0;
+        backstop_lp.active = true;
+        backstop_lp.bump = ctx.bumps.backstop_lp;
+
+        Ok(())
+    }
+
+    pub fn set_backstop_lp_active(ctx: Context<UpdateBackstopLp>, active: bool) -> Result<()> {
+        ctx.accounts.backstop_lp.active = active;
+        Ok(())
+    }
+
+    // --------- RISK VIEWS ---------
+    // Read-only; call with `.view()` / simulateTransaction.
+    pub fn simulate_liquidation_price(ctx: Context<ViewPositionRisk>) -> Result<PositionRiskView> {
+        let position = ctx.accounts.position.load()?;
+        let value_scale = ctx.accounts.market.value_scale()?;
+
+        require!(!position.is_closed(), ErrorCode::PositionClosed);
+
+        Ok(PositionRiskView {
+            liquidation_price: liquidation_price(&position, &MAINTENANCE_TIERS, value_scale)?,
+            bankruptcy_price: bankruptcy_price(&position, value_scale)?,
+        })
+    }
+
+    // --------- LIQUIDATORS ---------
+    // Opt-in: liquidators that pass their stats PDA to `liquidate_partial` /
+    // `liquidate_full` get a verifiable on-chain track record.
+    pub fn register_liquidator(ctx: Context<RegisterLiquidator>) -> Result<()> {
+        let stats = &mut ctx.accounts.liquidator_stats;
+
+        stats.liquidator = ctx.accounts.liquidator.key();
+        stats.liquidation_count = 0;
+        stats.total_reward = 0;
+        stats.last_liquidation_slot = 0;
+        stats.bump = ctx.bumps.liquidator_stats;
+
+        Ok(())
+    }
+
+    // --------- MARKETS ---------
+    // Also creates the market's PriceFeed at `initial_price`, so a market is
+    // never usable without a bound feed.
+    pub fn initialize_market(
+        ctx: Context<InitializeMarket>,
+        market_index: u16,
+        symbol: String,
+        price_decimals: u8,
+        base_decimals: u8,
+        min_position_notional: u64,
+        initial_price: u64,
+    ) -> Result<()> {
+        let feed = &mut ctx.accounts.price_feed;
+        set_price(feed, initial_price, Clock::get()?.unix_timestamp)?;
+        feed.version = PriceFeed::VERSION;
+
+        let market = &mut ctx.accounts.market;
+
+        market.market_index = market_index;
+        market.symbol = symbol_bytes(&symbol)?;
+        market.price_decimals = price_decimals;
+        market.base_decimals = base_decimals;
+        // Reject decimals that cannot express quote amounts.
+        market.value_scale()?;
+        market.min_position_notional = min_position_notional;
+        market.insurance_fund = ctx.accounts.insurance_fund.key();
+        market.bump = ctx.bumps.market;
+        market.price_feed = feed.key();
+
+        Ok(())
+    }
+
+    pub fn set_min_position_notional(
+        ctx: Context<UpdateMarket>,
+        min_position_notional: u64,
+    ) -> Result<()> {
+        ctx.accounts.market.min_position_notional = min_position_notional;
+        Ok(())
+    }
+
+    pub fn set_market_price_feed(ctx: Context<UpdateMarket>, price_feed: Pubkey) -> Result<()> {
+        require!(price_feed != Pubkey::default(), ErrorCode::PriceFeedMismatch);
+        ctx.accounts.market.price_feed = price_feed;
+        Ok(())
+    }
+
+    // Creates the token account holding the market's position collateral.
+    // Every position in the market posts collateral in `mint`.
+    pub fn initialize_collateral_vault(_ctx: Context<InitializeCollateralVault>) -> Result<()> {
+        Ok(())
+    }
+
+    // --------- PRICE FEEDS ---------
+    // Creates a PriceFeed and binds it to `market` (`Market.price_feed`).
+    pub fn initialize_price_feed(ctx: Context<InitializePriceFeed>, price: u64) -> Result<()> {
+        let feed = &mut ctx.accounts.price_feed;
+        set_price(feed, price, Clock::get()?.unix_timestamp)?;
+        feed.version = PriceFeed::VERSION;
+        ctx.accounts.market.price_feed = feed.key();
+        Ok(())
+    }
+
+    // Signed by the market admin, i.e. whoever runs the price pusher.
+    pub fn update_price_feed(ctx: Context<UpdatePriceFeed>, price: u64) -> Result<()> {
+        set_price(&mut ctx.accounts.price_feed, price, Clock::get()?.unix_timestamp)
+    }
+
+    pub fn set_at_risk_config(
+        ctx: Context<UpdateMarket>,
+        warning_buffer_bps: u64,
+        at_risk_grace_secs: i64,
+    ) -> Result<()> {
+        require!(warning_buffer_bps < BPS_DENOM, ErrorCode::InvalidAtRiskConfig);
+        require!(at_risk_grace_secs >= 0, ErrorCode::InvalidAtRiskConfig);
+
+        let market = &mut ctx.accounts.market;
+        market.warning_buffer_bps = warning_buffer_bps;
+        market.at_risk_grace_secs = at_risk_grace_secs;
+        Ok(())
+    }
+
+    // Upgrades a Position, InsuranceFund or PriceFeed from any known older
+    // layout to the current one, topping up rent from `payer` if it grows.
+    // Permissionless: the result is fully determined by the existing data
+    // (plus the matching Market for pre-zero-copy Positions).
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        let info = ctx.accounts.account.to_account_info();
+
+        let (from_version, to_version, body) = {
+            let data = info.try_borrow_data()?;
+            require!(data.len() >= 8, ErrorCode::UnknownAccountLayout);
+            let (discriminator, body) = data.split_at(8);
+
+            if discriminator == Position::DISCRIMINATOR {
+                let (from_version, body) =
+                    upgrade_position_data(body, ctx.accounts.market.as_deref())?;
+                (from_version, Position::VERSION, body)
+            } else if discriminator == InsuranceFund::DISCRIMINATOR {
+                let body = upgrade_appended_layout(
+                    body,
+                    InsuranceFund::V0_LEN,
+                    InsuranceFund::VERSION_OFFSET,
+                    InsuranceFund::VERSION,
+                    InsuranceFund::INIT_SPACE,
+                )?;
+                (0, InsuranceFund::VERSION, body)
+            } else if discriminator == PriceFeed::DISCRIMINATOR {
+                let body = upgrade_appended_layout(
+                    body,
+                    PriceFeed::V0_LEN,
+                    PriceFeed::VERSION_OFFSET,
+                    PriceFeed::VERSION,
+                    PriceFeed::INIT_SPACE,
+                )?;
+                (0, PriceFeed::VERSION, body)
+            } else {
+                return err!(ErrorCode::UnknownAccountLayout);
+            }
+        };
+
+        resize_account(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + body.len(),
+        )?;
+        info.try_borrow_mut_data()?[8..].copy_from_slice(&body);
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            from_version,
+            to_version,
+        });
+
+        Ok(())
+    }
+
+    // --------- INSURANCE FUND ---------
+    pub fn initialize_insurance_fund(
+        ctx: Context<InitializeInsuranceFund>,
+        config: InsuranceWithdrawalConfig,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let insurance = &mut ctx.accounts.insurance_fund;
+
+        insurance.authority = ctx.accounts.authority.key();
+        insurance.balance = 0;
+        insurance.total_contributions = 0;
+        insurance.total_bad_debt_covered = 0;
+        insurance.utilization_ratio = 0;
+        insurance.pending_withdrawal_amount = 0;
+        insurance.pending_withdrawal_requested_at = 0;
+        insurance.version = InsuranceFund::VERSION;
+        apply_withdrawal_config(insurance, &config, clock.unix_timestamp)?;
+
+        Ok(())
+    }
+
+    // Sets the safeguards on a fund migrated from the pre-safeguard layout,
+    // which comes up with them zeroed, or tightens them; see
+    // `apply_withdrawal_config`.
+    pub fn configure_insurance_withdrawals(
+        ctx: Context<ManageInsuranceWithdrawal>,
+        config: InsuranceWithdrawalConfig,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let insurance = &mut ctx.accounts.insurance_fund;
+
+        require!(
+            insurance.pending_withdrawal_amount == 0,
+            ErrorCode::WithdrawalAlreadyPending
+        );
+        apply_withdrawal_config(insurance, &config, clock.unix_timestamp)
+    }
+
+    // Creates the token account backing `InsuranceFund.balance`; owned by
+    // the fund's vault-authority PDA, which signs withdrawals and bad-debt
+    // coverage.
+    pub fn initialize_insurance_vault(_ctx: Context<InitializeInsuranceVault>) -> Result<()> {
+        Ok(())
+    }
+
+    // Funds the global insurance fund: `amount` moves from the contributor
+    // into its vault and becomes available for withdrawals and bad debt.
+    pub fn contribute_insurance(ctx: Context<ContributeInsurance>, amount: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let insurance = &mut ctx.accounts.insurance_fund;
+
+        record_contribution(insurance, amount)?;
+        transfer_to_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.contributor_token_account,
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.contributor,
+            amount,
+        )?;
+
+        emit!(InsuranceContributed {
+            contributor: ctx.accounts.contributor.key(),
+            amount,
+            balance_after: insurance.balance,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_market_insurance_fund(
+        ctx: Context<InitializeMarketInsuranceFund>,
+        market_index: u16,
+        global_backstop_cap: u64,
+    ) -> Result<()> {
+        let market_insurance = &mut ctx.accounts.market_insurance_fund;
+
+        market_insurance.insurance_fund = ctx.accounts.insurance_fund.key();
+        market_insurance.market_index = market_index;
+        market_insurance.balance = 0;
+        market_insurance.total_contributions = 0;
+        market_insurance.total_bad_debt_covered = 0;
+        market_insurance.utilization_ratio = 0;
+        market_insurance.global_backstop_cap = global_backstop_cap;
+        market_insurance.global_backstop_drawn = 0;
+        market_insurance.bump = ctx.bumps.market_insurance_fund;
+
+        Ok(())
+    }
+
+    // Funds a market's sub-fund: `amount` moves from the contributor into the
+    // sub-fund's vault and becomes available to cover that market's bad debt.
+    pub fn contribute_market_insurance(
+        ctx: Context<ContributeMarketInsurance>,
+        amount: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let market_insurance = &mut ctx.accounts.market_insurance_fund;
+
+        record_market_contribution(market_insurance, amount)?;
+        transfer_to_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.contributor_token_account,
+            &ctx.accounts.market_insurance_vault,
+            &ctx.accounts.contributor,
+            amount,
+        )?;
+
+        emit!(MarketInsuranceContributed {
+            contributor: ctx.accounts.contributor.key(),
+            market_index: market_insurance.market_index,
+            amount,
+            balance_after: market_insurance.balance,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Step 1 of a withdrawal: record the request and its recipient; funds only
+    // move after the timelock, and only to that recipient.
+    pub fn request_insurance_withdrawal(
+        ctx: Context<RequestInsuranceWithdrawal>,
+        amount: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let insurance = &mut ctx.accounts.insurance_fund;
+
+        require!(amount > 0, ErrorCode::InvalidWithdrawalAmount);
+        require!(
+            insurance.pending_withdrawal_amount == 0,
+            ErrorCode::WithdrawalAlreadyPending
+        );
+        // Reject early if the fund is already at or below its reserve.
+        require!(
+            insurance.balance > insurance.min_reserve,
+            ErrorCode::InsuranceReserveBreached
+        );
+
+        insurance.pending_withdrawal_amount = amount;
+        insurance.pending_withdrawal_requested_at = clock.unix_timestamp;
+        insurance.pending_withdrawal_recipient = ctx.accounts.recipient.key();
+
+        emit!(InsuranceWithdrawalRequested {
+            authority: insurance.authority,
+            recipient: insurance.pending_withdrawal_recipient,
+            amount,
+            unlocks_at: clock
+                .unix_timestamp
+                .checked_add(insurance.withdrawal_timelock_secs)
+                .ok_or(error!(ErrorCode::MathOverflow))?,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Step 2 of a withdrawal: once the timelock has elapsed, and subject to the
+    // per-epoch cap and the minimum reserve, pay the pending request out of the
+    // vault to the recipient recorded at request time.
+    pub fn execute_insurance_withdrawal(
+        ctx: Context<ExecuteInsuranceWithdrawal>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let insurance = &mut ctx.accounts.insurance_fund;
+
+        let amount = insurance.pending_withdrawal_amount;
+        require!(amount > 0, ErrorCode::NoPendingWithdrawal);
+
+        let unlocks_at = insurance
+            .pending_withdrawal_requested_at
+            .checked_add(insurance.withdrawal_timelock_secs)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        require!(
+            clock.unix_timestamp >= unlocks_at,
+            ErrorCode::WithdrawalTimelockActive
+        );
+
+        roll_withdrawal_epoch(insurance, clock.unix_timestamp);
+
+        let epoch_cap = withdrawal_epoch_cap(
+            insurance.epoch_start_balance,
+            insurance.max_withdrawal_bps_per_epoch,
+        )?;
+        check_withdrawal_allowed(
+            insurance.balance,
+            insurance.min_reserve,
+            epoch_cap,
+            insurance.withdrawn_this_epoch,
+            amount,
+        )?;
+
+        insurance.balance -= amount;
+        insurance.withdrawn_this_epoch = insurance
+            .withdrawn_this_epoch
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        let recipient = insurance.pending_withdrawal_recipient;
+        clear_pending_withdrawal(insurance);
+
+        let insurance_key = insurance.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            INSURANCE_VAULT_AUTHORITY_SEED,
+            insurance_key.as_ref(),
+            &[ctx.bumps.insurance_vault_authority],
+        ]];
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.recipient,
+            &ctx.accounts.insurance_vault_authority,
+            signer_seeds,
+            amount,
+        )?;
+
+        emit!(InsuranceWithdrawalExecuted {
+            authority: insurance.authority,
+            recipient,
+            amount,
+            balance_after: insurance.balance,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_insurance_withdrawal(ctx: Context<ManageInsuranceWithdrawal>) -> Result<()> {
+        let insurance = &mut ctx.accounts.insurance_fund;
+
+        require!(
+            insurance.pending_withdrawal_amount > 0,
+            ErrorCode::NoPendingWithdrawal
+        );
+
+        clear_pending_withdrawal(insurance);
+
+        Ok(())
+    }
+}
+
+// ------------- ACCOUNTS / STRUCTS -------------
+
+// Fixed 128-byte zero-copy layout so accounts can be filtered with memcmp
+// (e.g. `market_index` at offset 8 + 56).
+//
+// Versions: 0 = borsh with heap `symbol` (LegacyPosition),
+//           1 = current; `version` sits at VERSION_OFFSET.
+#[account(zero_copy)]
+pub struct Position {
+    pub owner: Pubkey,
+    pub size: u64,         // size * 10^market.base_decimals
+    pub entry_price: u64,  // price * 10^market.price_decimals
+    pub collateral: u64,   // quote * 1e6
+    pub market_index: u16, // see Market
+    pub leverage: u16,
+    pub is_long: u8,       // bool
+    pub closed: u8,        // bool
+    pub version: u8,
+    pub _padding: [u8; 1],
+    pub at_risk_since: i64, // set by flag_at_risk, 0 = not flagged
+    pub _reserved: [u8; 56],
+}
+
+impl Position {
+    pub const LEN: usize = std::mem::size_of::<Position>();
+    pub const VERSION: u8 = 1;
+    pub const VERSION_OFFSET: usize = 62;
+
+    pub fn is_long(&self) -> bool {
+        self.is_long != 0
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed != 0
+    }
+
+    pub fn is_flagged_at_risk(&self) -> bool {
+        self.at_risk_since != 0
+    }
+}
+
+// Borsh layout used before Position became zero-copy (version 0). Only read by migrate_account.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyPosition {
+    pub owner: Pubkey,
+    pub symbol: String,
+    pub size: u64,
+    pub is_long: bool,
+    pub entry_price: u64,
+    pub collateral: u64,
+    pub leverage: u16,
+    pub closed: bool,
+}
+
+// PDA: [MARKET_SEED, market_index.to_le_bytes()]
+#[account]
+#[derive(InitSpace)]
+pub struct Market {
+    pub market_index: u16,
+    pub symbol: [u8; MAX_SYMBOL_LEN], // zero-padded, e.g. "BTC-PERP"
+    pub insurance_fund: Pubkey,       // admin scope
+    pub bump: u8,
+    pub price_decimals: u8,           // price feed units = price * 10^price_decimals
+    pub base_decimals: u8,            // position.size units = size * 10^base_decimals
+    pub min_position_notional: u64,   // quote * 1e6; partials leaving less close fully
+    pub warning_buffer_bps: u64,      // flag_at_risk below maintenance + this buffer
+    pub at_risk_grace_secs: i64,      // flag age required before a non-critical liquidation; 0 = off
+    pub max_open_interest: u64,       // per side, in size units; 0 = uncapped
+    pub max_notional_per_position: u64, // quote * 1e6, each Position separately; 0 = uncapped
+    pub open_interest_long: u64,      // size units
+    pub open_interest_short: u64,     // size units
+    pub price_feed: Pubkey,           // PriceFeed instructions must read
+}
+
+impl Market {
+    // Divisor turning size * price into quote units (1e6):
+    // 10^(base_decimals + price_decimals - QUOTE_DECIMALS).
+    pub fn value_scale(&self) -> Result<u128> {
+        value_scale_for(self.price_decimals, self.base_decimals)
+    }
+
+    pub fn warning_threshold_bps(&self, maintenance_bps: u64) -> u64 {
+        maintenance_bps.saturating_add(self.warning_buffer_bps)
+    }
+
+    // Records new exposure, enforcing the per-side OI cap and the per-position
+    // notional cap (`position_value` is after the increase). The notional cap is
+    // not per owner: one owner may hold several Position accounts.
+    pub fn add_open_interest(
+        &mut self,
+        is_long: bool,
+        size: u64,
+        position_value: u128,
+    ) -> Result<()> {
+        require!(
+            self.max_notional_per_position == 0
+                || position_value <= self.max_notional_per_position as u128,
+            ErrorCode::PositionNotionalCapExceeded
+        );
+
+        let open_interest = if is_long {
+            &mut self.open_interest_long
+        } else {
+            &mut self.open_interest_short
+        };
+        *open_interest = open_interest_after(*open_interest, size, self.max_open_interest)?;
+        Ok(())
+    }
+
+    // Only the bound feed; `initialize_market` always binds one.
+    pub fn accepts_price_feed(&self, price_feed: &Pubkey) -> bool {
+        self.price_feed == *price_feed
+    }
+
+    // Saturating: positions opened before OI tracking existed were never added.
+    pub fn reduce_open_interest(&mut self, is_long: bool, size: u64) {
+        let open_interest = if is_long {
+            &mut self.open_interest_long
+        } else {
+            &mut self.open_interest_short
+        };
+        *open_interest = open_interest.saturating_sub(size);
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct InsuranceFund {
+    pub authority: Pubkey,
+    pub balance: u64,                // total funds
+    pub total_contributions: u64,
+    pub total_bad_debt_covered: u64,
+    pub utilization_ratio: u64,      // basis points
+
+    // Version 0 ends here. Withdrawal safeguards; zeroed on migration, which
+    // blocks withdrawals (0 bps per epoch) until configure_insurance_withdrawals.
+    pub withdrawal_timelock_secs: i64,      // delay between request and execute
+    pub withdrawal_epoch_secs: i64,         // length of a rate-limit epoch
+    pub max_withdrawal_bps_per_epoch: u64,  // cap as bps of epoch_start_balance
+    pub min_reserve: u64,                   // balance floor, never withdrawn below
+    pub epoch_start: i64,                   // unix timestamp
+    pub epoch_start_balance: u64,           // balance snapshot when the epoch rolled
+    pub withdrawn_this_epoch: u64,
+    pub pending_withdrawal_amount: u64,     // 0 = no pending request
+    pub pending_withdrawal_requested_at: i64,
+
+    // Later fields are carved out of `_reserved`.
+    pub version: u8,
+    pub pending_withdrawal_recipient: Pubkey, // token account the pending request pays
+    pub _reserved: [u8; 32],
+}
+
+impl InsuranceFund {
+    pub const VERSION: u8 = 1;
+    pub const V0_LEN: usize = 32 + 8 * 4;
+    pub const VERSION_OFFSET: usize = Self::V0_LEN + 8 * 9;
+}
+
+// Per-market sub-fund, drawn before the global InsuranceFund.
+// PDA: [MARKET_INSURANCE_SEED, market_index.to_le_bytes()]
+#[account]
+#[derive(InitSpace)]
+pub struct MarketInsuranceFund {
+    pub insurance_fund: Pubkey,      // global fund acting as backstop
+    pub market_index: u16,
+    pub balance: u64,
+    pub total_contributions: u64,
+    pub total_bad_debt_covered: u64,
+    pub utilization_ratio: u64,      // basis points
+    pub global_backstop_cap: u64,    // max total draw on the global fund
+    pub global_backstop_drawn: u64,
+    pub bump: u8,
+}
+
+// Registry entry for a backstop liquidity provider.
+// PDA: [BACKSTOP_LP_SEED, owner]
+#[account]
+#[derive(InitSpace)]
+pub struct BackstopLp {
+    pub owner: Pubkey,
+    pub insurance_fund: Pubkey,       // registry the LP was admitted under
+    pub max_position_notional: u64,   // largest single position it will absorb
+    pub positions_taken: u64,
+    pub total_notional_taken: u64,
+    pub active: bool,
+    pub bump: u8,
+}
+
+// PDA: [LIQUIDATOR_STATS_SEED, liquidator]
+#[account]
+#[derive(InitSpace)]
+pub struct LiquidatorStats {
+    pub liquidator: Pubkey,
+    pub liquidation_count: u64,      // partial + full
+    pub total_reward: u64,           // quote * 1e6
+    pub last_liquidation_slot: u64,
+    pub bump: u8,
+}
+
+impl LiquidatorStats {
+    pub fn record(&mut self, reward: u64, slot: u64) -> Result<()> {
+        self.liquidation_count = self
+            .liquidation_count
+            .checked_add(1)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        self.total_reward = self
+            .total_reward
+            .checked_add(reward)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        self.last_liquidation_slot = slot;
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PositionRiskView {
+    pub liquidation_price: u64,
+    pub bankruptcy_price: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct InsuranceWithdrawalConfig {
+    pub timelock_secs: i64,
+    pub epoch_duration_secs: i64,
+    pub max_withdrawal_bps_per_epoch: u64,
+    pub min_reserve: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PriceFeed {
+    pub price: u64,          // mark price * 1e6
+    pub last_updated: i64,   // unix timestamp
+
+    // Version 0 ends here; later fields are carved out of `_reserved`.
+    pub version: u8,
+    pub _reserved: [u8; 32],
+}
+
+impl PriceFeed {
+    pub const VERSION: u8 = 1;
+    pub const V0_LEN: usize = Self::INIT_SPACE - 1 - 32;
+    pub const VERSION_OFFSET: usize = Self::V0_LEN;
+}
+
+// This matches your assignment's LiquidationRecord structure (as an event)
+#[event]
+pub struct LiquidationRecord {
+    pub position_owner: Pubkey,
+    pub liquidator: Pubkey,
+    pub market_index: u16,
+    pub liquidated_size: u64,
+    pub liquidation_price: u64,
+    pub margin_before: u64,  // bps
+    pub margin_after: u64,   // bps
+    pub liquidator_reward: u64,
+    pub bad_debt: u64,
+    pub covered_by_market_fund: u64,
+    pub covered_by_global_fund: u64,
+    pub market_fund_utilization: u64, // bps
+    pub global_fund_utilization: u64, // bps
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct PositionOpened {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub market_index: u16,
+    pub size: u64,
+    pub entry_price: u64,
+    pub collateral: u64,
+    pub leverage: u16,
+    pub is_long: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionIncreased {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub market_index: u16,
+    pub additional_size: u64,
+    pub size_after: u64,
+    pub entry_price_after: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionAtRisk {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub margin_ratio_bps: u64,
+    pub warning_threshold_bps: u64,
+    pub flagged: bool, // false when cleared
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CollateralDeposited {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub collateral_after: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CollateralWithdrawn {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub collateral_after: u64,
+    pub margin_after: u64, // bps
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BackstopTakeover {
+    pub position_owner: Pubkey,
+    pub backstop_lp: Pubkey,
+    pub lp_position: Pubkey,
+    pub market_index: u16,
+    pub size: u64,
+    pub takeover_price: u64, // bankruptcy price, the LP position's entry
+    pub mark_price: u64,
+    pub lp_collateral: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceWithdrawalRequested {
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub unlocks_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceWithdrawalExecuted {
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub balance_after: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceContributed {
+    pub contributor: Pubkey,
+    pub amount: u64,
+    pub balance_after: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketInsuranceContributed {
+    pub contributor: Pubkey,
+    pub market_index: u16,
+    pub amount: u64,
+    pub balance_after: u64,
+    pub timestamp: i64,
+}
+
+// ------------- INSTRUCTION CONTEXTS -------------
+
+#[derive(Accounts)]
+pub struct LiquidatePartial<'info> {
+    #[account(mut)]
+    pub position: AccountLoader<'info, Position>,
+
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    // Only touched when a dust remainder escalates to a full close.
+    #[account(mut)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_INSURANCE_SEED, market_insurance_fund.market_index.to_le_bytes().as_ref()],
+        bump = market_insurance_fund.bump,
+        has_one = insurance_fund,
+        constraint = market_insurance_fund.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+    )]
+    pub market_insurance_fund: Account<'info, MarketInsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [LIQUIDATOR_STATS_SEED, liquidator.key().as_ref()],
+        bump = liquidator_stats.bump,
+    )]
+    pub liquidator_stats: Option<Account<'info, LiquidatorStats>>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    // Bad debt is covered out of these into `collateral_vault`.
+    #[account(
+        mut,
+        seeds = [
+            MARKET_INSURANCE_VAULT_SEED,
+            market_insurance_fund.market_index.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub market_insurance_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_VAULT_SEED, insurance_fund.key().as_ref()],
+        bump,
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA that only signs for `insurance_vault`; holds no data.
+    #[account(seeds = [INSURANCE_VAULT_AUTHORITY_SEED, insurance_fund.key().as_ref()], bump)]
+    pub insurance_vault_authority: UncheckedAccount<'info>,
+
+    // Receives the reward.
+    #[account(mut)]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+
+    // Receives the equity left after a full close.
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == position.load()?.owner
+            @ ErrorCode::InvalidOwnerTokenAccount,
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateFull<'info> {
+    #[account(mut)]
+    pub position: AccountLoader<'info, Position>,
+
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(mut)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_INSURANCE_SEED, market_insurance_fund.market_index.to_le_bytes().as_ref()],
+        bump = market_insurance_fund.bump,
+        has_one = insurance_fund,
+        constraint = market_insurance_fund.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+    )]
+    pub market_insurance_fund: Account<'info, MarketInsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [LIQUIDATOR_STATS_SEED, liquidator.key().as_ref()],
+        bump = liquidator_stats.bump,
+    )]
+    pub liquidator_stats: Option<Account<'info, LiquidatorStats>>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    // Bad debt is covered out of these into `collateral_vault`.
+    #[account(
+        mut,
+        seeds = [
+            MARKET_INSURANCE_VAULT_SEED,
+            market_insurance_fund.market_index.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub market_insurance_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_VAULT_SEED, insurance_fund.key().as_ref()],
+        bump,
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA that only signs for `insurance_vault`; holds no data.
+    #[account(seeds = [INSURANCE_VAULT_AUTHORITY_SEED, insurance_fund.key().as_ref()], bump)]
+    pub insurance_vault_authority: UncheckedAccount<'info>,
+
+    // Receives the reward.
+    #[account(mut)]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+
+    // Receives the equity left after a full close.
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == position.load()?.owner
+            @ ErrorCode::InvalidOwnerTokenAccount,
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPosition<'info> {
+    #[account(init, payer = owner, space = 8 + Position::LEN)]
+    pub position: AccountLoader<'info, Position>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct IncreasePosition<'info> {
+    #[account(mut, has_one = owner)]
+    pub position: AccountLoader<'info, Position>,
+
+    pub owner: Signer<'info>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAtRisk<'info> {
+    #[account(mut)]
+    pub position: AccountLoader<'info, Position>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct DepositCollateral<'info> {
+    #[account(mut, has_one = owner)]
+    pub position: AccountLoader<'info, Position>,
+
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    #[account(mut, has_one = owner)]
+    pub position: AccountLoader<'info, Position>,
+
+    pub owner: Signer<'info>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateBackstop<'info> {
+    #[account(mut)]
+    pub position: AccountLoader<'info, Position>,
+
+    #[account(init, payer = lp, space = 8 + Position::LEN)]
+    pub lp_position: AccountLoader<'info, Position>,
+
+    #[account(
+        mut,
+        seeds = [BACKSTOP_LP_SEED, lp.key().as_ref()],
+        bump = backstop_lp.bump,
+        constraint = backstop_lp.active @ ErrorCode::BackstopLpInactive,
+    )]
+    pub backstop_lp: Account<'info, BackstopLp>,
+
+    #[account(mut)]
+    pub lp: Signer<'info>,
+
+    #[account()]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+        constraint = market.accepts_price_feed(&price_feed.key())
+            @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut)]
+    pub lp_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterBackstopLp<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + BackstopLp::INIT_SPACE,
+        seeds = [BACKSTOP_LP_SEED, lp.key().as_ref()],
+        bump,
+    )]
+    pub backstop_lp: Account<'info, BackstopLp>,
+
+    // LP consents to being registered
+    pub lp: Signer<'info>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ViewPositionRisk<'info> {
+    pub position: AccountLoader<'info, Position>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_index == position.load()?.market_index
+            @ ErrorCode::MarketMismatch,
+    )]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterLiquidator<'info> {
+    #[account(
+        init,
+        payer = liquidator,
+        space = 8 + LiquidatorStats::INIT_SPACE,
+        seeds = [LIQUIDATOR_STATS_SEED, liquidator.key().as_ref()],
+        bump,
+    )]
+    pub liquidator_stats: Account<'info, LiquidatorStats>,
+
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBackstopLp<'info> {
+    #[account(mut, has_one = insurance_fund)]
+    pub backstop_lp: Account<'info, BackstopLp>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarket<'info> {
+    #[account(mut, has_one = insurance_fund)]
+    pub market: Account<'info, Market>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeCollateralVault<'info> {
+    #[account(has_one = insurance_fund)]
+    pub market: Account<'info, Market>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    pub mint: Account<'info, Mint>,
+
+    // Owned by the Market PDA, which signs withdrawals.
+    #[account(
+        init,
+        payer = authority,
+        seeds = [COLLATERAL_VAULT_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = market,
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePriceFeed<'info> {
+    #[account(init, payer = authority, space = 8 + PriceFeed::INIT_SPACE)]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(mut, has_one = insurance_fund)]
+    pub market: Account<'info, Market>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePriceFeed<'info> {
+    #[account(mut)]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(
+        has_one = insurance_fund,
+        has_one = price_feed @ ErrorCode::PriceFeedMismatch,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeInsuranceFund<'info> {
+    #[account(init, payer = authority, space = 8 + InsuranceFund::INIT_SPACE)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializeMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Market::INIT_SPACE,
+        seeds = [MARKET_SEED, market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(init, payer = authority, space = 8 + PriceFeed::INIT_SPACE)]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    /// CHECK: may be in any historical layout, so it can't be typed;
+    /// ownership is checked here, discriminator and layout in the handler.
+    #[account(mut, owner = crate::ID)]
+    pub account: UncheckedAccount<'info>,
+
+    // Only needed for version 0 Positions (symbol -> market_index).
+    #[account(
+        seeds = [MARKET_SEED, market.market_index.to_le_bytes().as_ref()],
+        bump = market.bump,
+    )]
+    pub market: Option<Account<'info, Market>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializeMarketInsuranceFund<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketInsuranceFund::INIT_SPACE,
+        seeds = [MARKET_INSURANCE_SEED, market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub market_insurance_fund: Account<'info, MarketInsuranceFund>,
+
+    pub mint: Account<'info, Mint>,
+
+    // Holds the sub-fund's tokens; owned by the MarketInsuranceFund PDA.
+    #[account(
+        init,
+        payer = authority,
+        seeds = [MARKET_INSURANCE_VAULT_SEED, market_index.to_le_bytes().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = market_insurance_fund,
+    )]
+    pub market_insurance_vault: Account<'info, TokenAccount>,
+
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeMarketInsurance<'info> {
+    #[account(
+        mut,
+        seeds = [MARKET_INSURANCE_SEED, market_insurance_fund.market_index.to_le_bytes().as_ref()],
+        bump = market_insurance_fund.bump,
+    )]
+    pub market_insurance_fund: Account<'info, MarketInsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_INSURANCE_VAULT_SEED,
+            market_insurance_fund.market_index.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub market_insurance_vault: Account<'info, TokenAccount>,
+
+    pub contributor: Signer<'info>,
+
+    #[account(mut)]
+    pub contributor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeInsuranceVault<'info> {
+    #[account(has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    pub mint: Account<'info, Mint>,
+
+    // Holds the global fund's tokens; owned by `insurance_vault_authority`.
+    #[account(
+        init,
+        payer = authority,
+        seeds = [INSURANCE_VAULT_SEED, insurance_fund.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = insurance_vault_authority,
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA that only signs for the vault; holds no data.
+    #[account(seeds = [INSURANCE_VAULT_AUTHORITY_SEED, insurance_fund.key().as_ref()], bump)]
+    pub insurance_vault_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeInsurance<'info> {
+    #[account(mut)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_VAULT_SEED, insurance_fund.key().as_ref()],
+        bump,
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+
+    pub contributor: Signer<'info>,
+
+    #[account(mut)]
+    pub contributor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ManageInsuranceWithdrawal<'info> {
+    #[account(mut, has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RequestInsuranceWithdrawal<'info> {
+    #[account(mut, has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(seeds = [INSURANCE_VAULT_SEED, insurance_fund.key().as_ref()], bump)]
+    pub insurance_vault: Account<'info, TokenAccount>,
+
+    // Fixed at request time: execution can only pay this account.
+    #[account(token::mint = insurance_vault.mint)]
+    pub recipient: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteInsuranceWithdrawal<'info> {
+    #[account(mut, has_one = authority)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_VAULT_SEED, insurance_fund.key().as_ref()],
+        bump,
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA that only signs for the vault; holds no data.
+    #[account(seeds = [INSURANCE_VAULT_AUTHORITY_SEED, insurance_fund.key().as_ref()], bump)]
+    pub insurance_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = insurance_fund.pending_withdrawal_recipient
+            @ ErrorCode::InvalidWithdrawalRecipient,
+    )]
+    pub recipient: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// ------------- POSITION RISK VIEWS -------------
+// Pure helpers shared by `simulate_liquidation_price` and off-chain callers
+// (depend on this crate with the `no-entrypoint` feature).
+
+pub use liquidation_math::{MaintenanceTiers, MAINTENANCE_TIERS};
+
+// Mark price at which the position's equity reaches zero.
+pub fn bankruptcy_price(position: &Position, value_scale: u128) -> Result<u64> {
+    bankruptcy_price_for_size(
+        position.size,
+        position.entry_price,
+        position.collateral,
+        position.is_long(),
+        value_scale,
+    )
+}
+
+// The LP's side of a backstop takeover: the user's size and direction,
+// entered at the user's bankruptcy price and backed by `lp_collateral` alone.
+// The user's equity at mark, what `liquidate_full` would realize before the
+// reward, becomes the LP's unrealized PnL; the user's collateral stays in the
+// vault to pay it.
+fn backstop_takeover_position(
+    position: &Position,
+    lp_owner: Pubkey,
+    lp_collateral: u64,
+    value_scale: u128,
+) -> Result<Position> {
+    let takeover_price = bankruptcy_price(position, value_scale)?;
+    require!(takeover_price > 0, ErrorCode::InvalidPrice);
+
+    Ok(Position {
+        owner: lp_owner,
+        size: position.size,
+        entry_price: takeover_price,
+        collateral: lp_collateral,
+        market_index: position.market_index,
+        leverage: position.leverage,
+        is_long: position.is_long,
+        closed: 0,
+        version: Position::VERSION,
+        _padding: [0; 1],
+        at_risk_since: 0,
+        _reserved: [0; 56],
+    })
+}
+
+// Mark price at which the margin ratio reaches maintenance: longs become
+// liquidatable below it, shorts above it. Returns 0 for a long that can
+// never be liquidated. Rounded toward the mark (liquidates no later than exact math).
+pub fn liquidation_price(
+    position: &Position,
+    tiers: &MaintenanceTiers,
+    value_scale: u128,
+) -> Result<u64> {
+    let bankruptcy = bankruptcy_price(position, value_scale)?;
+
+    let mut maintenance_bps = maintenance_bps_for_leverage(position.leverage);
+    let mut price = price_at_maintenance(bankruptcy, position.is_long(), maintenance_bps)?;
+
+    // The notional tier depends on the price itself. Only ever raising the
+    // requirement terminates (tiers are finite) and errs toward liquidating earlier.
+    loop {
+        let notional = position_value_for_size(position.size, price, value_scale)?;
+        let required = maintenance_bps.max(tiers.maintenance_bps(position.leverage, notional));
+        if required == maintenance_bps {
+            return Ok(price);
+        }
+        maintenance_bps = required;
+        price = price_at_maintenance(bankruptcy, position.is_long(), maintenance_bps)?;
+    }
+}
+
+// Solves equity / value = maintenance for the mark price:
+//   long:  bankruptcy * 10_000 / (10_000 - maintenance_bps)
+//   short: bankruptcy * 10_000 / (10_000 + maintenance_bps)
+fn price_at_maintenance(bankruptcy: u64, is_long: bool, maintenance_bps: u64) -> Result<u64> {
+    let price = if is_long {
+        mul_div_u128(
+            bankruptcy as u128,
+            BPS_DENOM as u128,
+            BPS_DENOM.saturating_sub(maintenance_bps) as u128,
+            Rounding::Ceil,
+        )
+    } else {
+        mul_div_u128(
+            bankruptcy as u128,
+            BPS_DENOM as u128,
+            (BPS_DENOM + maintenance_bps) as u128,
+            Rounding::Floor,
+        )
+    }
+    .ok_or(error!(ErrorCode::MathOverflow))?;
+
+    u64::try_from(price).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+// ------------- HELPER FUNCTIONS -------------
+
+// Stricter of the leverage tier and the notional tier.
+fn effective_maintenance_bps(leverage: u16, notional: u128) -> u64 {
+    MAINTENANCE_TIERS.maintenance_bps(leverage, notional)
+}
+
+// Returns (from_version, upgraded body without discriminator).
+fn upgrade_position_data(body: &[u8], market: Option<&Market>) -> Result<(u8, Vec<u8>)> {
+    // Decided by the version byte: a legacy body can have any length
+    // (64 + symbol length), including the current one.
+    if body.len() == Position::LEN && body[Position::VERSION_OFFSET] == Position::VERSION {
+        return err!(ErrorCode::AccountAlreadyMigrated);
+    }
+
+    // Version 0: borsh with a heap symbol, resolved through the Market.
+    let market = market.ok_or(error!(ErrorCode::MarketRequired))?;
+    let legacy = LegacyPosition::deserialize(&mut &body[..])
+        .map_err(|_| error!(ErrorCode::InvalidLegacyPosition))?;
+    require!(
+        symbol_bytes(&legacy.symbol)? == market.symbol,
+        ErrorCode::MarketMismatch
+    );
+
+    let upgraded = Position {
+        owner: legacy.owner,
+        size: legacy.size,
+        entry_price: legacy.entry_price,
+        collateral: legacy.collateral,
+        market_index: market.market_index,
+        leverage: legacy.leverage,
+        is_long: legacy.is_long as u8,
+        closed: legacy.closed as u8,
+        version: Position::VERSION,
+        _padding: [0; 1],
+        at_risk_since: 0,
+        _reserved: [0; 56],
+    };
+    Ok((0, bytemuck::bytes_of(&upgraded).to_vec()))
+}
+
+// For borsh accounts whose new fields were appended: old body, zeroed new
+// fields up to `version_offset`, version, zeroed reserve.
+fn upgrade_appended_layout(
+    body: &[u8],
+    v0_len: usize,
+    version_offset: usize,
+    version: u8,
+    current_len: usize,
+) -> Result<Vec<u8>> {
+    require!(body.len() != current_len, ErrorCode::AccountAlreadyMigrated);
+    require!(body.len() == v0_len, ErrorCode::UnknownAccountLayout);
+
+    let mut upgraded = body.to_vec();
+    upgraded.resize(version_offset, 0);
+    upgraded.push(version);
+    upgraded.resize(current_len, 0);
+    Ok(upgraded)
+}
+
+fn resize_account<'info>(
+    info: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_len: usize,
+) -> Result<()> {
+    let required = Rent::get()?.minimum_balance(new_len);
+    let current = info.lamports();
+    if required > current {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: info.clone(),
+                },
+            ),
+            required - current,
+        )?;
+    }
+    info.realloc(new_len, true)?;
+    Ok(())
+}
+
+pub fn value_scale_for(price_decimals: u8, base_decimals: u8) -> Result<u128> {
+    liquidation_math::value_scale_for(price_decimals, base_decimals)
+        .ok_or(error!(ErrorCode::InvalidMarketDecimals))
+}
+
+// Zero-padded fixed-size symbol, e.g. "BTC-PERP".
+fn symbol_bytes(symbol: &str) -> Result<[u8; MAX_SYMBOL_LEN]> {
+    require!(
+        !symbol.is_empty() && symbol.len() <= MAX_SYMBOL_LEN,
+        ErrorCode::InvalidSymbol
+    );
+    let mut out = [0u8; MAX_SYMBOL_LEN];
+    out[..symbol.len()].copy_from_slice(symbol.as_bytes());
+    Ok(out)
+}
+
+// The shared math crate returns `None` on overflow; these map it to MathOverflow.
+
+fn position_value_for_size(size: u64, price: u64, value_scale: u128) -> Result<u128> {
+    liquidation_math::position_value_for_size(size, price, value_scale)
+        .ok_or(error!(ErrorCode::MathOverflow))
+}
+
+fn realized_pnl_for_size(
+    size: u64,
+    entry_price: u64,
+    mark_price: u64,
+    is_long: bool,
+    value_scale: u128,
+) -> Result<i128> {
+    liquidation_math::realized_pnl_for_size(size, entry_price, mark_price, is_long, value_scale)
+        .ok_or(error!(ErrorCode::MathOverflow))
+}
+
+fn compute_margin_ratio(
+    size: u64,
+    entry_price: u64,
+    collateral: u64,
+    is_long: bool,
+    mark_price: u64,
+    value_scale: u128,
+) -> Result<(u64, u128)> {
+    liquidation_math::compute_margin_ratio(
+        size,
+        entry_price,
+        collateral,
+        is_long,
+        mark_price,
+        value_scale,
+    )
+    .ok_or(error!(ErrorCode::MathOverflow))
+}
+
+// Rounded up: the liquidator is never shorted at the user's benefit.
+fn liquidator_reward_for_value(value: u128) -> Result<u64> {
+    let reward = mul_div_u128(
+        value,
+        LIQUIDATOR_REWARD_BPS as u128,
+        BPS_DENOM as u128,
+        Rounding::Ceil,
+    )
+    .ok_or(error!(ErrorCode::MathOverflow))?;
+    u64::try_from(reward).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+// Mark price at which equity (collateral + unrealized PnL) reaches zero.
+fn bankruptcy_price_for_size(
+    size: u64,
+    entry_price: u64,
+    collateral: u64,
+    is_long: bool,
+    value_scale: u128,
+) -> Result<u64> {
+    require!(size > 0, ErrorCode::InvalidLiquidationSize);
+
+    // Floor the offset so the bankruptcy price sits no further from entry than exact math.
+    let offset = mul_div_u128(collateral as u128, value_scale, size as u128, Rounding::Floor)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+
+    let price = if is_long {
+        (entry_price as u128).saturating_sub(offset)
+    } else {
+        (entry_price as u128)
+            .checked_add(offset)
+            .ok_or(error!(ErrorCode::MathOverflow))?
+    };
+
+    u64::try_from(price).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+fn apply_pnl(collateral: u64, pnl: i128) -> Result<u64> {
+    liquidation_math::apply_pnl(collateral, pnl).ok_or(error!(ErrorCode::MathOverflow))
+}
+
+// Returns (from_market, from_global). The market sub-fund pays first; the
+// global fund only covers what is left, bounded by the market's backstop allowance.
+fn split_bad_debt_coverage(
+    bad_debt: u64,
+    market_balance: u64,
+    global_balance: u64,
+    global_backstop_remaining: u64,
+) -> (u64, u64) {
+    let from_market = market_balance.min(bad_debt);
+    let from_global = (bad_debt - from_market)
+        .min(global_balance)
+        .min(global_backstop_remaining);
+    (from_market, from_global)
+}
+
+fn utilization_bps(total_bad_debt_covered: u64, total_contributions: u64) -> Option<u64> {
+    total_bad_debt_covered
+        .saturating_mul(BPS_DENOM)
+        .checked_div(total_contributions)
+}
+
+fn open_interest_after(open_interest: u64, added: u64, max_open_interest: u64) -> Result<u64> {
+    let after = open_interest
+        .checked_add(added)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    require!(
+        max_open_interest == 0 || after <= max_open_interest,
+        ErrorCode::OpenInterestCapExceeded
+    );
+    Ok(after)
+}
+
+// Size-weighted average entry, rounded against the owner (up for longs,
+// down for shorts).
+fn average_entry_price(
+    size: u64,
+    entry_price: u64,
+    added_size: u64,
+    mark_price: u64,
+    is_long: bool,
+) -> Result<u64> {
+    let total_size = (size as u128)
+        .checked_add(added_size as u128)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    let weighted = (size as u128)
+        .checked_mul(entry_price as u128)
+        .and_then(|v| v.checked_add((added_size as u128).checked_mul(mark_price as u128)?))
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    let rounding = if is_long { Rounding::Ceil } else { Rounding::Floor };
+    let price = div_u128(weighted, total_size, rounding).ok_or(error!(ErrorCode::MathOverflow))?;
+    u64::try_from(price).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+fn check_initial_margin(position: &Position, mark_price: u64, value_scale: u128) -> Result<()> {
+    let (margin_ratio_bps, position_value) = compute_margin_ratio(
+        position.size,
+        position.entry_price,
+        position.collateral,
+        position.is_long(),
+        mark_price,
+        value_scale,
+    )?;
+    require!(
+        margin_ratio_bps >= initial_margin_bps(position.leverage, position_value),
+        ErrorCode::InitialMarginViolated
+    );
+    Ok(())
+}
+
+// Moves `amount` from a token account `authority` controls into a program vault.
+fn transfer_to_vault<'info>(
+    token_program: &Program<'info, Token>,
+    from: &Account<'info, TokenAccount>,
+    vault: &Account<'info, TokenAccount>,
+    authority: &Signer<'info>,
+    amount: u64,
+) -> Result<()> {
+    token::transfer(
+        CpiContext::new(
+            token_program.to_account_info(),
+            Transfer {
+                from: from.to_account_info(),
+                to: vault.to_account_info(),
+                authority: authority.to_account_info(),
+            },
+        ),
+        amount,
+    )
+}
+
+// Moves `amount` out of a program-owned token account, signed by the PDA
+// that owns it. Zero amounts are skipped.
+fn transfer_from_vault<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: vault.to_account_info(),
+                to: to.to_account_info(),
+                authority: authority.clone(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+// Moves a market sub-fund's share of bad debt from its vault into the
+// collateral vault, signed by the MarketInsuranceFund PDA.
+fn cover_from_market_fund<'info>(
+    token_program: &Program<'info, Token>,
+    market_insurance: &Account<'info, MarketInsuranceFund>,
+    market_insurance_vault: &Account<'info, TokenAccount>,
+    collateral_vault: &Account<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    let market_index = market_insurance.market_index.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        MARKET_INSURANCE_SEED,
+        market_index.as_ref(),
+        &[market_insurance.bump],
+    ]];
+    transfer_from_vault(
+        token_program,
+        market_insurance_vault,
+        collateral_vault,
+        &market_insurance.to_account_info(),
+        signer_seeds,
+        amount,
+    )
+}
+
+// Moves the global fund's share of bad debt from its vault into the
+// collateral vault, signed by the fund's vault-authority PDA.
+fn cover_from_global_fund<'info>(
+    token_program: &Program<'info, Token>,
+    insurance_fund: &Pubkey,
+    insurance_vault: &Account<'info, TokenAccount>,
+    insurance_vault_authority: &AccountInfo<'info>,
+    insurance_vault_authority_bump: u8,
+    collateral_vault: &Account<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        INSURANCE_VAULT_AUTHORITY_SEED,
+        insurance_fund.as_ref(),
+        &[insurance_vault_authority_bump],
+    ]];
+    transfer_from_vault(
+        token_program,
+        insurance_vault,
+        collateral_vault,
+        insurance_vault_authority,
+        signer_seeds,
+        amount,
+    )
+}
+
+// Pays each `(token account, amount)` out of the market's collateral vault,
+// signed by the Market PDA.
+fn pay_out_of_collateral_vault<'info>(
+    token_program: &Program<'info, Token>,
+    market: &Account<'info, Market>,
+    collateral_vault: &Account<'info, TokenAccount>,
+    payouts: &[(&Account<'info, TokenAccount>, u64)],
+) -> Result<()> {
+    let market_index = market.market_index.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[MARKET_SEED, market_index.as_ref(), &[market.bump]]];
+    for (to, amount) in payouts {
+        transfer_from_vault(
+            token_program,
+            collateral_vault,
+            to,
+            &market.to_account_info(),
+            signer_seeds,
+            *amount,
+        )?;
+    }
+    Ok(())
+}
+
+fn set_price(feed: &mut PriceFeed, price: u64, now: i64) -> Result<()> {
+    require!(price > 0, ErrorCode::InvalidPrice);
+    feed.price = price;
+    feed.last_updated = now;
+    Ok(())
+}
+
+// (margin ratio, warning threshold) at a fresh oracle price.
+fn at_risk_margins(
+    position: &Position,
+    price_feed: &PriceFeed,
+    market: &Market,
+    now: i64,
+) -> Result<(u64, u64)> {
+    require!(!position.is_closed(), ErrorCode::PositionClosed);
+
+    let age = now - price_feed.last_updated;
+    require!(age <= MAX_ORACLE_STALENESS_SECS, ErrorCode::StaleOraclePrice);
+
+    let (margin_ratio_bps, position_value) = compute_margin_ratio(
+        position.size,
+        position.entry_price,
+        position.collateral,
+        position.is_long(),
+        price_feed.price,
+        market.value_scale()?,
+    )?;
+    let maintenance_bps = effective_maintenance_bps(position.leverage, position_value);
+
+    Ok((margin_ratio_bps, market.warning_threshold_bps(maintenance_bps)))
+}
+
+// Liquidations (partial, full or backstop) are allowed once the flag is
+// `grace_secs` old, immediately when the grace period is disabled, and
+// immediately for critical positions (margin below
+// CRITICAL_MAINTENANCE_FRACTION_BPS of maintenance). Public so the
+// off-chain executor can tell when to flag a position and when to retry.
+pub fn liquidation_grace_elapsed(
+    at_risk_since: i64,
+    grace_secs: i64,
+    margin_ratio_bps: u64,
+    maintenance_bps: u64,
+    now: i64,
+) -> bool {
+    if grace_secs <= 0 {
+        return true;
+    }
+    let critical_bps = maintenance_bps * CRITICAL_MAINTENANCE_FRACTION_BPS / BPS_DENOM;
+    if margin_ratio_bps < critical_bps {
+        return true;
+    }
+    at_risk_since != 0 && now.saturating_sub(at_risk_since) >= grace_secs
+}
+
+// A partial that can't reduce the position (size 1) or would leave less than
+// the market's minimum notional is escalated to a full close.
+fn partial_leaves_dust(
+    size: u64,
+    liq_size: u64,
+    mark_price: u64,
+    value_scale: u128,
+    min_position_notional: u64,
+) -> Result<bool> {
+    if liq_size == 0 {
+        return Ok(true);
+    }
+    let remaining_size = size
+        .checked_sub(liq_size)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    let remaining_value = position_value_for_size(remaining_size, mark_price, value_scale)?;
+    Ok(remaining_value < min_position_notional as u128)
+}
+
+// Outcome of closing a whole position at mark.
+struct FullLiquidation {
+    position_owner: Pubkey,
+    market_index: u16,
+    liquidated_size: u64,
+    liquidation_price: u64,
+    reward_paid: u64,
+    user_refund: u64, // equity left after the reward, returned to the owner
+    bad_debt: u64,
+    covered_by_market: u64,
+    covered_by_global: u64,
+    market_fund_utilization: u64,
+    global_fund_utilization: u64,
+}
+
+impl FullLiquidation {
+    fn into_record(
+        self,
+        liquidator: Pubkey,
+        margin_before: u64,
+        timestamp: i64,
+    ) -> LiquidationRecord {
+        LiquidationRecord {
+            position_owner: self.position_owner,
+            liquidator,
+            market_index: self.market_index,
+            liquidated_size: self.liquidated_size,
+            liquidation_price: self.liquidation_price,
+            margin_before,
+            margin_after: 0,
+            liquidator_reward: self.reward_paid,
+            bad_debt: self.bad_debt,
+            covered_by_market_fund: self.covered_by_market,
+            covered_by_global_fund: self.covered_by_global,
+            market_fund_utilization: self.market_fund_utilization,
+            global_fund_utilization: self.global_fund_utilization,
+            timestamp,
+        }
+    }
+}
+
+// Realizes full PnL, pays the reward out of equity, covers any shortfall from
+// the insurance funds and closes the position. Shared by `liquidate_full` and
+// partials that escalate because they would leave dust.
+fn settle_full_liquidation(
+    position: &mut Position,
+    insurance: &mut InsuranceFund,
+    market_insurance: &mut MarketInsuranceFund,
+    mark_price: u64,
+    position_value: u128,
+    value_scale: u128,
+) -> Result<FullLiquidation> {
+    let pnl_full = realized_pnl_for_size(
+        position.size,
+        position.entry_price,
+        mark_price,
+        position.is_long(),
+        value_scale,
+    )?;
+
+    let equity_after_pnl = apply_pnl(position.collateral, pnl_full)?;
+
+    // Reward based on full notional
+    let liquidator_reward = liquidator_reward_for_value(position_value)?;
+
+    // Without enough margin to pay the reward in full, the shortfall is bad debt
+    let reward_paid = liquidator_reward.min(equity_after_pnl);
+    let user_refund = equity_after_pnl - reward_paid;
+    let bad_debt = liquidator_reward - reward_paid;
+
+    // Cover bad debt: market sub-fund first, then the global fund up to
+    // the market's remaining backstop allowance
+    let (covered_by_market, covered_by_global) = split_bad_debt_coverage(
+        bad_debt,
+        market_insurance.balance,
+        insurance.balance,
+        market_insurance
+            .global_backstop_cap
+            .saturating_sub(market_insurance.global_backstop_drawn),
+    );
+
+    market_insurance.balance -= covered_by_market;
+    market_insurance.total_bad_debt_covered = market_insurance
+        .total_bad_debt_covered
+        .saturating_add(covered_by_market);
+    market_insurance.global_backstop_drawn = market_insurance
+        .global_backstop_drawn
+        .saturating_add(covered_by_global);
+
+    insurance.balance -= covered_by_global;
+    insurance.total_bad_debt_covered = insurance
+        .total_bad_debt_covered
+        .saturating_add(covered_by_global);
+
+    // Update utilization
+    if let Some(ratio) = utilization_bps(
+        market_insurance.total_bad_debt_covered,
+        market_insurance.total_contributions,
+    ) {
+        market_insurance.utilization_ratio = ratio;
+    }
+    if let Some(ratio) = utilization_bps(
+        insurance.total_bad_debt_covered,
+        insurance.total_contributions,
+    ) {
+        insurance.utilization_ratio = ratio;
+    }
+
+    // Close position logically
+    let liquidated_size = position.size;
+    position.size = 0;
+    position.collateral = 0;
+    position.closed = 1;
+
+    Ok(FullLiquidation {
+        position_owner: position.owner,
+        market_index: position.market_index,
+        liquidated_size,
+        liquidation_price: mark_price,
+        reward_paid,
+        user_refund,
+        bad_debt,
+        covered_by_market,
+        covered_by_global,
+        market_fund_utilization: market_insurance.utilization_ratio,
+        global_fund_utilization: insurance.utilization_ratio,
+    })
+}
+
+// Credits a contribution to the global fund and refreshes its utilization.
+fn record_contribution(insurance: &mut InsuranceFund, amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::InvalidContributionAmount);
+
+    insurance.balance = insurance
+        .balance
+        .checked_add(amount)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    insurance.total_contributions = insurance
+        .total_contributions
+        .checked_add(amount)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    if let Some(ratio) =
+        utilization_bps(insurance.total_bad_debt_covered, insurance.total_contributions)
+    {
+        insurance.utilization_ratio = ratio;
+    }
+
+    Ok(())
+}
+
+// Credits a contribution to a market sub-fund and refreshes its utilization.
+fn record_market_contribution(
+    market_insurance: &mut MarketInsuranceFund,
+    amount: u64,
+) -> Result<()> {
+    require!(amount > 0, ErrorCode::InvalidContributionAmount);
+
+    market_insurance.balance = market_insurance
+        .balance
+        .checked_add(amount)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    market_insurance.total_contributions = market_insurance
+        .total_contributions
+        .checked_add(amount)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    if let Some(ratio) = utilization_bps(
+        market_insurance.total_bad_debt_covered,
+        market_insurance.total_contributions,
+    ) {
+        market_insurance.utilization_ratio = ratio;
+    }
+
+    Ok(())
+}
+
+// Validates and stores the withdrawal safeguards. The first configuration (a
+// new fund, or one migrated with them zeroed) seeds the epoch from the current
+// balance so its cap reflects what the fund holds. After that they can only be
+// tightened, and the running epoch is kept: an authority key alone can neither
+// loosen the limits nor reopen the epoch's cap.
+fn apply_withdrawal_config(
+    insurance: &mut InsuranceFund,
+    config: &InsuranceWithdrawalConfig,
+    now: i64,
+) -> Result<()> {
+    require!(config.timelock_secs > 0, ErrorCode::InvalidInsuranceConfig);
+    require!(config.epoch_duration_secs > 0, ErrorCode::InvalidInsuranceConfig);
+    require!(
+        config.max_withdrawal_bps_per_epoch <= MAX_INSURANCE_WITHDRAWAL_BPS,
+        ErrorCode::InvalidInsuranceConfig
+    );
+
+    let configured = insurance.withdrawal_epoch_secs > 0;
+    if configured {
+        require!(
+            config.timelock_secs >= insurance.withdrawal_timelock_secs
+                && config.epoch_duration_secs >= insurance.withdrawal_epoch_secs
+                && config.max_withdrawal_bps_per_epoch <= insurance.max_withdrawal_bps_per_epoch
+                && config.min_reserve >= insurance.min_reserve,
+            ErrorCode::InsuranceConfigLoosened
+        );
+    }
+
+    insurance.withdrawal_timelock_secs = config.timelock_secs;
+    insurance.withdrawal_epoch_secs = config.epoch_duration_secs;
+    insurance.max_withdrawal_bps_per_epoch = config.max_withdrawal_bps_per_epoch;
+    insurance.min_reserve = config.min_reserve;
+    if !configured {
+        insurance.epoch_start = now;
+        insurance.epoch_start_balance = insurance.balance;
+        insurance.withdrawn_this_epoch = 0;
+    }
+    Ok(())
+}
+
+fn clear_pending_withdrawal(insurance: &mut InsuranceFund) {
+    insurance.pending_withdrawal_amount = 0;
+    insurance.pending_withdrawal_requested_at = 0;
+    insurance.pending_withdrawal_recipient = Pubkey::default();
+}
+
+// Start a new rate-limit epoch if the current one has elapsed.
+fn roll_withdrawal_epoch(insurance: &mut InsuranceFund, now: i64) {
+    let epoch_end = insurance
+        .epoch_start
+        .saturating_add(insurance.withdrawal_epoch_secs);
+    if now >= epoch_end {
+        insurance.epoch_start = now;
+        insurance.epoch_start_balance = insurance.balance;
+        insurance.withdrawn_this_epoch = 0;
+    }
+}
+
+fn withdrawal_epoch_cap(epoch_start_balance: u64, max_withdrawal_bps: u64) -> Result<u64> {
+    let cap = (epoch_start_balance as u128)
+        .checked_mul(max_withdrawal_bps as u128)
+        .ok_or(error!(ErrorCode::MathOverflow))?
+        / BPS_DENOM as u128;
+    Ok(cap as u64)
+}
+
+fn check_withdrawal_allowed(
+    balance: u64,
+    min_reserve: u64,
+    epoch_cap: u64,
+    withdrawn_this_epoch: u64,
+    amount: u64,
+) -> Result<()> {
+    require!(balance > min_reserve, ErrorCode::InsuranceReserveBreached);
+    let remaining = balance
+        .checked_sub(amount)
+        .ok_or(error!(ErrorCode::InsuranceReserveBreached))?;
+    require!(remaining >= min_reserve, ErrorCode::InsuranceReserveBreached);
+
+    let withdrawn_after = withdrawn_this_epoch
+        .checked_add(amount)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    require!(
+        withdrawn_after <= epoch_cap,
+        ErrorCode::WithdrawalRateLimitExceeded
+    );
+
+    Ok(())
+}
+
+// ------------- ERRORS -------------
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Position is healthy, cannot be liquidated")]
+    PositionHealthy,
+    #[msg("Oracle price is stale")]
+    StaleOraclePrice,
+    #[msg("Invalid liquidation size")]
+    InvalidLiquidationSize,
+    #[msg("Position already closed")]
+    PositionClosed,
+    #[msg("Invalid insurance fund configuration")]
+    InvalidInsuranceConfig,
+    #[msg("Withdrawal amount must be greater than zero")]
+    InvalidWithdrawalAmount,
+    #[msg("A withdrawal request is already pending")]
+    WithdrawalAlreadyPending,
+    #[msg("No pending withdrawal request")]
+    NoPendingWithdrawal,
+    #[msg("Withdrawal timelock has not elapsed")]
+    WithdrawalTimelockActive,
+    #[msg("Withdrawal exceeds the per-epoch limit")]
+    WithdrawalRateLimitExceeded,
+    #[msg("Withdrawal would breach the insurance fund minimum reserve")]
+    InsuranceReserveBreached,
+    #[msg("Invalid market symbol")]
+    InvalidSymbol,
+    #[msg("Backstop LP is not active")]
+    BackstopLpInactive,
+    #[msg("Position exceeds backstop LP capacity")]
+    BackstopCapacityExceeded,
+    #[msg("Backstop LP collateral is insufficient for the takeover")]
+    BackstopUndercollateralized,
+    #[msg("Collateral amount must be greater than zero")]
+    InvalidCollateralAmount,
+    #[msg("Insufficient collateral")]
+    InsufficientCollateral,
+    #[msg("Withdrawal would put the position below initial margin")]
+    InitialMarginViolated,
+    #[msg("Account does not belong to this market")]
+    MarketMismatch,
+    #[msg("Account already uses the current layout")]
+    AccountAlreadyMigrated,
+    #[msg("Account is not a legacy Position")]
+    InvalidLegacyPosition,
+    #[msg("Unknown account type or layout")]
+    UnknownAccountLayout,
+    #[msg("Market account is required to migrate this Position")]
+    MarketRequired,
+    #[msg("Invalid market price / base decimals")]
+    InvalidMarketDecimals,
+    #[msg("Invalid at-risk warning configuration")]
+    InvalidAtRiskConfig,
+    #[msg("Position margin is above the warning threshold")]
+    PositionNotAtRisk,
+    #[msg("Position is already flagged at risk")]
+    AlreadyFlaggedAtRisk,
+    #[msg("Position is not flagged at risk")]
+    NotFlaggedAtRisk,
+    #[msg("Position margin is still below the warning threshold")]
+    PositionNotRecovered,
+    #[msg("Position must be flagged at risk for the grace period before liquidation")]
+    AtRiskGracePeriodActive,
+    #[msg("Invalid position size")]
+    InvalidPositionSize,
+    #[msg("Invalid leverage")]
+    InvalidLeverage,
+    #[msg("Market open interest cap exceeded")]
+    OpenInterestCapExceeded,
+    #[msg("Position notional cap exceeded")]
+    PositionNotionalCapExceeded,
+    #[msg("Price feed is not the market's price feed")]
+    PriceFeedMismatch,
+    #[msg("Price must be greater than zero")]
+    InvalidPrice,
+    #[msg("Contribution must be greater than zero")]
+    InvalidContributionAmount,
+    #[msg("Withdrawal safeguards can only be tightened once configured")]
+    InsuranceConfigLoosened,
+    #[msg("Recipient is not the one recorded with the withdrawal request")]
+    InvalidWithdrawalRecipient,
+    #[msg("Token account is not owned by the position owner")]
+    InvalidOwnerTokenAccount,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_maintenance_bps_takes_stricter_tier() {
+        // Small position: leverage tier dominates
+        assert_eq!(effective_maintenance_bps(10, 100 * SCALE), 250);
+        assert_eq!(effective_maintenance_bps(700, 100 * SCALE), 10);
+
+        // Whale position: notional tier dominates
+        assert_eq!(effective_maintenance_bps(10, 100_000_000 * SCALE), 500);
+        assert_eq!(effective_maintenance_bps(700, 10_000_000 * SCALE), 100);
+    }
+
+    #[test]
+    fn test_position_zero_copy_layout() {
+        assert_eq!(Position::LEN, 128);
+        assert_eq!(std::mem::offset_of!(Position, market_index), 56);
+        assert_eq!(std::mem::offset_of!(Position, version), 62);
+        assert_eq!(std::mem::offset_of!(Position, at_risk_since), 64);
+    }
+
+    #[test]
+    fn test_upgrade_position_checks_version_byte() {
+        let mut current = vec![0u8; Position::LEN];
+        current[Position::VERSION_OFFSET] = Position::VERSION;
+        assert!(upgrade_position_data(&current, None).is_err());
+
+        // A legacy body with an empty symbol is 64 bytes; it must be read as
+        // borsh (and rejected for its symbol), never as a zero-copy layout.
+        let legacy = LegacyPosition {
+            owner: Pubkey::new_unique(),
+            symbol: String::new(),
+            size: SCALE as u64,
+            is_long: true,
+            entry_price: 3_000 * SCALE as u64,
+            collateral: 100 * SCALE as u64,
+            leverage: 20,
+            closed: false,
+        };
+        let body = legacy.try_to_vec().unwrap();
+        assert_eq!(body.len(), 64);
+
+        let market = test_market(7, "ETH-PERP");
+        assert!(upgrade_position_data(&body, None).is_err());
+        assert!(upgrade_position_data(&body, Some(&market)).is_err());
+    }
+
+    #[test]
+    fn test_upgrade_position_from_legacy_borsh() {
+        let market = test_market(7, "ETH-PERP");
+        let legacy = LegacyPosition {
+            owner: Pubkey::new_unique(),
+            symbol: "ETH-PERP".to_string(),
+            size: 2 * SCALE as u64,
+            is_long: true,
+            entry_price: 3_000 * SCALE as u64,
+            collateral: 100 * SCALE as u64,
+            leverage: 20,
+            closed: false,
+        };
+        let body = legacy.try_to_vec().unwrap();
+
+        // Market is required for version 0
+        assert!(upgrade_position_data(&body, None).is_err());
+
+        let (from, upgraded) = upgrade_position_data(&body, Some(&market)).unwrap();
+        assert_eq!(from, 0);
+        let position: &Position = bytemuck::from_bytes(&upgraded);
+        assert_eq!(position.owner, legacy.owner);
+        assert_eq!(position.market_index, 7);
+        assert!(position.is_long());
+        assert_eq!(position.version, Position::VERSION);
+    }
+
+    #[test]
+    fn test_upgrade_appended_layout() {
+        let v0 = vec![1u8; PriceFeed::V0_LEN];
+        let upgraded = upgrade_appended_layout(
+            &v0,
+            PriceFeed::V0_LEN,
+            PriceFeed::VERSION_OFFSET,
+            PriceFeed::VERSION,
+            PriceFeed::INIT_SPACE,
+        )
+        .unwrap();
+        assert_eq!(upgraded.len(), PriceFeed::INIT_SPACE);
+
+        let feed = PriceFeed::deserialize(&mut &upgraded[..]).unwrap();
+        assert_eq!(feed.version, PriceFeed::VERSION);
+        assert_eq!(feed.price, u64::from_le_bytes([1; 8]));
+
+        assert!(upgrade_appended_layout(
+            &upgraded,
+            PriceFeed::V0_LEN,
+            PriceFeed::VERSION_OFFSET,
+            PriceFeed::VERSION,
+            PriceFeed::INIT_SPACE
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_upgrade_insurance_fund_from_baseline() {
+        // Pre-safeguard layout: authority + balance, contributions, bad debt, utilization
+        let authority = Pubkey::new_unique();
+        let mut v0 = authority.to_bytes().to_vec();
+        for field in [500 * SCALE as u64, 600 * SCALE as u64, 100 * SCALE as u64, 1_666] {
+            v0.extend_from_slice(&field.to_le_bytes());
+        }
+        assert_eq!(v0.len(), 64);
+        assert_eq!(InsuranceFund::V0_LEN, 64);
+
+        let upgraded = upgrade_appended_layout(
+            &v0,
+            InsuranceFund::V0_LEN,
+            InsuranceFund::VERSION_OFFSET,
+            InsuranceFund::VERSION,
+            InsuranceFund::INIT_SPACE,
+        )
+        .unwrap();
+        assert_eq!(upgraded.len(), InsuranceFund::INIT_SPACE);
+
+        let insurance = InsuranceFund::deserialize(&mut &upgraded[..]).unwrap();
+        assert_eq!(insurance.authority, authority);
+        assert_eq!(insurance.balance, 500 * SCALE as u64);
+        assert_eq!(insurance.utilization_ratio, 1_666);
+        assert_eq!(insurance.withdrawal_timelock_secs, 0);
+        assert_eq!(insurance.max_withdrawal_bps_per_epoch, 0);
+        assert_eq!(insurance.pending_withdrawal_amount, 0);
+        assert_eq!(insurance.version, InsuranceFund::VERSION);
+
+        // Zeroed safeguards allow nothing out until configured
+        let cap = withdrawal_epoch_cap(
+            insurance.epoch_start_balance,
+            insurance.max_withdrawal_bps_per_epoch,
+        )
+        .unwrap();
+        assert!(check_withdrawal_allowed(insurance.balance, 0, cap, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_symbol_bytes() {
+        let sym = symbol_bytes("BTC-PERP").unwrap();
+        assert_eq!(&sym[..8], b"BTC-PERP");
+        assert!(sym[8..].iter().all(|b| *b == 0));
+
+        assert!(symbol_bytes("").is_err());
+        assert!(symbol_bytes("A-VERY-LONG-SYMBOL-PERP").is_err());
+    }
+
+    #[test]
+    fn test_realized_pnl_long_gain() {
+        // 1 contract (scaled), entry 10,000, mark 11,000
+        let size = SCALE as u64;              // 1 * 1e6
+        let entry_price = 10 * SCALE as u64;  // 10 * 1e6
+        let mark_price = 11 * SCALE as u64;   // 11 * 1e6
+
+        let pnl = realized_pnl_for_size(size, entry_price, mark_price, true, SCALE).unwrap();
+        // Expect +1 (scaled)
+        assert_eq!(pnl, SCALE as i128);
+    }
+
+    #[test]
+    fn test_realized_pnl_short_gain() {
+        // short from 10k to 9k
+        let size = SCALE as u64;
+        let entry_price = 10 * SCALE as u64;
+        let mark_price = 9 * SCALE as u64;
+
+        let pnl = realized_pnl_for_size(size, entry_price, mark_price, false, SCALE).unwrap();
+        // Expect +1 (scaled)
+        assert_eq!(pnl, SCALE as i128);
+    }
+
+    #[test]
+    fn test_value_and_reward_round_up() {
+        assert_eq!(position_value_for_size(1, 1, SCALE).unwrap(), 1);
+        assert_eq!(position_value_for_size(0, 1, SCALE).unwrap(), 0);
+        assert_eq!(liquidator_reward_for_value(1).unwrap(), 1);
+        assert_eq!(liquidator_reward_for_value(40 * SCALE).unwrap(), SCALE as u64);
+    }
+
+    // Tiny xorshift so the property tests stay deterministic and dependency-free.
+    fn next_rand(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn prop_rounding_never_favors_user() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15u64;
+        for _ in 0..10_000 {
+            let size = next_rand(&mut seed) % (1_000_000 * SCALE as u64) + 1;
+            let entry_price = next_rand(&mut seed) % (100_000 * SCALE as u64) + 1;
+            let mark_price = next_rand(&mut seed) % (100_000 * SCALE as u64) + 1;
+            let collateral = next_rand(&mut seed) % (1_000_000 * SCALE as u64);
+            let is_long = next_rand(&mut seed) & 1 == 0;
+
+            // Exact values, scaled by SCALE, in wide integers
+            let exact_value = size as u128 * mark_price as u128;
+            let diff = if is_long {
+                mark_price as i128 - entry_price as i128
+            } else {
+                entry_price as i128 - mark_price as i128
+            };
+            let exact_pnl = diff * size as i128;
+
+            let value = position_value_for_size(size, mark_price, SCALE).unwrap();
+            assert!(value * SCALE >= exact_value);
+            assert!(value * SCALE < exact_value + SCALE);
+
+            let pnl = realized_pnl_for_size(size, entry_price, mark_price, is_long, SCALE).unwrap();
+            assert!(pnl * SCALE as i128 <= exact_pnl);
+            assert!(pnl * SCALE as i128 > exact_pnl - SCALE as i128);
+
+            let reward = liquidator_reward_for_value(value).unwrap();
+            assert!(reward as u128 * BPS_DENOM as u128 >= value * LIQUIDATOR_REWARD_BPS as u128);
+
+            // Margin ratio never exceeds the exact ratio
+            let (mr_bps, _) =
+                compute_margin_ratio(size, entry_price, collateral, is_long, mark_price, SCALE)
+                    .unwrap();
+            let exact_equity = collateral as i128 * SCALE as i128 + exact_pnl;
+            if exact_equity > 0 && mr_bps != u64::MAX {
+                assert!(
+                    mr_bps as u128 * exact_value <= exact_equity as u128 * BPS_DENOM as u128
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_value_scale_for_market_decimals() {
+        // 1e6 price and size -> 1e6 quote
+        assert_eq!(value_scale_for(6, 6).unwrap(), SCALE);
+        // BTC sized in satoshis
+        assert_eq!(value_scale_for(6, 8).unwrap(), 100 * SCALE);
+        // Low-priced asset: 12 price decimals, whole-unit size
+        assert_eq!(value_scale_for(12, 0).unwrap(), SCALE);
+        // Coarser than quote decimals, or out of range
+        assert!(value_scale_for(2, 2).is_err());
+        assert!(value_scale_for(19, 0).is_err());
+
+        // 0.5 BTC at 60,000 -> 30,000 quote
+        let btc_scale = value_scale_for(6, 8).unwrap();
+        let value = position_value_for_size(50_000_000, 60_000 * SCALE as u64, btc_scale).unwrap();
+        assert_eq!(value, 30_000 * SCALE);
+    }
+
+    #[test]
+    fn test_partial_leaves_dust() {
+        let price = 100 * SCALE as u64;
+        let min_notional = 10 * SCALE as u64; // $10
+
+        // 1 unit can't be halved
+        assert!(partial_leaves_dust(1, 0, price, SCALE, 0).unwrap());
+        // 0.2 left at $100 = $20 -> fine
+        assert!(!partial_leaves_dust(400_000, 200_000, price, SCALE, min_notional).unwrap());
+        // 0.05 left at $100 = $5 -> dust
+        assert!(partial_leaves_dust(100_000, 50_000, price, SCALE, min_notional).unwrap());
+        // Exactly at the minimum is kept open
+        assert!(!partial_leaves_dust(200_000, 100_000, price, SCALE, min_notional).unwrap());
+    }
+
+    #[test]
+    fn test_liquidator_stats_record() {
+        let mut stats = LiquidatorStats {
+            liquidator: Pubkey::new_unique(),
+            liquidation_count: 0,
+            total_reward: 0,
+            last_liquidation_slot: 0,
+            bump: 255,
+        };
+
+        stats.record(1_500_000, 100).unwrap();
+        stats.record(0, 250).unwrap();
+        assert_eq!(stats.liquidation_count, 2);
+        assert_eq!(stats.total_reward, 1_500_000);
+        assert_eq!(stats.last_liquidation_slot, 250);
+
+        stats.total_reward = u64::MAX;
+        assert!(stats.record(1, 300).is_err());
+    }
+
+    #[test]
+    fn test_liquidation_grace_elapsed() {
+        let maintenance = 250;
+        let now = 1_000;
+
+        // Grace disabled
+        assert!(liquidation_grace_elapsed(0, 0, 200, maintenance, now));
+        // Not flagged, non-critical
+        assert!(!liquidation_grace_elapsed(0, 60, 200, maintenance, now));
+        // Flagged but window still open
+        assert!(!liquidation_grace_elapsed(now - 30, 60, 200, maintenance, now));
+        // Window elapsed
+        assert!(liquidation_grace_elapsed(now - 60, 60, 200, maintenance, now));
+        // Critical: below 50% of maintenance skips the window
+        assert!(liquidation_grace_elapsed(0, 60, 124, maintenance, now));
+        assert!(!liquidation_grace_elapsed(0, 60, 125, maintenance, now));
+    }
+
+    #[test]
+    fn test_open_interest_caps() {
+        assert_eq!(open_interest_after(100, 50, 200).unwrap(), 150);
+        assert_eq!(open_interest_after(100, 100, 200).unwrap(), 200);
+        assert!(open_interest_after(100, 101, 200).is_err());
+        // 0 = uncapped
+        assert_eq!(open_interest_after(100, 1_000, 0).unwrap(), 1_100);
+        assert!(open_interest_after(u64::MAX, 1, 0).is_err());
+
+        let mut market = test_market(0, "BTC-PERP");
+        market.max_open_interest = 10;
+        market.max_notional_per_position = 1_000;
+
+        // Sides are tracked separately
+        market.add_open_interest(true, 10, 500).unwrap();
+        market.add_open_interest(false, 4, 500).unwrap();
+        assert!(market.add_open_interest(true, 1, 500).is_err());
+        assert!(market.add_open_interest(false, 1, 1_001).is_err());
+
+        market.reduce_open_interest(true, 3);
+        market.reduce_open_interest(false, 10);
+        assert_eq!(market.open_interest_long, 7);
+        assert_eq!(market.open_interest_short, 0);
+    }
+
+    #[test]
+    fn test_market_price_feed_binding() {
+        let mut market = test_market(0, "BTC-PERP");
+        let feed = Pubkey::new_unique();
+
+        // An unset feed matches nothing, not everything
+        assert!(!market.accepts_price_feed(&feed));
+
+        market.price_feed = feed;
+        assert!(market.accepts_price_feed(&feed));
+        assert!(!market.accepts_price_feed(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_set_price_stamps_update_time() {
+        let mut feed = PriceFeed {
+            price: 60_000 * SCALE as u64,
+            last_updated: 1_700_000_000,
+            version: PriceFeed::VERSION,
+            _reserved: [0; 32],
+        };
+
+        set_price(&mut feed, 61_000 * SCALE as u64, 1_700_000_010).unwrap();
+        assert_eq!(feed.price, 61_000 * SCALE as u64);
+        assert_eq!(feed.last_updated, 1_700_000_010);
+
+        // A zero price would make every position look bankrupt
+        assert!(set_price(&mut feed, 0, 1_700_000_020).is_err());
+        assert_eq!(feed.last_updated, 1_700_000_010);
+    }
+
+    #[test]
+    fn test_average_entry_price_rounds_against_owner() {
+        // 1 @ 100 + 1 @ 110 -> 105
+        assert_eq!(average_entry_price(1, 100, 1, 110, true).unwrap(), 105);
+        // 2 @ 100 + 1 @ 101 -> 100.33: long rounds up, short down
+        assert_eq!(average_entry_price(2, 100, 1, 101, true).unwrap(), 101);
+        assert_eq!(average_entry_price(2, 100, 1, 101, false).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_apply_pnl_positive_and_negative() {
+        let collateral = 5 * SCALE as u64;
+
+        // +2
+        let after_gain = apply_pnl(collateral, 2 * SCALE as i128).unwrap();
+        assert_eq!(after_gain, 7 * SCALE as u64);
+
+        // -10 (should clamp at 0, not underflow)
+        let after_loss = apply_pnl(collateral, -10 * SCALE as i128).unwrap();
+        assert_eq!(after_loss, 0);
+    }
+
+    #[test]
+    #[allow(clippy::identity_op, clippy::unnecessary_cast)]
+    fn test_compute_margin_ratio_basic() {
+        // size 1, price 10, collateral 1 -> equity 1, value 10 -> MR = 10%
+        let size = SCALE as u64;
+        let price = 10 * SCALE as u64;
+        let collateral = 1 * SCALE as u64;
+
+        let (mr_bps, position_value) =
+            compute_margin_ratio(size, price, collateral, true, price, SCALE).unwrap();
+
+        // Position value ~= 10 * SCALE
+        assert_eq!(position_value, 10 * SCALE as u128);
+
+        // 10% margin -> 1000 bps
+        assert_eq!(mr_bps, 1000);
+    }
+
+    #[test]
+    fn test_withdrawal_epoch_cap() {
+        // 10% of 1,000 -> 100
+        let cap = withdrawal_epoch_cap(1_000 * SCALE as u64, 1_000).unwrap();
+        assert_eq!(cap, 100 * SCALE as u64);
+    }
+
+    #[test]
+    fn test_check_withdrawal_allowed_limits() {
+        let balance = 1_000 * SCALE as u64;
+        let min_reserve = 500 * SCALE as u64;
+        let cap = 100 * SCALE as u64;
+
+        // Within cap and reserve
+        assert!(check_withdrawal_allowed(balance, min_reserve, cap, 0, 100 * SCALE as u64).is_ok());
+
+        // Over the epoch cap once earlier withdrawals are counted
+        assert!(check_withdrawal_allowed(
+            balance,
+            min_reserve,
+            cap,
+            50 * SCALE as u64,
+            60 * SCALE as u64
+        )
+        .is_err());
+
+        // Would dip below the reserve
+        assert!(check_withdrawal_allowed(
+            550 * SCALE as u64,
+            min_reserve,
+            cap,
+            0,
+            60 * SCALE as u64
+        )
+        .is_err());
+
+        // At the reserve, nothing can be withdrawn
+        assert!(check_withdrawal_allowed(min_reserve, min_reserve, cap, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_apply_withdrawal_config_seeds_epoch() {
+        let mut insurance =
+            InsuranceFund::deserialize(&mut &vec![0u8; InsuranceFund::INIT_SPACE][..]).unwrap();
+        insurance.balance = 1_000 * SCALE as u64;
+        insurance.withdrawn_this_epoch = 7;
+
+        let mut config = InsuranceWithdrawalConfig {
+            timelock_secs: 0,
+            epoch_duration_secs: 86_400,
+            max_withdrawal_bps_per_epoch: 1_000,
+            min_reserve: 0,
+        };
+        // A zero timelock would let withdrawals execute in the request's slot
+        assert!(apply_withdrawal_config(&mut insurance, &config, 100).is_err());
+
+        config.timelock_secs = 3_600;
+        apply_withdrawal_config(&mut insurance, &config, 100).unwrap();
+        assert_eq!(insurance.epoch_start, 100);
+        assert_eq!(insurance.epoch_start_balance, 1_000 * SCALE as u64);
+        assert_eq!(insurance.withdrawn_this_epoch, 0);
+
+        // The first epoch is capped against the seeded balance, not zero
+        let cap = withdrawal_epoch_cap(
+            insurance.epoch_start_balance,
+            insurance.max_withdrawal_bps_per_epoch,
+        )
+        .unwrap();
+        assert_eq!(cap, 100 * SCALE as u64);
+    }
+
+    #[test]
+    fn test_reconfigure_only_tightens_and_keeps_epoch() {
+        let mut insurance =
+            InsuranceFund::deserialize(&mut &vec![0u8; InsuranceFund::INIT_SPACE][..]).unwrap();
+        insurance.balance = 1_000 * SCALE as u64;
+        let config = InsuranceWithdrawalConfig {
+            timelock_secs: 3_600,
+            epoch_duration_secs: 86_400,
+            max_withdrawal_bps_per_epoch: 1_000,
+            min_reserve: 100 * SCALE as u64,
+        };
+        apply_withdrawal_config(&mut insurance, &config, 100).unwrap();
+
+        // The epoch's whole cap is withdrawn
+        insurance.balance -= 100 * SCALE as u64;
+        insurance.withdrawn_this_epoch = 100 * SCALE as u64;
+
+        // Tightening is allowed but does not restart the epoch
+        let tighter = InsuranceWithdrawalConfig {
+            timelock_secs: 7_200,
+            max_withdrawal_bps_per_epoch: 1_000,
+            ..config
+        };
+        apply_withdrawal_config(&mut insurance, &tighter, 200).unwrap();
+        assert_eq!(insurance.withdrawal_timelock_secs, 7_200);
+        assert_eq!(insurance.epoch_start, 100);
+        assert_eq!(insurance.epoch_start_balance, 1_000 * SCALE as u64);
+        assert_eq!(insurance.withdrawn_this_epoch, 100 * SCALE as u64);
+
+        let cap = withdrawal_epoch_cap(
+            insurance.epoch_start_balance,
+            insurance.max_withdrawal_bps_per_epoch,
+        )
+        .unwrap();
+        assert!(check_withdrawal_allowed(
+            insurance.balance,
+            insurance.min_reserve,
+            cap,
+            insurance.withdrawn_this_epoch,
+            1,
+        )
+        .is_err());
+
+        // Each limit refuses to loosen
+        for looser in [
+            InsuranceWithdrawalConfig { timelock_secs: 3_600, ..tighter },
+            InsuranceWithdrawalConfig { epoch_duration_secs: 3_600, ..tighter },
+            InsuranceWithdrawalConfig { max_withdrawal_bps_per_epoch: 2_000, ..tighter },
+            InsuranceWithdrawalConfig { min_reserve: 0, ..tighter },
+        ] {
+            assert!(apply_withdrawal_config(&mut insurance, &looser, 300).is_err());
+        }
+        assert_eq!(insurance.withdrawal_timelock_secs, 7_200);
+        assert_eq!(insurance.min_reserve, 100 * SCALE as u64);
+    }
+
+    #[test]
+    fn test_record_contribution_credits_global_fund() {
+        let mut insurance =
+            InsuranceFund::deserialize(&mut &vec![0u8; InsuranceFund::INIT_SPACE][..]).unwrap();
+        insurance.total_bad_debt_covered = 10 * SCALE as u64;
+
+        assert!(record_contribution(&mut insurance, 0).is_err());
+        record_contribution(&mut insurance, 200 * SCALE as u64).unwrap();
+        assert_eq!(insurance.balance, 200 * SCALE as u64);
+        assert_eq!(insurance.total_contributions, 200 * SCALE as u64);
+        assert_eq!(insurance.utilization_ratio, 500);
+    }
+
+    #[test]
+    fn test_split_bad_debt_coverage() {
+        // Market fund covers everything
+        assert_eq!(split_bad_debt_coverage(100, 500, 1_000, 1_000), (100, 0));
+
+        // Market fund short, global covers the rest
+        assert_eq!(split_bad_debt_coverage(100, 30, 1_000, 1_000), (30, 70));
+
+        // Global draw limited by the market's backstop allowance
+        assert_eq!(split_bad_debt_coverage(100, 30, 1_000, 20), (30, 20));
+
+        // Global draw limited by the global balance
+        assert_eq!(split_bad_debt_coverage(100, 0, 10, 1_000), (0, 10));
+    }
+
+    #[test]
+    fn test_funded_market_fund_covers_bad_debt() {
+        let mut insurance =
+            InsuranceFund::deserialize(&mut &vec![0u8; InsuranceFund::INIT_SPACE][..]).unwrap();
+        insurance.balance = 1_000 * SCALE as u64;
+        let mut market_insurance = MarketInsuranceFund::deserialize(
+            &mut &vec![0u8; MarketInsuranceFund::INIT_SPACE][..],
+        )
+        .unwrap();
+
+        assert!(record_market_contribution(&mut market_insurance, 0).is_err());
+        record_market_contribution(&mut market_insurance, 50 * SCALE as u64).unwrap();
+        assert_eq!(market_insurance.balance, 50 * SCALE as u64);
+        assert_eq!(market_insurance.total_contributions, 50 * SCALE as u64);
+
+        // size 1, entry 100, collateral 10, marked at 85: no equity left for
+        // the 2.125 reward, which becomes bad debt
+        let mut position =
+            test_position(SCALE as u64, 100 * SCALE as u64, 10 * SCALE as u64, 10, true);
+        let mark_price = 85 * SCALE as u64;
+        let value = position_value_for_size(position.size, mark_price, SCALE).unwrap();
+        let full = settle_full_liquidation(
+            &mut position,
+            &mut insurance,
+            &mut market_insurance,
+            mark_price,
+            value,
+            SCALE,
+        )
+        .unwrap();
+
+        assert_eq!(full.reward_paid, 0);
+        assert_eq!(full.user_refund, 0);
+        assert_eq!(full.bad_debt, 2_125_000);
+        assert_eq!(full.covered_by_market, full.bad_debt);
+        assert_eq!(full.covered_by_global, 0);
+        assert_eq!(market_insurance.balance, 50 * SCALE as u64 - 2_125_000);
+        assert_eq!(market_insurance.utilization_ratio, 425); // 2.125 / 50
+        assert_eq!(insurance.balance, 1_000 * SCALE as u64);
+    }
+
+    #[test]
+    fn test_full_liquidation_refunds_remaining_equity() {
+        let mut insurance =
+            InsuranceFund::deserialize(&mut &vec![0u8; InsuranceFund::INIT_SPACE][..]).unwrap();
+        let mut market_insurance = MarketInsuranceFund::deserialize(
+            &mut &vec![0u8; MarketInsuranceFund::INIT_SPACE][..],
+        )
+        .unwrap();
+
+        // size 1, entry 100, collateral 10, marked at 95: equity 5 pays the
+        // 2.375 reward and the rest goes back to the owner
+        let mut position =
+            test_position(SCALE as u64, 100 * SCALE as u64, 10 * SCALE as u64, 10, true);
+        let mark_price = 95 * SCALE as u64;
+        let value = position_value_for_size(position.size, mark_price, SCALE).unwrap();
+        let full = settle_full_liquidation(
+            &mut position,
+            &mut insurance,
+            &mut market_insurance,
+            mark_price,
+            value,
+            SCALE,
+        )
+        .unwrap();
+
+        assert_eq!(full.reward_paid, 2_375_000);
+        assert_eq!(full.user_refund, 2_625_000);
+        assert_eq!(full.bad_debt, 0);
+        assert!(position.is_closed());
+        assert_eq!(position.collateral, 0);
+    }
+
+    #[test]
+    fn test_bankruptcy_price_long_and_short() {
+        // size 1, entry 100, collateral 10 -> long bankrupt at 90, short at 110
+        let size = SCALE as u64;
+        let entry_price = 100 * SCALE as u64;
+        let collateral = 10 * SCALE as u64;
+
+        let long_bk =
+            bankruptcy_price_for_size(size, entry_price, collateral, true, SCALE).unwrap();
+        assert_eq!(long_bk, 90 * SCALE as u64);
+
+        let short_bk =
+            bankruptcy_price_for_size(size, entry_price, collateral, false, SCALE).unwrap();
+        assert_eq!(short_bk, 110 * SCALE as u64);
+
+        // Equity is zero at the bankruptcy price
+        let (mr_bps, _) =
+            compute_margin_ratio(size, entry_price, collateral, true, long_bk, SCALE).unwrap();
+        assert_eq!(mr_bps, 0);
+    }
+
+    #[test]
+    fn test_backstop_takeover_at_bankruptcy_price() {
+        let lp = Pubkey::new_unique();
+        // size 1, entry 100, collateral 10 -> bankrupt at 90 (long) / 110 (short)
+        for (is_long, mark_price, takeover_price) in [
+            (true, 92 * SCALE as u64, 90 * SCALE as u64),
+            (false, 108 * SCALE as u64, 110 * SCALE as u64),
+        ] {
+            let position =
+                test_position(SCALE as u64, 100 * SCALE as u64, 10 * SCALE as u64, 10, is_long);
+            let lp_position =
+                backstop_takeover_position(&position, lp, 20 * SCALE as u64, SCALE).unwrap();
+            assert_eq!(lp_position.owner, lp);
+            assert_eq!(lp_position.entry_price, takeover_price);
+            assert_eq!(lp_position.collateral, 20 * SCALE as u64);
+            assert_eq!(lp_position.size, position.size);
+            assert_eq!(lp_position.is_long(), is_long);
+
+            // The LP's unrealized PnL is the equity liquidate_full would realize
+            let lp_pnl =
+                realized_pnl_for_size(lp_position.size, takeover_price, mark_price, is_long, SCALE)
+                    .unwrap();
+            let user_pnl = realized_pnl_for_size(
+                position.size,
+                position.entry_price,
+                mark_price,
+                is_long,
+                SCALE,
+            )
+            .unwrap();
+            let user_equity = apply_pnl(position.collateral, user_pnl).unwrap();
+            assert_eq!(lp_pnl, user_equity as i128);
+            assert_eq!(lp_pnl, 2 * SCALE as i128);
+        }
+    }
+
+    // 6-decimal market with no caps, grace period or bound feed.
+    fn test_market(market_index: u16, symbol: &str) -> Market {
+        Market {
+            market_index,
+            symbol: symbol_bytes(symbol).unwrap(),
+            insurance_fund: Pubkey::default(),
+            bump: 255,
+            price_decimals: 6,
+            base_decimals: 6,
+            min_position_notional: 0,
+            warning_buffer_bps: 0,
+            at_risk_grace_secs: 0,
+            max_open_interest: 0,
+            max_notional_per_position: 0,
+            open_interest_long: 0,
+            open_interest_short: 0,
+            price_feed: Pubkey::default(),
+        }
+    }
+
+    fn test_position(
+        size: u64,
+        entry_price: u64,
+        collateral: u64,
+        leverage: u16,
+        is_long: bool,
+    ) -> Position {
+        Position {
+            owner: Pubkey::default(),
+            size,
+            entry_price,
+            collateral,
+            market_index: 0,
+            leverage,
+            is_long: is_long as u8,
+            closed: 0,
+            version: Position::VERSION,
+            _padding: [0; 1],
+            at_risk_since: 0,
+            _reserved: [0; 56],
+        }
+    }
+
+    #[test]
+    fn test_liquidation_price_matches_margin_check() {
+        // size 1, entry 100, collateral 10, 10x -> 2.5% maintenance
+        let long = test_position(SCALE as u64, 100 * SCALE as u64, 10 * SCALE as u64, 10, true);
+        let short = test_position(SCALE as u64, 100 * SCALE as u64, 10 * SCALE as u64, 10, false);
+
+        // long: 90 / 0.975 ~ 92.307693, short: 110 / 1.025 ~ 107.317073
+        let long_liq = liquidation_price(&long, &MAINTENANCE_TIERS, SCALE).unwrap();
+        let short_liq = liquidation_price(&short, &MAINTENANCE_TIERS, SCALE).unwrap();
+        assert_eq!(long_liq, 92_307_693);
+        assert_eq!(short_liq, 107_317_073);
+
+        for (position, liq) in [(&long, long_liq), (&short, short_liq)] {
+            let margin_at = |price: u64| {
+                let (mr, value) = compute_margin_ratio(
+                    position.size,
+                    position.entry_price,
+                    position.collateral,
+                    position.is_long(),
+                    price,
+                    SCALE,
+                )
+                .unwrap();
+                (mr, effective_maintenance_bps(position.leverage, value))
+            };
+            let (worse, better) = if position.is_long() {
+                (liq - liq / 1_000, liq + liq / 1_000)
+            } else {
+                (liq + liq / 1_000, liq - liq / 1_000)
+            };
+            let (mr, maintenance) = margin_at(worse);
+            assert!(mr < maintenance);
+            let (mr, maintenance) = margin_at(better);
+            assert!(mr >= maintenance);
+        }
+
+        // Over-collateralized long can never be liquidated
+        let safe = test_position(SCALE as u64, 100 * SCALE as u64, 200 * SCALE as u64, 1, true);
+        assert_eq!(liquidation_price(&safe, &MAINTENANCE_TIERS, SCALE).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_liquidation_price_uses_notional_tier() {
+        // 10,000 units at $1,000 (~$10M notional), 100x: leverage tier 0.5%,
+        // but the $5M-$20M notional tier requires 1.0%
+        let size = 10_000 * SCALE as u64;
+        let position = test_position(size, 1_000 * SCALE as u64, 200_000 * SCALE as u64, 100, true);
+        let bankruptcy = bankruptcy_price(&position, SCALE).unwrap();
+
+        assert_eq!(
+            liquidation_price(&position, &MAINTENANCE_TIERS, SCALE).unwrap(),
+            price_at_maintenance(bankruptcy, true, 100).unwrap()
+        );
+    }
+}